@@ -0,0 +1,425 @@
+//! An on-disk cache of scan results.
+//!
+//! Scanning a project drive over SMB can take minutes, and we often re-run `image-list` while
+//! tweaking record start times. The cache remembers directory listings (keyed on the directory's
+//! modification time), timestamp file contents, and passed image integrity checks (keyed on each
+//! file's size and modification time), so only changed files are read again.
+//!
+//! Image numbers aren't cached separately: they are parsed from the cached file names, which
+//! costs nothing next to listing the directory. EXIF data isn't cached either. The integrity
+//! check is the only step that reads it without rewriting the image, and passed checks are
+//! already cached; geotagging reads the old EXIF data of each image it rewrites anyway.
+//!
+//! When the cache is saved, entries for files that are missing from a directory listed in this run
+//! are dropped. Nothing else is checked, so entries for other projects, or for drives that aren't
+//! mounted, are kept.
+
+use Result;
+use listing::Listing;
+use timestamp::File;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::UNIX_EPOCH;
+
+/// Bump this whenever the cache contents change shape, so old caches are ignored.
+const VERSION: u32 = 3;
+
+/// A scan cache, backed by a file.
+///
+/// The cache can be shared between threads. Changes are kept in memory until `save` is called.
+#[derive(Debug)]
+pub struct Cache {
+    path: PathBuf,
+    contents: Mutex<Contents>,
+    dirty: AtomicBool,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Contents {
+    version: u32,
+    directories: HashMap<String, Directory>,
+    timestamps: HashMap<String, Timestamps>,
+    integrity: HashMap<String, Integrity>,
+    /// The keys of the directories that were listed in this run.
+    #[serde(skip)]
+    listed: HashSet<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Directory {
+    modified: Stamp,
+    file_names: Vec<String>,
+    unreadable: Vec<String>,
+    non_utf8: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Timestamps {
+    stamp: Stamp,
+    source: String,
+    timestamps: Vec<f64>,
+    lines: Vec<usize>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Integrity {
+    stamp: Stamp,
+    config: String,
+}
+
+/// A file size (zero for directories) and modification time.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+struct Stamp {
+    size: u64,
+    seconds: u64,
+    nanoseconds: u32,
+}
+
+impl Cache {
+    /// Returns the default cache path, in the user's cache directory.
+    ///
+    /// Returns None if the platform doesn't have a cache directory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::cache::Cache;
+    /// let path = Cache::default_path();
+    /// ```
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::cache_dir().map(|directory| directory.join("riprocess").join("scan-cache.json"))
+    }
+
+    /// Opens the cache at the provided path.
+    ///
+    /// A missing, unreadable, or outdated cache file is treated as an empty cache.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::cache::Cache;
+    /// let cache = Cache::open(std::env::temp_dir().join("riprocess-cache-doc.json"));
+    /// ```
+    pub fn open<P: AsRef<Path>>(path: P) -> Cache {
+        let contents = fs::read(path.as_ref())
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<Contents>(&bytes).ok())
+            .filter(|contents| contents.version == VERSION)
+            .unwrap_or_else(|| Contents { version: VERSION, ..Default::default() });
+        Cache {
+            path: path.as_ref().to_path_buf(),
+            contents: Mutex::new(contents),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// Deletes the cache file at the provided path, if it exists.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::cache::Cache;
+    /// Cache::clear(std::env::temp_dir().join("riprocess-cache-doc.json")).unwrap();
+    /// ```
+    pub fn clear<P: AsRef<Path>>(path: P) -> Result<()> {
+        match fs::remove_file(path) {
+            Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            result => result.map_err(From::from),
+        }
+    }
+
+    /// Returns the path to the cache file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes the cache to disk, if anything has changed since it was opened.
+    pub fn save(&self) -> Result<()> {
+        if !self.dirty.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let bytes = {
+            let mut contents = self.lock();
+            contents.prune();
+            serde_json::to_vec(&*contents).expect("cache contents should always serialize")
+        };
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        fs::write(&temporary, bytes)?;
+        fs::rename(&temporary, &self.path)?;
+        self.dirty.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Lists a directory, reading the directory only if it has changed.
+    ///
+    /// Skipped entries are cached along with the file names, so they are reported either way.
+    pub(crate) fn listing(&self, directory: &Path) -> Result<Listing> {
+        let modified = Stamp::directory(directory)?;
+        let key = match key(directory) {
+            Some(key) => key,
+            None => return Listing::read(directory),
+        };
+        {
+            let mut contents = self.lock();
+            contents.listed.insert(key.clone());
+            if let Some(entry) = contents.directories.get(&key) {
+                if entry.modified == modified {
+                    return Ok(Listing {
+                                  file_names: entry.file_names.clone(),
+                                  unreadable: entry.unreadable.clone(),
+                                  non_utf8: entry.non_utf8.clone(),
+                              });
+                }
+            }
+        }
+        let listing = Listing::read(directory)?;
+        let entry = Directory {
+            modified,
+            file_names: listing.file_names.clone(),
+            unreadable: listing.unreadable.clone(),
+            non_utf8: listing.non_utf8.clone(),
+        };
+        self.insert(|contents| {
+                        contents.directories.insert(key, entry);
+                    });
+        Ok(listing)
+    }
+
+    /// Reads a timestamp file, unless it has already been read by the same source.
+    ///
+    /// Only the timestamps and their lines are cached, so files with skipped lines shouldn't be
+    /// read through the cache.
+    pub(crate) fn timestamps<F>(&self, path: &Path, source: &str, read: F) -> Result<File>
+        where F: FnOnce(&Path) -> Result<File>
+    {
+        let (key, stamp) = match (key(path), Stamp::file(path)) {
+            (Some(key), Ok(stamp)) => (key, stamp),
+            _ => return read(path),
+        };
+        if let Some(entry) = self.lock().timestamps.get(&key) {
+            if entry.stamp == stamp && entry.source == source {
+                return Ok(File {
+                              path: path.to_path_buf(),
+                              timestamps: entry.timestamps.clone(),
+                              lines: entry.lines.clone(),
+                              skipped_lines: Vec::new(),
+                          });
+            }
+        }
+        let file = read(path)?;
+        let entry = Timestamps {
+            stamp,
+            source: source.to_string(),
+            timestamps: file.timestamps.clone(),
+            lines: file.lines.clone(),
+        };
+        self.insert(|contents| {
+                        contents.timestamps.insert(key, entry);
+                    });
+        Ok(file)
+    }
+
+    /// Runs an integrity check, unless the file already passed the same check.
+    ///
+    /// Only passed checks are cached, so failures are always reported afresh.
+    pub(crate) fn integrity<T, F>(&self, path: &Path, config: &str, check: F) -> Vec<T>
+        where F: FnOnce(&Path) -> Vec<T>
+    {
+        let (key, stamp) = match (key(path), Stamp::file(path)) {
+            (Some(key), Ok(stamp)) => (key, stamp),
+            _ => return check(path),
+        };
+        if let Some(entry) = self.lock().integrity.get(&key) {
+            if entry.stamp == stamp && entry.config == config {
+                return Vec::new();
+            }
+        }
+        let problems = check(path);
+        if problems.is_empty() {
+            let config = config.to_string();
+            self.insert(|contents| {
+                            contents.integrity.insert(key, Integrity { stamp, config });
+                        });
+        }
+        problems
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Contents> {
+        self.contents.lock().expect("cache mutex should not be poisoned")
+    }
+
+    fn insert<F: FnOnce(&mut Contents)>(&self, f: F) {
+        f(&mut self.lock());
+        self.dirty.store(true, Ordering::SeqCst);
+    }
+}
+
+impl Contents {
+    /// Drops the file entries for files that are missing from a directory listed in this run.
+    fn prune(&mut self) {
+        let Contents { ref directories, ref listed, ref mut timestamps, ref mut integrity, .. } =
+            *self;
+        let file_names = listed.iter()
+            .filter_map(|key| directories.get(key).map(|directory| (key.as_str(), directory)))
+            .map(|(key, directory)| {
+                     (key, directory.file_names.iter().map(String::as_str).collect::<HashSet<_>>())
+                 })
+            .collect::<HashMap<_, _>>();
+        let is_listed = |key: &String| {
+            let path = Path::new(key);
+            let name = path.file_name().and_then(|name| name.to_str());
+            match (path.parent().and_then(Path::to_str), name) {
+                (Some(parent), Some(name)) => {
+                    file_names.get(parent).map(|names| names.contains(name)).unwrap_or(true)
+                }
+                _ => true,
+            }
+        };
+        timestamps.retain(|key, _| is_listed(key));
+        integrity.retain(|key, _| is_listed(key));
+    }
+}
+
+impl Stamp {
+    fn file(path: &Path) -> Result<Stamp> {
+        let metadata = fs::metadata(path)?;
+        Ok(Stamp::new(metadata.len(), &metadata))
+    }
+
+    fn directory(path: &Path) -> Result<Stamp> {
+        let metadata = fs::metadata(path)?;
+        Ok(Stamp::new(0, &metadata))
+    }
+
+    fn new(size: u64, metadata: &fs::Metadata) -> Stamp {
+        let modified = metadata.modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        Stamp {
+            size,
+            seconds: modified.as_secs(),
+            nanoseconds: modified.subsec_nanos(),
+        }
+    }
+}
+
+/// Returns the cache key for a path, or None if the path can't be used as a key.
+fn key(path: &Path) -> Option<String> {
+    path.canonicalize().ok().and_then(|path| path.to_str().map(String::from))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join("riprocess-cache").join(name);
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn timestamps() {
+        let directory = directory("timestamps");
+        let path = directory.join("170621_202939.eif");
+        fs::write(&path, "1.5\n").unwrap();
+        let cache = Cache::open(directory.join("cache.json"));
+        let read = |path: &Path| -> Result<File> {
+            Ok(File {
+                   path: path.to_path_buf(),
+                   timestamps: vec![fs::read_to_string(path)?.trim().parse()?],
+                   lines: vec![1],
+                   ..Default::default()
+               })
+        };
+        let timestamps = |file: Result<File>| file.unwrap().timestamps;
+        assert_eq!(vec![1.5], timestamps(cache.timestamps(&path, "eif", read)));
+        cache.save().unwrap();
+
+        let cache = Cache::open(directory.join("cache.json"));
+        let unread = |_: &Path| -> Result<File> { panic!("the file should not be read") };
+        let file = cache.timestamps(&path, "eif", unread).unwrap();
+        assert_eq!((vec![1.5], vec![1]), (file.timestamps, file.lines));
+        assert_eq!(vec![1.5], timestamps(cache.timestamps(&path, "csv", read)));
+
+        fs::write(&path, "2.25\n").unwrap();
+        assert_eq!(vec![2.25], timestamps(cache.timestamps(&path, "csv", read)));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn file_names() {
+        let directory = directory("file-names");
+        fs::write(directory.join("DSC03522.JPG"), "").unwrap();
+        let cache = Cache::open(directory.join("cache.json"));
+        assert_eq!(vec!["DSC03522.JPG".to_string()],
+                   cache.listing(&directory).unwrap().file_names);
+        assert!(cache.lock().directories.values().any(|entry| entry.file_names.len() == 1));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn integrity() {
+        let directory = directory("integrity");
+        let path = directory.join("DSC03522.JPG");
+        fs::write(&path, "").unwrap();
+        let cache = Cache::open(directory.join("cache.json"));
+        assert_eq!(vec![1], cache.integrity(&path, "a", |_| vec![1]));
+        assert!(cache.integrity(&path, "a", |_| Vec::<u8>::new()).is_empty());
+        assert!(cache.integrity(&path, "a", |_| vec![1]).is_empty());
+        assert_eq!(vec![1], cache.integrity(&path, "b", |_| vec![1]));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn prune() {
+        let directory = directory("prune");
+        let images = directory.join("images");
+        let elsewhere = directory.join("elsewhere");
+        fs::create_dir_all(&images).unwrap();
+        fs::create_dir_all(&elsewhere).unwrap();
+        let kept = images.join("DSC03522.JPG");
+        let removed = images.join("DSC03523.JPG");
+        let unlisted = elsewhere.join("DSC03524.JPG");
+        for path in &[&kept, &removed, &unlisted] {
+            fs::write(path, "").unwrap();
+        }
+        let cache = Cache::open(directory.join("cache.json"));
+        for path in &[&kept, &removed, &unlisted] {
+            assert!(cache.integrity(path, "a", |_| Vec::<u8>::new()).is_empty());
+        }
+        let mut keys = vec![key(&kept).unwrap(), key(&unlisted).unwrap()];
+        keys.sort();
+        fs::remove_file(&removed).unwrap();
+        fs::remove_file(&unlisted).unwrap();
+        cache.listing(&images).unwrap();
+        cache.save().unwrap();
+
+        let cache = Cache::open(directory.join("cache.json"));
+        let mut saved = cache.lock().integrity.keys().cloned().collect::<Vec<_>>();
+        saved.sort();
+        assert_eq!(keys, saved);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn outdated() {
+        let directory = directory("outdated");
+        let path = directory.join("cache.json");
+        fs::write(&path, "{\"version\": 0}").unwrap();
+        assert_eq!(VERSION, Cache::open(&path).lock().version);
+        Cache::clear(&path).unwrap();
+        assert!(!path.exists());
+        Cache::clear(&path).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,5 +1,9 @@
-use {Error, Result, image, record, timestamp};
-use std::iter::Zip;
+use {Error, Result, exclude, export, geotag, image, integrity, lint, migrate, organize,
+     overrides, profile, record, rename, sidecar, thin, timestamp, trajectory, validate};
+use cache::Cache;
+use listing::{Scan, Skipped};
+use report::{Mode, Report, Stage};
+use overrides::Override;
 use std::path::{Path, PathBuf};
 use std::vec::IntoIter;
 
@@ -11,34 +15,124 @@ use std::vec::IntoIter;
 /// # use riprocess::Config;
 /// let config = Config::from_path("data/config.toml").unwrap();
 /// ```
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Config {
+    /// The configuration format version.
+    ///
+    /// If None, the file predates versioning. See the `migrate` module.
+    pub version: Option<u32>,
     /// Image file configuration.
     pub images: image::Config,
     /// Timestamp configuration.
     pub timestamps: timestamp::Config,
     /// Record configuration.
     pub records: record::Config,
+    /// Timestamp validation configuration.
+    #[serde(default)]
+    pub validation: validate::Config,
+    /// Image integrity configuration.
+    ///
+    /// If None, the contents of the image files are not checked.
+    pub integrity: Option<integrity::Config>,
+    /// Configuration for organizing images into the project tree.
+    pub organize: Option<organize::Config>,
+    /// Configuration for renaming images.
+    #[serde(default)]
+    pub rename: rename::Config,
+    /// The platform trajectory.
+    ///
+    /// If provided, each image's pose is interpolated from the trajectory when geotagging.
+    pub trajectory: Option<trajectory::Config>,
+    /// Configuration for writing GPS time and pose into images.
+    #[serde(default)]
+    pub geotag: geotag::Config,
+    /// Configuration for writing XMP sidecars.
+    #[serde(default)]
+    pub sidecar: sidecar::Config,
+    /// Configuration for writing photogrammetry reference files.
+    #[serde(default)]
+    pub export: export::Config,
+    /// Configuration for thinning the image list.
+    #[serde(default)]
+    pub thin: thin::Config,
+    /// Images and time windows to exclude from the image list.
+    #[serde(default)]
+    pub exclude: exclude::Config,
+    /// The number of threads to use for scanning and reading files.
+    ///
+    /// If None, uses one thread per CPU.
+    pub threads: Option<usize>,
+    /// The path to the scan cache file.
+    ///
+    /// Only used by the command line tool. If None, the tool uses `Cache::default_path`.
+    pub cache: Option<PathBuf>,
+    /// How warnings and recoverable problems are handled when building the image list.
+    #[serde(default)]
+    pub mode: Mode,
+}
+
+/// Builds a configuration in code, checking it when it is done.
+///
+/// Every section starts out with its defaults, as if it were left out of a TOML file, and the
+/// version is the current format version.
+#[derive(Debug)]
+pub struct ConfigBuilder {
+    config: Config,
 }
 
 /// An iterator over timestamps and images.
 #[derive(Debug)]
 pub struct ImageList {
-    iter: Zip<IntoIter<PathBuf>, IntoIter<f64>>,
+    iter: IntoIter<Image>,
+    excluded: Vec<integrity::Failure>,
+    dropped: Vec<exclude::Dropped>,
+    orphans: Vec<Image>,
+    skipped: Vec<Skipped>,
+    report: Report,
 }
 
 /// An image record.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Default, PartialEq)]
 pub struct Image {
     /// The path to the image.
     pub path: PathBuf,
     /// The timestamp of the image.
     pub timestamp: f64,
+    /// The index of the record that the image belongs to, starting at zero.
+    pub record: usize,
+    /// Where the image and its timestamp came from.
+    ///
+    /// Images that weren't built by `Config::image_list` have the default source.
+    pub source: Source,
+}
+
+/// Where an image and its timestamp came from.
+///
+/// The source is kept when an image is renamed or organized, so it always describes the original
+/// image file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Source {
+    /// The image number of the original image file, e.g. 3522 for `DSC03522.JPG`.
+    pub image_number: usize,
+    /// The timestamp file that the timestamp was read from.
+    pub timestamp_file: PathBuf,
+    /// The line of the timestamp file that the timestamp was read from, starting at one.
+    pub line: usize,
+    /// The timestamp as it was read from the file, before it was adjusted to its record.
+    pub raw_timestamp: f64,
+    /// The name of the record that the image belongs to, if known.
+    pub record_name: Option<String>,
+    /// The offset that was added to the raw timestamp, as described in
+    /// `record::Config::adjust_timestamps`, or zero if the timestamps are absolute.
+    pub offset: f64,
 }
 
 impl Config {
     /// Creates a configuration from a TOML file at the provided path.
     ///
+    /// If the file `extends` one or more profiles, they are merged in first; see the `profile`
+    /// module.
+    ///
     /// # Examples
     ///
     /// ```
@@ -46,13 +140,60 @@ impl Config {
     /// let config = Config::from_path("data/config.toml").unwrap();
     /// ```
     pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Config> {
-        use std::fs::File;
-        use std::io::Read;
-        use toml;
-        let mut contents = String::new();
-        let mut file = File::open(path)?;
-        file.read_to_string(&mut contents)?;
-        toml::from_str(&contents).map_err(Error::from)
+        Config::from_path_with(path, &[])
+    }
+
+    /// Creates a configuration from a TOML file, with overrides applied on top of it.
+    ///
+    /// Overrides are applied in order, so later overrides win over earlier ones, and all of them
+    /// win over the file and its profiles.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::Config;
+    /// use riprocess::overrides::Override;
+    /// let overrides = vec![Override::parse("images.end=3524").unwrap()];
+    /// let config = Config::from_path_with("data/config.toml", &overrides).unwrap();
+    /// assert_eq!(Some(3524), config.images.end);
+    /// ```
+    pub fn from_path_with<P: AsRef<Path>>(path: P, overrides: &[Override]) -> Result<Config> {
+        use toml::Value;
+        let mut table = profile::load(path.as_ref())?;
+        overrides::apply(&mut table, overrides)?;
+        let config: Config = Value::Table(table).try_into()?;
+        match config.version {
+            Some(version) if version > migrate::VERSION => {
+                Err(Error::InvalidConfig(format!("the configuration is version {}, but this \
+                                                  riprocess only understands up to version {}",
+                                                 version,
+                                                 migrate::VERSION)))
+            }
+            _ => Ok(config),
+        }
+    }
+
+    /// Creates a configuration from a TOML file, rejecting unknown keys and values of the wrong
+    /// type.
+    ///
+    /// Warnings, like sections that fell back to their defaults, don't cause an error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use riprocess::Config;
+    /// let config = Config::from_path_strict("data/config.toml").unwrap();
+    /// ```
+    pub fn from_path_strict<P: AsRef<Path>>(path: P) -> Result<Config> {
+        let errors = lint::check(path.as_ref())?
+            .into_iter()
+            .filter(|issue| issue.level == validate::Level::Error)
+            .collect::<Vec<_>>();
+        if errors.is_empty() {
+            Config::from_path(path)
+        } else {
+            Err(Error::Lint(errors))
+        }
     }
 
     /// Creates a new, default configuration.
@@ -67,10 +208,95 @@ impl Config {
         Default::default()
     }
 
+    /// Returns this configuration as canonical TOML.
+    ///
+    /// Keys are sorted and unset optional values are left out, so two equal configurations
+    /// always produce the same text. Reading the text back with `from_path` gives an equal
+    /// configuration.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use riprocess::Config;
+    /// let config = Config::from_path("data/config.toml").unwrap();
+    /// let toml = config.to_toml().unwrap();
+    /// assert!(toml.contains("start_times = [332978.669, 333039.279]"));
+    /// ```
+    pub fn to_toml(&self) -> Result<String> {
+        use toml::{self, Value};
+        // Going through a `Value` emits plain values before tables, which the TOML serializer
+        // requires but our field order doesn't follow.
+        let value = Value::try_from(self)?;
+        toml::to_string(&value).map_err(Error::from)
+    }
+
+    /// Writes this configuration as canonical TOML to the provided path.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use riprocess::Config;
+    /// let config = Config::from_path("data/config.toml").unwrap();
+    /// let path = std::env::temp_dir().join("riprocess-to-path-doc.toml");
+    /// config.to_path(&path).unwrap();
+    /// assert_eq!(config.to_toml().unwrap(),
+    ///            Config::from_path(&path).unwrap().to_toml().unwrap());
+    /// ```
+    pub fn to_path<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        std::fs::write(path, self.to_toml()?).map_err(Error::from)
+    }
+
+    /// Checks the configured timestamps against the validation rules.
+    ///
+    /// Returns every violation, including warnings. Use this to report warnings, since
+    /// `image_list` only fails on errors.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use riprocess::Config;
+    /// let config = Config::from_path("data/config.toml").unwrap();
+    /// let issues = config.validate().unwrap();
+    /// assert!(issues.is_empty());
+    /// ```
+    pub fn validate(&self) -> Result<Vec<validate::Issue>> {
+        self.validate_with(None)
+    }
+
+    /// Checks the configured timestamps against the validation rules, reading the timestamp
+    /// files through a cache.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use riprocess::Config;
+    /// use riprocess::cache::Cache;
+    /// let config = Config::from_path("data/config.toml").unwrap();
+    /// let cache = Cache::open(std::env::temp_dir().join("riprocess-validate-doc.json"));
+    /// let issues = config.validate_with(Some(&cache)).unwrap();
+    /// ```
+    pub fn validate_with(&self, cache: Option<&Cache>) -> Result<Vec<validate::Issue>> {
+        self.install(|| {
+            self.timestamps
+                .files_with(cache)
+                .and_then(|files| {
+                              let records = self.records(&files)?;
+                              let source = self.timestamps.source();
+                              self.validation.check(&files, &records, source)
+                          })
+        })
+    }
+
     /// Returns an iterator over timestamp+path pairs for each configued image.
     ///
-    /// Errors occur when the number of timestamp files doesn't match the number of records or the
-    /// number of images doesn't match the number of timestamps.
+    /// Files are scanned and read in parallel, using `threads` threads, but the list is the same
+    /// as if they had been read one at a time.
+    ///
+    /// Errors occur when the number of timestamp files doesn't match the number of records, the
+    /// number of images doesn't match the number of timestamps, the timestamps violate a
+    /// validation rule at the error level, an image fails its integrity checks and the
+    /// configured action is to abort, or thinning by distance is configured without a
+    /// trajectory.
     ///
     /// # Examples
     ///
@@ -80,30 +306,542 @@ impl Config {
     /// let image_list = config.image_list().unwrap().collect::<Vec<_>>();
     /// ```
     pub fn image_list(&self) -> Result<ImageList> {
-        let paths = self.images.paths()?;
-        let timestamps = self.timestamps
-            .timestamps()
-            .and_then(|timestamps| self.records.adjust_timestamps(&timestamps))?;
-        if paths.len() != timestamps.len() {
-            Err(Error::TimestampCountMismatch {
-                    timestamps: timestamps.len(),
-                    images: paths.len(),
-                })
+        self.image_list_with(None)
+    }
+
+    /// Returns an iterator over timestamp+path pairs for each configued image, scanning through
+    /// a cache.
+    ///
+    /// Only the files that have changed since they were cached are read. The cache isn't saved;
+    /// call `Cache::save` for that.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use riprocess::Config;
+    /// use riprocess::cache::Cache;
+    /// let config = Config::from_path("data/config.toml").unwrap();
+    /// let cache = Cache::open(std::env::temp_dir().join("riprocess-image-list-doc.json"));
+    /// let image_list = config.image_list_with(Some(&cache)).unwrap();
+    /// ```
+    pub fn image_list_with(&self, cache: Option<&Cache>) -> Result<ImageList> {
+        self.install(|| self.build_image_list(cache))
+    }
+
+    fn build_image_list(&self, cache: Option<&Cache>) -> Result<ImageList> {
+        let lenient = self.mode == Mode::Lenient;
+        let mut report = Report::new();
+        let (scan, files) = rayon::join(|| self.images.scan_with(cache),
+                                        || self.timestamps.scan_files_with(cache, lenient));
+        let (Scan { paths, mut skipped }, (mut files, timestamp_skipped)) = (scan?, files?);
+        skipped.extend(timestamp_skipped);
+        for skipped in &skipped {
+            report.warn(Stage::Scan, skipped.to_string());
+        }
+        for file in &files {
+            for line in &file.skipped_lines {
+                report.repaired(Stage::Parse,
+                                format!("{}:{}: the line isn't a timestamp",
+                                        file.path.display(),
+                                        line),
+                                "skipped the line");
+            }
+        }
+        let mut records = self.records(&files)?.into_iter().enumerate().collect::<Vec<_>>();
+        if lenient {
+            repair_records(&mut files, &mut records, &mut report);
+        }
+        let (indices, records) = records.into_iter().unzip::<_, _, Vec<_>, Vec<_>>();
+        let mut errors = Vec::new();
+        let source = self.timestamps.source();
+        for issue in self.validation.check(&files, &records, source)? {
+            // Orphans are reported below, once they've been separated.
+            if issue.rule == validate::Rule::OutsideRecord && self.records.orphan_list.is_some() {
+                continue;
+            }
+            if issue.level == validate::Level::Warn {
+                report.warn(Stage::Validation, issue.to_string());
+            } else {
+                errors.push(issue);
+            }
+        }
+        if !errors.is_empty() {
+            return Err(Error::Validation(errors));
+        }
+        let timestamps = files.iter().map(|file| file.timestamps.clone()).collect::<Vec<_>>();
+        let offsets = source.offsets(&records, &timestamps)?;
+        let mut sources = Vec::new();
+        for (i, (file, offset)) in files.into_iter().zip(offsets).enumerate() {
+            for (j, &raw_timestamp) in file.timestamps.iter().enumerate() {
+                sources.push((i,
+                              Source {
+                                  image_number: 0,
+                                  timestamp_file: file.path.clone(),
+                                  line: file.line(j),
+                                  raw_timestamp,
+                                  record_name: records[i].name.clone(),
+                                  offset,
+                              }));
+            }
+        }
+        if paths.len() != sources.len() {
+            return Err(Error::TimestampCountMismatch {
+                           timestamps: sources.len(),
+                           images: paths.len(),
+                       });
+        }
+        let excluded = match self.integrity {
+            Some(integrity) => integrity.check_paths_with(&paths, cache),
+            None => Vec::new(),
+        };
+        if !excluded.is_empty() &&
+           self.integrity.map(|integrity| integrity.on_failure) == Some(integrity::Action::Abort) {
+            return Err(Error::Integrity(excluded));
+        }
+        for failure in &excluded {
+            report.warn(Stage::Integrity, format!("excluded {}", failure));
+        }
+        let images = paths.into_iter()
+            .zip(sources)
+            .filter(|(path, _)| !excluded.iter().any(|failure| failure.path == *path))
+            .map(|(path, (i, source))| {
+                     (i,
+                      Image {
+                          timestamp: source.raw_timestamp + source.offset,
+                          record: indices[i],
+                          source: Source {
+                              image_number: image::image_number(&path).unwrap_or_default(),
+                              ..source
+                          },
+                          path,
+                      })
+                 })
+            .collect::<Vec<_>>();
+        let (images, orphans) = if self.records.orphan_list.is_some() {
+            let (images, orphans): (Vec<_>, Vec<_>) = images.into_iter()
+                .partition(|(i, image)| records[*i].contains(image.timestamp, records.get(i + 1)));
+            for (_, orphan) in &orphans {
+                report.warn(Stage::Pairing,
+                            format!("{} is outside of its record, so it is an orphan",
+                                    orphan.path.display()));
+            }
+            (images, orphans.into_iter().map(|(_, image)| image).collect())
+        } else {
+            (images, Vec::new())
+        };
+        let images = images.into_iter().map(|(_, image)| image).collect();
+        let (images, dropped) = self.exclude.exclude(images);
+        let images = if self.thin.is_enabled() {
+            let trajectory = match (self.thin.min_distance, self.trajectory.as_ref()) {
+                (Some(_), Some(trajectory)) => Some(trajectory.read()?),
+                _ => None,
+            };
+            self.thin.thin(images, trajectory.as_ref())?
+        } else {
+            images
+        };
+        if self.mode == Mode::Strict && !report.is_empty() {
+            return Err(Error::Warnings(report.into_warnings()));
+        }
+        Ok(ImageList {
+               iter: images.into_iter(),
+               excluded,
+               dropped,
+               orphans,
+               skipped,
+               report,
+           })
+    }
+
+    /// Checks the configuration for mistakes that can be found without reading any files.
+    fn check(&self) -> Result<()> {
+        let invalid = |message: &str| Err(Error::InvalidConfig(message.to_string()));
+        if self.images.path.as_os_str().is_empty() {
+            return invalid("the image path is not set");
+        }
+        if self.timestamps.path.as_os_str().is_empty() {
+            return invalid("the timestamp path is not set");
+        }
+        if let (Some(start), Some(end)) = (self.images.start, self.images.end) {
+            if start > end {
+                return invalid("the first image comes after the last image");
+            }
+        }
+        if let (Some(ref start), Some(ref end)) = (&self.timestamps.start, &self.timestamps.end) {
+            if start > end {
+                return invalid("the first timestamp file comes after the last timestamp file");
+            }
+        }
+        if self.records.csv.is_none() {
+            if self.records.start_times.is_empty() && !self.timestamps.source().is_absolute() {
+                return invalid("there are no record start times, and no Records.csv");
+            }
+            self.records.records()?;
+        }
+        if self.threads == Some(0) {
+            return invalid("the number of threads must be at least one");
+        }
+        if self.thin.every == Some(0) {
+            return invalid("thinning can't keep every 0th image");
+        }
+        Ok(())
+    }
+
+    /// Returns the configured records.
+    ///
+    /// If no records are configured and the timestamps are absolute, each timestamp file gets a
+    /// record of its own, starting at its first timestamp and lasting until the next one starts.
+    pub(crate) fn records(&self, files: &[timestamp::File]) -> Result<Vec<record::Record>> {
+        if self.records.csv.is_some() || !self.records.start_times.is_empty() ||
+           !self.timestamps.source().is_absolute() {
+            return self.records.records();
+        }
+        Ok(files.iter()
+               .map(|file| {
+                        record::Record {
+                            name: None,
+                            start: file.timestamps.first().cloned().unwrap_or_default(),
+                            end: None,
+                        }
+                    })
+               .collect())
+    }
+
+    /// Runs `f` in a thread pool with the configured number of threads.
+    fn install<T, F>(&self, f: F) -> Result<T>
+        where F: FnOnce() -> Result<T> + Send,
+              T: Send
+    {
+        use rayon::ThreadPoolBuilder;
+        match self.threads {
+            Some(threads) => ThreadPoolBuilder::new().num_threads(threads).build()?.install(f),
+            None => f(),
+        }
+    }
+}
+
+/// Drops empty timestamp files along with their records, and records without a timestamp file.
+fn repair_records(files: &mut Vec<timestamp::File>,
+                  records: &mut Vec<(usize, record::Record)>,
+                  report: &mut Report) {
+    let mut i = 0;
+    while i < files.len() {
+        if files[i].timestamps.is_empty() && i < records.len() {
+            report.repaired(Stage::Records,
+                            format!("{} has no timestamps", files[i].path.display()),
+                            format!("dropped it and record {}", records[i].0 + 1));
+            files.remove(i);
+            records.remove(i);
         } else {
-            Ok(ImageList { iter: paths.into_iter().zip(timestamps.into_iter()) })
+            i += 1;
+        }
+    }
+    if records.len() > files.len() {
+        for &(index, _) in &records[files.len()..] {
+            report.repaired(Stage::Records,
+                            format!("record {} has no timestamp file", index + 1),
+                            "dropped the record");
+        }
+        records.truncate(files.len());
+    }
+}
+
+impl Default for ConfigBuilder {
+    fn default() -> ConfigBuilder {
+        ConfigBuilder {
+            config: Config { version: Some(migrate::VERSION), ..Default::default() },
         }
     }
 }
 
+impl ConfigBuilder {
+    /// Creates a new builder, starting from the default configuration.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::ConfigBuilder;
+    /// let builder = ConfigBuilder::new();
+    /// ```
+    pub fn new() -> ConfigBuilder {
+        Default::default()
+    }
+
+    /// Sets the directory that holds the images.
+    pub fn images<P: Into<PathBuf>>(mut self, path: P) -> ConfigBuilder {
+        self.config.images.path = path.into();
+        self
+    }
+
+    /// Sets the numbers of the first and last images to be used.
+    pub fn image_range(mut self, start: usize, end: usize) -> ConfigBuilder {
+        self.config.images.start = Some(start);
+        self.config.images.end = Some(end);
+        self
+    }
+
+    /// Sets the name of the camera that took the images.
+    pub fn camera<S: Into<String>>(mut self, camera: S) -> ConfigBuilder {
+        self.config.images.camera = Some(camera.into());
+        self
+    }
+
+    /// Sets the directory that holds the timestamp files.
+    pub fn timestamps<P: Into<PathBuf>>(mut self, path: P) -> ConfigBuilder {
+        self.config.timestamps.path = path.into();
+        self
+    }
+
+    /// Sets the names of the first and last timestamp files to be used.
+    pub fn timestamp_range<S: Into<String>>(mut self, start: S, end: S) -> ConfigBuilder {
+        self.config.timestamps.start = Some(start.into());
+        self.config.timestamps.end = Some(end.into());
+        self
+    }
+
+    /// Sets the start time of each record.
+    pub fn start_times(mut self, start_times: Vec<f64>) -> ConfigBuilder {
+        self.config.records.start_times = start_times;
+        self
+    }
+
+    /// Sets the whole record configuration.
+    pub fn records(mut self, records: record::Config) -> ConfigBuilder {
+        self.config.records = records;
+        self
+    }
+
+    /// Sets the timestamp validation configuration.
+    pub fn validation(mut self, validation: validate::Config) -> ConfigBuilder {
+        self.config.validation = validation;
+        self
+    }
+
+    /// Turns on image integrity checks.
+    pub fn integrity(mut self, integrity: integrity::Config) -> ConfigBuilder {
+        self.config.integrity = Some(integrity);
+        self
+    }
+
+    /// Sets the configuration for organizing images into the project tree.
+    pub fn organize(mut self, organize: organize::Config) -> ConfigBuilder {
+        self.config.organize = Some(organize);
+        self
+    }
+
+    /// Sets the configuration for renaming images.
+    pub fn rename(mut self, rename: rename::Config) -> ConfigBuilder {
+        self.config.rename = rename;
+        self
+    }
+
+    /// Sets the platform trajectory.
+    pub fn trajectory(mut self, trajectory: trajectory::Config) -> ConfigBuilder {
+        self.config.trajectory = Some(trajectory);
+        self
+    }
+
+    /// Sets the configuration for writing GPS time and pose into images.
+    pub fn geotag(mut self, geotag: geotag::Config) -> ConfigBuilder {
+        self.config.geotag = geotag;
+        self
+    }
+
+    /// Sets the configuration for writing XMP sidecars.
+    pub fn sidecar(mut self, sidecar: sidecar::Config) -> ConfigBuilder {
+        self.config.sidecar = sidecar;
+        self
+    }
+
+    /// Sets the configuration for writing photogrammetry reference files.
+    pub fn export(mut self, export: export::Config) -> ConfigBuilder {
+        self.config.export = export;
+        self
+    }
+
+    /// Sets the configuration for thinning the image list.
+    pub fn thin(mut self, thin: thin::Config) -> ConfigBuilder {
+        self.config.thin = thin;
+        self
+    }
+
+    /// Sets the images and time windows to exclude from the image list.
+    pub fn exclude(mut self, exclude: exclude::Config) -> ConfigBuilder {
+        self.config.exclude = exclude;
+        self
+    }
+
+    /// Sets the number of threads to use for scanning and reading files.
+    pub fn threads(mut self, threads: usize) -> ConfigBuilder {
+        self.config.threads = Some(threads);
+        self
+    }
+
+    /// Sets the path to the scan cache file.
+    pub fn cache<P: Into<PathBuf>>(mut self, path: P) -> ConfigBuilder {
+        self.config.cache = Some(path.into());
+        self
+    }
+
+    /// Sets how warnings and recoverable problems are handled.
+    pub fn mode(mut self, mode: Mode) -> ConfigBuilder {
+        self.config.mode = mode;
+        self
+    }
+
+    /// Checks the configuration and returns it.
+    ///
+    /// Returns an error if the image or timestamp path is missing, if a range is backwards, if
+    /// the records are inconsistent, or if a count is zero. No files are read; use
+    /// `Config::validate` to check the data itself.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::ConfigBuilder;
+    /// let config = ConfigBuilder::new()
+    ///     .images("data/images")
+    ///     .image_range(3522, 3525)
+    ///     .timestamps("data/timestamps")
+    ///     .timestamp_range("170621_202939.eif", "170621_203040.eif")
+    ///     .start_times(vec![332978.669, 333039.279])
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(4, config.image_list().unwrap().count());
+    /// assert!(ConfigBuilder::new().images("data/images").build().is_err());
+    /// ```
+    pub fn build(self) -> Result<Config> {
+        self.config.check()?;
+        Ok(self.config)
+    }
+}
+
+impl ImageList {
+    /// Returns the images that were left out of this list because they failed their integrity
+    /// checks.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use riprocess::Config;
+    /// let config = Config::from_path("data/config.toml").unwrap();
+    /// let image_list = config.image_list().unwrap();
+    /// assert!(image_list.excluded().is_empty());
+    /// ```
+    pub fn excluded(&self) -> &[integrity::Failure] {
+        &self.excluded
+    }
+
+    /// Returns the images that were dropped by the configured exclusions.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use riprocess::Config;
+    /// let mut config = Config::from_path("data/config.toml").unwrap();
+    /// config.exclude.images = vec![3522];
+    /// let image_list = config.image_list().unwrap();
+    /// assert_eq!(1, image_list.dropped().len());
+    /// ```
+    pub fn dropped(&self) -> &[exclude::Dropped] {
+        &self.dropped
+    }
+
+    /// Returns the images that are outside of their record's span.
+    ///
+    /// Orphans are only separated from the list if the record configuration has an
+    /// `orphan_list`; otherwise this is empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use riprocess::Config;
+    /// let mut config = Config::from_path("data/config.toml").unwrap();
+    /// config.records.durations = vec![2., 2.];
+    /// config.records.orphan_list = Some("orphans.txt".into());
+    /// let image_list = config.image_list().unwrap();
+    /// assert_eq!(2, image_list.orphans().len());
+    /// ```
+    pub fn orphans(&self) -> &[Image] {
+        &self.orphans
+    }
+
+    /// Returns the entries in the image and timestamp directories that were skipped.
+    ///
+    /// See `image::Config::scan` and `timestamp::Config::scan`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use riprocess::Config;
+    /// let config = Config::from_path("data/config.toml").unwrap();
+    /// let image_list = config.image_list().unwrap();
+    /// assert!(image_list.skipped().is_empty());
+    /// ```
+    pub fn skipped(&self) -> &[Skipped] {
+        &self.skipped
+    }
+
+    /// Returns the warnings that were collected while building the image list.
+    ///
+    /// The report includes the skipped entries, warning-level validation issues, excluded images,
+    /// and orphans, along with anything that was repaired in lenient mode. In strict mode, the
+    /// image list isn't built if there are any warnings, so the report is always empty.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use riprocess::Config;
+    /// let config = Config::from_path("data/config.toml").unwrap();
+    /// let image_list = config.image_list().unwrap();
+    /// assert!(image_list.report().is_empty());
+    /// ```
+    pub fn report(&self) -> &Report {
+        &self.report
+    }
+}
+
+impl Image {
+    /// The names of the provenance columns, in the order that `provenance` returns them.
+    pub const PROVENANCE_COLUMNS: [&'static str; 7] = ["image_number",
+                                                       "timestamp_file",
+                                                       "line",
+                                                       "raw_timestamp",
+                                                       "record",
+                                                       "record_name",
+                                                       "offset"];
+
+    /// Returns the provenance columns for this image, for output formats that include them.
+    ///
+    /// The record is its index, starting at zero, and the record name is empty if it isn't known.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use riprocess::{Config, Image};
+    /// let config = Config::from_path("data/config.toml").unwrap();
+    /// let image = config.image_list().unwrap().next().unwrap();
+    /// let columns = image.provenance();
+    /// assert_eq!(Image::PROVENANCE_COLUMNS.len(), columns.len());
+    /// assert_eq!("3522", columns[0]);
+    /// assert_eq!("1", columns[2]);
+    /// assert_eq!("73779.899441", columns[3]);
+    /// ```
+    pub fn provenance(&self) -> Vec<String> {
+        vec![self.source.image_number.to_string(),
+             self.source.timestamp_file.display().to_string(),
+             self.source.line.to_string(),
+             format!("{:.6}", self.source.raw_timestamp),
+             self.record.to_string(),
+             self.source.record_name.clone().unwrap_or_default(),
+             format!("{:.3}", self.source.offset)]
+    }
+}
+
 impl Iterator for ImageList {
     type Item = Image;
     fn next(&mut self) -> Option<Image> {
-        self.iter.next().map(|(path, timestamp)| {
-                                 Image {
-                                     path: path,
-                                     timestamp: timestamp,
-                                 }
-                             })
+        self.iter.next()
     }
 }
 
@@ -127,6 +865,120 @@ mod tests {
             .map(|(image, expected)| (expected - image.timestamp).abs())
             .collect::<Vec<_>>();
         assert!(deltas.iter().all(|&delta| delta < 1e-7), "{:?}", deltas);
+
+        let records = images.iter().map(|image| image.record).collect::<Vec<_>>();
+        assert_eq!(vec![0, 0, 1, 1], records);
+    }
+
+    #[test]
+    fn source() {
+        let config = Config::from_path("data/config.toml").unwrap();
+        let images = config.image_list().unwrap().collect::<Vec<_>>();
+        let sources = images.iter()
+            .map(|image| {
+                     (image.source.image_number,
+                      image.source.timestamp_file.file_name().unwrap().to_str().unwrap(),
+                      image.source.line)
+                 })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(3522, "170621_202939.eif", 1),
+                        (3523, "170621_202939.eif", 2),
+                        (3524, "170621_203040.eif", 1),
+                        (3525, "170621_203040.eif", 2)],
+                   sources);
+        for image in &images {
+            assert_eq!(332900. - 73700., image.source.offset);
+            assert_eq!(image.timestamp, image.source.raw_timestamp + image.source.offset);
+            assert_eq!(None, image.source.record_name);
+        }
+    }
+
+    #[test]
+    fn threads() {
+        let mut config = Config::from_path("data/config.toml").unwrap();
+        let parallel = config.image_list().unwrap().collect::<Vec<_>>();
+        config.threads = Some(1);
+        assert_eq!(parallel, config.image_list().unwrap().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn cache() {
+        let path = std::env::temp_dir().join("riprocess-config-cache.json");
+        Cache::clear(&path).unwrap();
+        let mut config = Config::from_path("data/config.toml").unwrap();
+        config.integrity = Some(integrity::Config::new());
+        let expected = config.image_list().unwrap().collect::<Vec<_>>();
+        for _ in 0..2 {
+            let cache = Cache::open(&path);
+            assert!(config.validate_with(Some(&cache)).unwrap().is_empty());
+            assert_eq!(expected,
+                       config.image_list_with(Some(&cache)).unwrap().collect::<Vec<_>>());
+            cache.save().unwrap();
+        }
+        Cache::clear(&path).unwrap();
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = std::env::temp_dir().join("riprocess-round-trip.toml");
+        let mut config = Config::from_path("data/config.toml").unwrap();
+        config.records.end_times = vec![333008.669, 333069.279];
+        config.integrity = Some(integrity::Config::new());
+        config.organize = Some(organize::Config {
+                                   project: "project".into(),
+                                   ..Default::default()
+                               });
+        config.trajectory = Some(trajectory::Config { skip_lines: 1, ..Default::default() });
+        config.geotag.gps_week = Some(1954);
+        config.sidecar.packages = vec![sidecar::Package::RealityCapture];
+        config.export.accuracy = Some(export::Accuracy {
+                                          horizontal: 0.05,
+                                          vertical: 0.1,
+                                          angular: None,
+                                      });
+        config.thin.min_interval = Some(1.5);
+        config.exclude.image_ranges = vec![(3600, 3620)];
+        config.exclude.time_windows = vec![(333000., 333030.)];
+        config.threads = Some(2);
+        config.to_path(&path).unwrap();
+        let read = Config::from_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(config.to_toml().unwrap(), read.to_toml().unwrap());
+        assert_eq!(format!("{:?}", config), format!("{:?}", read));
+    }
+
+    #[test]
+    fn newer_version() {
+        let path = std::env::temp_dir().join("riprocess-newer-version.toml");
+        let contents = std::fs::read_to_string("data/config.toml").unwrap();
+        std::fs::write(&path, format!("version = {}\n{}", migrate::VERSION + 1, contents))
+            .unwrap();
+        let result = Config::from_path(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn builder() {
+        let builder = || {
+            ConfigBuilder::new()
+                .images("data/images")
+                .timestamps("data/timestamps")
+                .start_times(vec![332978.669, 333039.279])
+        };
+        assert!(builder().build().is_ok());
+        assert!(builder().image_range(3525, 3522).build().is_err());
+        assert!(builder().threads(0).build().is_err());
+        let records = |durations| {
+            record::Config {
+                start_times: vec![1.],
+                durations,
+                ..Default::default()
+            }
+        };
+        assert!(builder().records(records(vec![2.])).build().is_ok());
+        assert!(builder().records(records(vec![1., 2.])).build().is_err());
+        assert!(builder().start_times(vec![]).build().is_err());
     }
 
     #[test]
@@ -143,6 +995,225 @@ mod tests {
         assert!(config.image_list().is_err());
     }
 
+    #[test]
+    fn validation_error() {
+        let mut config = Config::from_path("data/config.toml").unwrap();
+        config.timestamps.start = Some("170621_202750.eif".to_string());
+        config.timestamps.end = Some("170621_202939.eif".to_string());
+        config.images.end = Some(3526);
+        config.records.start_times = vec![332978.669, 332978.669];
+        match config.image_list() {
+            Err(Error::Validation(issues)) => assert!(!issues.is_empty()),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn integrity() {
+        let mut config = Config::from_path("data/config.toml").unwrap();
+        config.integrity = Some(integrity::Config { min_size: 1 << 20, ..Default::default() });
+        match config.image_list() {
+            Err(Error::Integrity(failures)) => assert_eq!(4, failures.len()),
+            other => panic!("expected an integrity error, got {:?}", other),
+        }
+        config.integrity = Some(integrity::Config {
+                                    min_size: 1 << 20,
+                                    on_failure: integrity::Action::Exclude,
+                                    ..Default::default()
+                                });
+        let image_list = config.image_list().unwrap();
+        assert_eq!(4, image_list.excluded().len());
+        assert_eq!(0, image_list.count());
+    }
+
+    #[test]
+    fn orphans() {
+        let mut config = Config::from_path("data/config.toml").unwrap();
+        config.records.durations = vec![2., 2.];
+        config.validation.outside_record = validate::Level::Error;
+        match config.image_list() {
+            Err(Error::Validation(issues)) => assert_eq!(2, issues.len()),
+            other => panic!("expected a validation error, got {:?}", other),
+        }
+        config.records.orphan_list = Some("orphans.txt".into());
+        let image_list = config.image_list().unwrap();
+        let orphans = image_list.orphans()
+            .iter()
+            .map(|image| image::image_number(&image.path).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(vec![3523, 3525], orphans);
+        assert_eq!(2, image_list.report().warnings().len());
+        assert_eq!(2, image_list.count());
+    }
+
+    #[test]
+    fn strict() {
+        let mut config = Config::from_path("data/config.toml").unwrap();
+        config.mode = Mode::Strict;
+        assert!(config.image_list().is_ok());
+        config.records.durations = vec![2., 2.];
+        config.records.orphan_list = Some("orphans.txt".into());
+        match config.image_list() {
+            Err(Error::Warnings(warnings)) => {
+                assert!(warnings.iter().all(|warning| warning.stage == Stage::Pairing))
+            }
+            other => panic!("expected a warnings error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn lenient() {
+        use std::fs;
+
+        let directory = std::env::temp_dir().join("riprocess-config-lenient");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("170621_202939.eif"), "73779.899441\nbad\n73781.419326\n")
+            .unwrap();
+        fs::write(directory.join("170621_203000.eif"), "").unwrap();
+        fs::copy("data/timestamps/170621_203040.eif",
+                 directory.join("170621_203040.eif"))
+            .unwrap();
+        let mut config = Config::from_path("data/config.toml").unwrap();
+        config.timestamps = timestamp::Config { path: directory.clone(), ..Default::default() };
+        config.records.start_times = vec![332978.669, 333000., 333039.279, 333100.];
+        assert!(config.image_list().is_err());
+        config.mode = Mode::Lenient;
+        let image_list = config.image_list();
+        fs::remove_dir_all(&directory).unwrap();
+        let image_list = image_list.unwrap();
+        assert_eq!(3, image_list.report().repairs());
+        let images = image_list.collect::<Vec<_>>();
+        assert_eq!(vec![0, 0, 2, 2], images.iter().map(|image| image.record).collect::<Vec<_>>());
+        assert_eq!(3, images[1].source.line);
+        assert!((images[2].timestamp - 333040.399224).abs() < 1e-7);
+    }
+
+    #[test]
+    fn timestamp_formats() {
+        use std::fs;
+        use timestamp::{Columns, Csv, Format};
+
+        let directory = std::env::temp_dir().join("riprocess-config-timestamp-formats");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("record1.csv"),
+                  "channel,time\nCAM1,73779.899441\nCAM2,73780.5\nCAM1,73781.419326\n")
+            .unwrap();
+        fs::write(directory.join("record2.csv"),
+                  "channel,time\nCAM1,73840.399224\nCAM1,73842.018970\n")
+            .unwrap();
+        let mark = |seconds| {
+            format!("#MARK1PVAA,COM1,0,77.5,FINESTEERING,1953,{0},00000000,0,1;\
+                     1953,{0},39.7,-105.2,1650.0,0.0,0.0,0.0,0.0,0.0,90.0,INS_SOLUTION_GOOD\
+                     *0a1b2c3d\n",
+                    seconds)
+        };
+        let log = format!("#BESTPOSA,COM1,0,77.5;SOL_COMPUTED*4a3e1b2c\n{}{}",
+                          mark(332979.899441),
+                          mark(332981.419326));
+        fs::write(directory.join("record1.asc"), log).unwrap();
+        fs::write(directory.join("record2.asc"),
+                  mark(333040.399224) + &mark(333042.018970))
+            .unwrap();
+
+        let mut config = Config::from_path("data/config.toml").unwrap();
+        let expected =
+            config.image_list().unwrap().map(|image| image.timestamp).collect::<Vec<_>>();
+        config.timestamps = timestamp::Config {
+            path: directory.clone(),
+            format: Format::Csv,
+            csv: Csv {
+                skip_lines: 1,
+                columns: Columns { time: 1, channel: Some(0) },
+                channel: Some("CAM1".to_string()),
+            },
+            ..Default::default()
+        };
+        let csv = config.image_list().map(|image_list| image_list.collect::<Vec<_>>());
+        config.timestamps.format = Format::Novatel;
+        let novatel = config.image_list().map(|image_list| image_list.collect::<Vec<_>>());
+        fs::remove_dir_all(&directory).unwrap();
+
+        let csv = csv.unwrap();
+        assert_eq!(expected, csv.iter().map(|image| image.timestamp).collect::<Vec<_>>());
+        assert_eq!(4, csv[1].source.line);
+        let novatel = novatel.unwrap();
+        assert_eq!(4, novatel.len());
+        for (expected, image) in expected.iter().zip(&novatel) {
+            assert!((expected - image.timestamp).abs() < 1e-7);
+        }
+        assert_eq!(3, novatel[1].source.line);
+    }
+
+    #[test]
+    fn novatel_is_absolute() {
+        use std::fs;
+        use timestamp::Format;
+
+        let directory = std::env::temp_dir().join("riprocess-config-novatel-is-absolute");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let mark = |seconds| {
+            format!("#MARKTIMEA,COM1,0,77.5,FINESTEERING,1953,{0},0,0,1;\
+                     1953,{0},0.0,0.0,-18.0,VALID*d8a2b4e5\n",
+                    seconds)
+        };
+        // The first record starts at 332978.669, in a different hundred seconds than its marks.
+        fs::write(directory.join("record1.asc"), mark(333001.) + &mark(333002.5)).unwrap();
+        fs::write(directory.join("record2.asc"), mark(333040.25) + &mark(333042.)).unwrap();
+
+        let mut config = Config::from_path("data/config.toml").unwrap();
+        config.timestamps = timestamp::Config {
+            path: directory.clone(),
+            format: Format::Novatel,
+            ..Default::default()
+        };
+        let timestamps = |config: &Config| {
+            config.image_list().map(|image_list| {
+                                        image_list.map(|image| image.timestamp)
+                                            .collect::<Vec<_>>()
+                                    })
+        };
+        let with_records = timestamps(&config);
+        config.records.start_times.clear();
+        let checked = config.check();
+        let without_records = timestamps(&config);
+        fs::remove_dir_all(&directory).unwrap();
+
+        let expected = vec![333001., 333002.5, 333040.25, 333042.];
+        assert_eq!(expected, with_records.unwrap());
+        checked.unwrap();
+        assert_eq!(expected, without_records.unwrap());
+    }
+
+    #[test]
+    fn exclude() {
+        let mut config = Config::from_path("data/config.toml").unwrap();
+        config.exclude.images = vec![3523];
+        config.exclude.time_windows = vec![(333042., 333043.)];
+        let image_list = config.image_list().unwrap();
+        let dropped = image_list.dropped()
+            .iter()
+            .map(|dropped| image::image_number(&dropped.image.path).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(vec![3523, 3525], dropped);
+        let timestamps = image_list.map(|image| image.timestamp).collect::<Vec<_>>();
+        assert!((timestamps[1] - 333040.399224).abs() < 1e-7);
+    }
+
+    #[test]
+    fn thin() {
+        let mut config = Config::from_path("data/config.toml").unwrap();
+        config.thin.min_interval = Some(10.);
+        let timestamps =
+            config.image_list().unwrap().map(|image| image.timestamp).collect::<Vec<_>>();
+        assert_eq!(2, timestamps.len());
+        assert!((timestamps[1] - 333040.399224).abs() < 1e-7);
+        config.thin.min_distance = Some(10.);
+        assert!(config.image_list().is_err());
+    }
+
     #[test]
     fn no_images() {
         let mut config = Config::from_path("data/config.toml").unwrap();
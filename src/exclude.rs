@@ -0,0 +1,172 @@
+//! Exclusion lists.
+//!
+//! Test shots on the ground, lens-cap frames, and the turns between flight lines can be dropped
+//! by image number or by GPS time. Exclusions are applied to the paired image list, so excluded
+//! images still consume their timestamps.
+
+use Image;
+use std::fmt;
+
+/// Configuration for excluding images.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    /// Image numbers to exclude.
+    pub images: Vec<usize>,
+    /// Inclusive ranges of image numbers to exclude, e.g. `[[3600, 3620]]`.
+    pub image_ranges: Vec<(usize, usize)>,
+    /// Inclusive windows of adjusted GPS time to exclude, e.g. `[[333000.0, 333030.0]]`.
+    pub time_windows: Vec<(f64, f64)>,
+}
+
+/// An image that was dropped by an exclusion.
+#[derive(Debug, PartialEq)]
+pub struct Dropped {
+    /// The dropped image.
+    pub image: Image,
+    /// The exclusion that dropped it.
+    pub reason: Reason,
+}
+
+/// The exclusion that dropped an image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reason {
+    /// The image number is in the list of excluded images.
+    ImageNumber(usize),
+    /// The image number is in an excluded range.
+    ImageRange(usize, usize),
+    /// The timestamp is in an excluded time window.
+    TimeWindow(f64, f64),
+}
+
+impl Config {
+    /// Creates a new configuration that doesn't exclude anything.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::exclude::Config;
+    /// let config = Config::new();
+    /// ```
+    pub fn new() -> Config {
+        Default::default()
+    }
+
+    /// Returns the exclusion that matches an image, if any.
+    ///
+    /// Images are matched by the image number of their source, so renamed images still match.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::{Image, Source};
+    /// use riprocess::exclude::{Config, Reason};
+    /// let config = Config { image_ranges: vec![(3520, 3529)], ..Default::default() };
+    /// let image = Image {
+    ///     path: "DSC03522.JPG".into(),
+    ///     timestamp: 332979.899441,
+    ///     source: Source { image_number: 3522, ..Default::default() },
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(Some(Reason::ImageRange(3520, 3529)), config.reason(&image));
+    /// ```
+    pub fn reason(&self, image: &Image) -> Option<Reason> {
+        let image_number = image.source.image_number;
+        if self.images.contains(&image_number) {
+            return Some(Reason::ImageNumber(image_number));
+        }
+        if let Some(&(start, end)) = self.image_ranges
+            .iter()
+            .find(|&&(start, end)| start <= image_number && image_number <= end) {
+            return Some(Reason::ImageRange(start, end));
+        }
+        self.time_windows
+            .iter()
+            .find(|&&(start, end)| start <= image.timestamp && image.timestamp <= end)
+            .map(|&(start, end)| Reason::TimeWindow(start, end))
+    }
+
+    /// Splits a list of images into kept and dropped images.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use riprocess::Config;
+    /// use riprocess::exclude;
+    /// let config = Config::from_path("data/config.toml").unwrap();
+    /// let images = config.image_list().unwrap().collect();
+    /// let exclude = exclude::Config { images: vec![3523], ..Default::default() };
+    /// let (kept, dropped) = exclude.exclude(images);
+    /// assert_eq!(3, kept.len());
+    /// assert_eq!(1, dropped.len());
+    /// ```
+    pub fn exclude(&self, images: Vec<Image>) -> (Vec<Image>, Vec<Dropped>) {
+        let mut kept = Vec::new();
+        let mut dropped = Vec::new();
+        for image in images {
+            match self.reason(&image) {
+                Some(reason) => dropped.push(Dropped { image, reason }),
+                None => kept.push(image),
+            }
+        }
+        (kept, dropped)
+    }
+}
+
+impl fmt::Display for Dropped {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{} ({:.6}): {}",
+               self.image.path.display(),
+               self.image.timestamp,
+               self.reason)
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Reason::ImageNumber(image_number) => write!(f, "image {} is excluded", image_number),
+            Reason::ImageRange(start, end) => write!(f, "images {}-{} are excluded", start, end),
+            Reason::TimeWindow(start, end) => {
+                write!(f, "times {:.6}-{:.6} are excluded", start, end)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Source;
+
+    fn image(image_number: usize, timestamp: f64) -> Image {
+        Image {
+            path: format!("renamed_{}.jpg", image_number).into(),
+            timestamp,
+            record: 0,
+            source: Source { image_number, ..Default::default() },
+        }
+    }
+
+    #[test]
+    fn exclude() {
+        let config = Config {
+            images: vec![1],
+            image_ranges: vec![(3, 4)],
+            time_windows: vec![(10., 20.)],
+        };
+        let images = vec![image(0, 0.), image(1, 1.), image(3, 3.), image(4, 4.), image(5, 15.),
+                          image(6, 20.5)];
+        let (kept, dropped) = config.exclude(images);
+        assert_eq!(vec![image(0, 0.), image(6, 20.5)], kept);
+        let reasons = dropped.iter().map(|dropped| dropped.reason).collect::<Vec<_>>();
+        assert_eq!(vec![Reason::ImageNumber(1),
+                        Reason::ImageRange(3, 4),
+                        Reason::ImageRange(3, 4),
+                        Reason::TimeWindow(10., 20.)],
+                   reasons);
+        assert_eq!("renamed_1.jpg (1.000000): image 1 is excluded",
+                   dropped[0].to_string());
+    }
+}
@@ -0,0 +1,445 @@
+//! Minimal EXIF (TIFF) reading and writing.
+//!
+//! Only the image file directories that we care about are read: IFD0, and the EXIF and GPS
+//! directories that it points to. When writing, the existing TIFF data is never moved: a new GPS
+//! directory and a new copy of IFD0 are appended, and the header is pointed at the new IFD0. This
+//! keeps every existing offset, including those inside of maker notes, valid. If the data ends with
+//! a GPS directory and IFD0 copy appended by an earlier write, and nothing else points into them,
+//! they are replaced instead of kept, so writing again doesn't grow the data.
+
+use {Error, Result};
+
+/// The tag of the IFD0 field that points to the EXIF directory.
+pub const EXIF_IFD_POINTER: u16 = 0x8769;
+/// The tag of the IFD0 field that points to the GPS directory.
+pub const GPS_IFD_POINTER: u16 = 0x8825;
+
+/// The tags of IFD0 fields that can hold offsets into the TIFF data.
+const POINTERS: [u16; 4] = [EXIF_IFD_POINTER, 0x0111, 0x014a, 0x0201];
+
+const BYTE: u16 = 1;
+const ASCII: u16 = 2;
+const LONG: u16 = 4;
+const RATIONAL: u16 = 5;
+
+/// A value of a field in an image file directory.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// One or more bytes.
+    Byte(Vec<u8>),
+    /// A string, which is NUL-terminated when written.
+    Ascii(String),
+    /// One or more unsigned rationals, as numerator and denominator.
+    Rational(Vec<(u32, u32)>),
+}
+
+/// A field in an image file directory.
+#[derive(Clone, Debug)]
+struct Field {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    data: Vec<u8>,
+}
+
+/// Checks that TIFF data, e.g. the payload of an EXIF segment, can be read.
+///
+/// IFD0 is read, along with the EXIF and GPS directories if IFD0 points to them.
+pub fn check(bytes: &[u8]) -> Result<()> {
+    let reader = Reader::new(bytes)?;
+    let ifd0 = reader.ifd(reader.u32(4)? as usize)?;
+    for tag in &[EXIF_IFD_POINTER, GPS_IFD_POINTER] {
+        if let Some(field) = ifd0.iter().find(|field| field.tag == *tag) {
+            reader.ifd(reader.read_u32(&field.data) as usize)?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the fields of the GPS directory that have byte, ASCII, or rational values.
+pub fn gps(bytes: &[u8]) -> Result<Vec<(u16, Value)>> {
+    let reader = Reader::new(bytes)?;
+    Ok(reader.gps()?
+           .into_iter()
+           .filter_map(|field| reader.value(&field).map(|value| (field.tag, value)))
+           .collect())
+}
+
+/// Sets fields in the GPS directory, returning the new TIFF data.
+///
+/// Existing GPS fields that aren't set are kept. If `bytes` is None, a new big-endian TIFF
+/// structure that only holds the GPS directory is created.
+pub fn set_gps(bytes: Option<&[u8]>, fields: &[(u16, Value)]) -> Result<Vec<u8>> {
+    let mut tiff = match bytes {
+        Some(bytes) => bytes.to_vec(),
+        None => b"MM\x00\x2a\x00\x00\x00\x08\x00\x00\x00\x00\x00\x00".to_vec(),
+    };
+    let (big_endian, ifd0, next, mut gps, appended) = {
+        let reader = Reader::new(&tiff)?;
+        let offset = reader.u32(4)? as usize;
+        let count = reader.u16(offset)? as usize;
+        let ifd0 = (0..count)
+            .map(|i| reader.slice(offset + 2 + i * 12, 12).map(|entry| entry.to_vec()))
+            .collect::<Result<Vec<_>>>()?;
+        let next = reader.u32(offset + 2 + count * 12)?;
+        (reader.big_endian, ifd0, next, reader.gps()?, reader.appended()?)
+    };
+    if let Some(offset) = appended {
+        tiff.truncate(offset);
+    }
+    let writer = Writer { big_endian };
+    for &(tag, ref value) in fields {
+        gps.retain(|field| field.tag != tag);
+        gps.push(writer.field(tag, value));
+    }
+    gps.sort_by_key(|field| field.tag);
+
+    pad(&mut tiff);
+    let gps_offset = tiff.len() as u32;
+    let mut data_offset = gps_offset + 2 + 12 * gps.len() as u32 + 4;
+    let mut data = Vec::new();
+    writer.u16(&mut tiff, gps.len() as u16);
+    for field in &gps {
+        writer.u16(&mut tiff, field.tag);
+        writer.u16(&mut tiff, field.kind);
+        writer.u32(&mut tiff, field.count);
+        if field.data.len() <= 4 {
+            tiff.extend(&field.data);
+            tiff.extend(std::iter::repeat_n(0, 4 - field.data.len()));
+        } else {
+            writer.u32(&mut tiff, data_offset);
+            data.extend(&field.data);
+            if data.len() % 2 == 1 {
+                data.push(0);
+            }
+            data_offset = gps_offset + 2 + 12 * gps.len() as u32 + 4 + data.len() as u32;
+        }
+    }
+    writer.u32(&mut tiff, 0);
+    tiff.extend(data);
+
+    pad(&mut tiff);
+    let ifd0_offset = tiff.len() as u32;
+    let mut pointer = Vec::new();
+    writer.u16(&mut pointer, GPS_IFD_POINTER);
+    writer.u16(&mut pointer, LONG);
+    writer.u32(&mut pointer, 1);
+    writer.u32(&mut pointer, gps_offset);
+    let mut entries = ifd0.into_iter()
+        .filter(|entry| writer.read_u16(&entry[0..2]) != GPS_IFD_POINTER)
+        .collect::<Vec<_>>();
+    entries.push(pointer);
+    entries.sort_by_key(|entry| writer.read_u16(&entry[0..2]));
+    writer.u16(&mut tiff, entries.len() as u16);
+    for entry in entries {
+        tiff.extend(entry);
+    }
+    writer.u32(&mut tiff, next);
+
+    let mut header = Vec::new();
+    writer.u32(&mut header, ifd0_offset);
+    tiff[4..8].copy_from_slice(&header);
+    Ok(tiff)
+}
+
+fn pad(bytes: &mut Vec<u8>) {
+    if bytes.len() % 2 == 1 {
+        bytes.push(0);
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    big_endian: bool,
+}
+
+struct Writer {
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Result<Reader<'a>> {
+        let big_endian = match bytes.get(0..2) {
+            Some(b"MM") => true,
+            Some(b"II") => false,
+            _ => return Err(invalid("missing byte order mark")),
+        };
+        let reader = Reader {
+            bytes,
+            big_endian,
+        };
+        if reader.u16(2)? != 42 {
+            return Err(invalid("missing TIFF magic number"));
+        }
+        Ok(reader)
+    }
+
+    fn slice(&self, offset: usize, length: usize) -> Result<&'a [u8]> {
+        self.bytes
+            .get(offset..offset.saturating_add(length))
+            .ok_or_else(|| invalid("offset points outside of the TIFF data"))
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16> {
+        self.slice(offset, 2).map(|bytes| self.read_u16(bytes))
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32> {
+        self.slice(offset, 4).map(|bytes| self.read_u32(bytes))
+    }
+
+    fn read_u16(&self, bytes: &[u8]) -> u16 {
+        let (a, b) = (u16::from(bytes[0]), u16::from(bytes[1]));
+        if self.big_endian { a << 8 | b } else { b << 8 | a }
+    }
+
+    fn read_u32(&self, bytes: &[u8]) -> u32 {
+        let (a, b) = (u32::from(self.read_u16(&bytes[0..2])),
+                      u32::from(self.read_u16(&bytes[2..4])));
+        if self.big_endian { a << 16 | b } else { b << 16 | a }
+    }
+
+    fn ifd(&self, offset: usize) -> Result<Vec<Field>> {
+        let count = self.u16(offset)? as usize;
+        (0..count)
+            .map(|i| {
+                let entry = offset + 2 + i * 12;
+                let tag = self.u16(entry)?;
+                let kind = self.u16(entry + 2)?;
+                let count = self.u32(entry + 4)?;
+                let length = type_size(kind).saturating_mul(count as usize);
+                let data = if length <= 4 {
+                    self.slice(entry + 8, length)?
+                } else {
+                    self.slice(self.u32(entry + 8)? as usize, length)?
+                };
+                Ok(Field {
+                       tag,
+                       kind,
+                       count,
+                       data: data.to_vec(),
+                   })
+            })
+            .collect()
+    }
+
+    /// Returns the tag, data length, and value or offset of each entry in a directory.
+    fn entries(&self, offset: usize) -> Result<Vec<(u16, usize, u32)>> {
+        let count = self.u16(offset)? as usize;
+        (0..count)
+            .map(|i| {
+                let entry = offset + 2 + i * 12;
+                let length = type_size(self.u16(entry + 2)?)
+                    .saturating_mul(self.u32(entry + 4)? as usize);
+                Ok((self.u16(entry)?, length, self.u32(entry + 8)?))
+            })
+            .collect()
+    }
+
+    /// Returns the offset of the GPS directory that an earlier `set_gps` appended, if it and the
+    /// IFD0 copy after it are the last thing in the data, and nothing else points into them.
+    fn appended(&self) -> Result<Option<usize>> {
+        let ifd0 = self.u32(4)? as usize;
+        let entries = self.entries(ifd0)?;
+        let end = ifd0 + 2 + 12 * entries.len() + 4;
+        let gps = match entries.iter().find(|entry| entry.0 == GPS_IFD_POINTER) {
+            Some(&(_, _, offset)) => offset as usize,
+            None => return Ok(None),
+        };
+        let next = self.u32(end - 4)? as usize;
+        if end != self.bytes.len() || gps >= ifd0 || next >= gps {
+            return Ok(None);
+        }
+        let points_before = |&(tag, length, value): &(u16, usize, u32)| {
+            tag == GPS_IFD_POINTER || (length <= 4 && !POINTERS.contains(&tag)) ||
+            (value as usize) < gps
+        };
+        if !entries.iter().all(points_before) {
+            return Ok(None);
+        }
+        let gps_entries = self.entries(gps)?;
+        let mut gps_end = gps + 2 + 12 * gps_entries.len() + 4;
+        for &(_, length, offset) in gps_entries.iter().filter(|entry| entry.1 > 4) {
+            if (offset as usize) < gps {
+                return Ok(None);
+            }
+            gps_end = gps_end.max(offset as usize + length);
+        }
+        if gps_end + gps_end % 2 == ifd0 {
+            Ok(Some(gps))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn gps(&self) -> Result<Vec<Field>> {
+        let ifd0 = self.ifd(self.u32(4)? as usize)?;
+        match ifd0.iter().find(|field| field.tag == GPS_IFD_POINTER) {
+            Some(field) => self.ifd(self.read_u32(&field.data) as usize),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn value(&self, field: &Field) -> Option<Value> {
+        match field.kind {
+            BYTE => Some(Value::Byte(field.data.clone())),
+            ASCII => {
+                let end = field.data.iter().position(|&byte| byte == 0).unwrap_or(field.data.len());
+                Some(Value::Ascii(String::from_utf8_lossy(&field.data[..end]).into_owned()))
+            }
+            RATIONAL => {
+                Some(Value::Rational(field.data
+                                         .chunks(8)
+                                         .map(|chunk| {
+                                                  (self.read_u32(&chunk[0..4]),
+                                                   self.read_u32(&chunk[4..8]))
+                                              })
+                                         .collect()))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Writer {
+    fn read_u16(&self, bytes: &[u8]) -> u16 {
+        if self.big_endian {
+            u16::from_be_bytes([bytes[0], bytes[1]])
+        } else {
+            u16::from_le_bytes([bytes[0], bytes[1]])
+        }
+    }
+
+    fn u16(&self, bytes: &mut Vec<u8>, value: u16) {
+        if self.big_endian {
+            bytes.extend(&value.to_be_bytes());
+        } else {
+            bytes.extend(&value.to_le_bytes());
+        }
+    }
+
+    fn u32(&self, bytes: &mut Vec<u8>, value: u32) {
+        if self.big_endian {
+            bytes.extend(&value.to_be_bytes());
+        } else {
+            bytes.extend(&value.to_le_bytes());
+        }
+    }
+
+    fn field(&self, tag: u16, value: &Value) -> Field {
+        let mut data = Vec::new();
+        let (kind, count) = match *value {
+            Value::Byte(ref bytes) => {
+                data.extend(bytes);
+                (BYTE, bytes.len())
+            }
+            Value::Ascii(ref string) => {
+                data.extend(string.as_bytes());
+                data.push(0);
+                (ASCII, data.len())
+            }
+            Value::Rational(ref rationals) => {
+                for &(numerator, denominator) in rationals {
+                    self.u32(&mut data, numerator);
+                    self.u32(&mut data, denominator);
+                }
+                (RATIONAL, rationals.len())
+            }
+        };
+        Field {
+            tag,
+            kind,
+            count: count as u32,
+            data,
+        }
+    }
+}
+
+/// Returns the size, in bytes, of one value of the given TIFF type.
+fn type_size(kind: u16) -> usize {
+    match kind {
+        1 | 2 | 6 | 7 => 1,
+        3 | 8 => 2,
+        4 | 9 | 11 => 4,
+        5 | 10 | 12 => 8,
+        _ => 0,
+    }
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidExif(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jpeg::Jpeg;
+
+    fn sample() -> Vec<u8> {
+        let bytes = std::fs::read("data/images/DSC03522.JPG").unwrap();
+        Jpeg::parse(&bytes).unwrap().exif().unwrap().to_vec()
+    }
+
+    #[test]
+    fn read() {
+        check(&sample()).unwrap();
+        assert!(gps(&sample()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn invalid_offset() {
+        assert!(check(b"MM\x00\x2a\x00\x00\x10\x00").is_err());
+        assert!(check(b"XX\x00\x2a\x00\x00\x00\x08").is_err());
+    }
+
+    #[test]
+    fn write() {
+        let original = sample();
+        let fields = vec![(0, Value::Byte(vec![2, 3, 0, 0])),
+                          (1, Value::Ascii("N".to_string())),
+                          (2, Value::Rational(vec![(40, 1), (0, 1), (1234, 100)]))];
+        let tiff = set_gps(Some(&original), &fields).unwrap();
+        check(&tiff).unwrap();
+        assert_eq!(&original[8..], &tiff[8..original.len()]);
+        assert_eq!(fields, gps(&tiff).unwrap());
+
+        let length = tiff.len();
+        let tiff = set_gps(Some(&tiff), &[(1, Value::Ascii("S".to_string()))]).unwrap();
+        check(&tiff).unwrap();
+        assert_eq!(length, tiff.len());
+        assert_eq!(&original[8..], &tiff[8..original.len()]);
+        let gps = gps(&tiff).unwrap();
+        assert_eq!(3, gps.len());
+        assert_eq!((1, Value::Ascii("S".to_string())), gps[1]);
+    }
+
+    #[test]
+    fn write_again() {
+        let fields = vec![(2, Value::Rational(vec![(40, 1), (0, 1), (1234, 100)]))];
+        let once = set_gps(None, &fields).unwrap();
+        let twice = set_gps(Some(&once), &fields).unwrap();
+        assert_eq!(once, twice);
+
+        // Anything after the appended directories could point into them, so they are kept.
+        let mut trailing = once.clone();
+        trailing.extend(&[0, 0]);
+        let tiff = set_gps(Some(&trailing), &fields).unwrap();
+        assert_eq!(&trailing[8..], &tiff[8..trailing.len()]);
+        assert_eq!(fields, gps(&tiff).unwrap());
+    }
+
+    #[test]
+    fn write_new() {
+        let tiff = set_gps(None, &[(1, Value::Ascii("N".to_string()))]).unwrap();
+        assert_eq!(vec![(1, Value::Ascii("N".to_string()))], gps(&tiff).unwrap());
+    }
+
+    #[test]
+    fn little_endian() {
+        let tiff = b"II\x2a\x00\x08\x00\x00\x00\x00\x00\x00\x00\x00\x00";
+        let fields = vec![(2, Value::Rational(vec![(40, 1), (0, 1), (1234, 100)]))];
+        let tiff = set_gps(Some(tiff), &fields).unwrap();
+        assert_eq!(b"II", &tiff[0..2]);
+        assert_eq!(fields, gps(&tiff).unwrap());
+    }
+}
@@ -0,0 +1,452 @@
+//! Reference files for photogrammetry packages.
+//!
+//! Metashape and Pix4D import camera positions and orientations from delimited text files, and
+//! COLMAP can use an `images.txt` as pose priors. Each image's pose is interpolated from the
+//! trajectory.
+//!
+//! Orientations assume a nadir camera whose image top points toward the front of the platform.
+
+use {Image, Result};
+use std::f64::consts::PI;
+use std::io::Write;
+use trajectory::{Pose, Trajectory};
+
+/// The WGS84 semi-major axis, used for COLMAP's local coordinates.
+const EARTH_RADIUS: f64 = 6378137.;
+
+/// Configuration for writing reference files.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    /// The format of the reference file.
+    pub format: Format,
+    /// The order of the horizontal coordinates.
+    ///
+    /// Ignored for COLMAP, which uses local east, north, up coordinates.
+    pub order: Order,
+    /// The angle convention.
+    ///
+    /// Ignored for COLMAP, which uses quaternions.
+    pub angles: Angles,
+    /// Accuracy columns.
+    ///
+    /// If None, no accuracy columns are written. Ignored for COLMAP.
+    pub accuracy: Option<Accuracy>,
+    /// Whether to append the provenance columns, e.g. the source timestamp file and line.
+    ///
+    /// Only written for Metashape, since the Pix4D and COLMAP formats have fixed columns. See
+    /// `Image::provenance`.
+    pub provenance: bool,
+}
+
+/// A reference file format.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// A Metashape reference CSV, with a commented header.
+    Metashape,
+    /// A Pix4D image geolocation file.
+    Pix4d,
+    /// A COLMAP `images.txt`, positioned in meters east, north and up of the first image.
+    Colmap,
+}
+
+/// The order of the horizontal coordinates.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    /// Latitude, then longitude.
+    LatLon,
+    /// Longitude, then latitude.
+    LonLat,
+}
+
+/// An angle convention.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Angles {
+    /// Yaw, pitch and roll, as in the trajectory.
+    Ypr,
+    /// Omega, phi and kappa, in a local east, north, up frame.
+    Opk,
+}
+
+/// Values for the accuracy columns.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct Accuracy {
+    /// The horizontal accuracy, in meters.
+    pub horizontal: f64,
+    /// The vertical accuracy, in meters.
+    pub vertical: f64,
+    /// The angular accuracy, in degrees.
+    ///
+    /// Only written for Metashape, since Pix4D doesn't have a column for it.
+    pub angular: Option<f64>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            format: Format::Metashape,
+            order: Order::LatLon,
+            angles: Angles::Ypr,
+            accuracy: None,
+            provenance: false,
+        }
+    }
+}
+
+impl Config {
+    /// Creates a new, default configuration.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::export::Config;
+    /// let config = Config::new();
+    /// ```
+    pub fn new() -> Config {
+        Default::default()
+    }
+
+    /// Writes a reference file for the images.
+    ///
+    /// Images outside of the trajectory can't be positioned, so they are left out of the file
+    /// and returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::Image;
+    /// use riprocess::export::Config;
+    /// use riprocess::trajectory::{Pose, Trajectory};
+    /// let pose = Pose {
+    ///     time: 1.,
+    ///     latitude: 40.,
+    ///     longitude: -105.,
+    ///     height: 1600.,
+    ///     roll: 0.,
+    ///     pitch: 0.,
+    ///     yaw: 0.,
+    /// };
+    /// let trajectory = Trajectory::new(vec![pose, Pose { time: 2., ..pose }]).unwrap();
+    /// let image = Image { path: "DSC03522.JPG".into(), timestamp: 1.5, ..Default::default() };
+    /// let mut file = Vec::new();
+    /// let skipped = Config::new().write(&mut file, vec![image], &trajectory).unwrap();
+    /// assert!(skipped.is_empty());
+    /// ```
+    pub fn write<W, I>(&self,
+                       mut write: W,
+                       images: I,
+                       trajectory: &Trajectory)
+                       -> Result<Vec<Image>>
+        where W: Write,
+              I: IntoIterator<Item = Image>
+    {
+        let mut skipped = Vec::new();
+        let mut posed = Vec::new();
+        for image in images {
+            match trajectory.pose(image.timestamp) {
+                Some(pose) => posed.push((image, pose)),
+                None => skipped.push(image),
+            }
+        }
+        match self.format {
+            Format::Metashape => {
+                writeln!(write, "# {}", self.header().join(","))?;
+                for (image, pose) in &posed {
+                    writeln!(write, "{}", self.row(image, pose).join(","))?;
+                }
+            }
+            Format::Pix4d => {
+                for (image, pose) in &posed {
+                    writeln!(write, "{}", self.row(image, pose).join(","))?;
+                }
+            }
+            Format::Colmap => {
+                writeln!(write, "# Image list with two lines of data per image:")?;
+                writeln!(write, "#   IMAGE_ID, QW, QX, QY, QZ, TX, TY, TZ, CAMERA_ID, NAME")?;
+                writeln!(write, "#   POINTS2D[] as (X, Y, POINT3D_ID)")?;
+                let origin = posed.first().map(|&(_, pose)| pose);
+                for (i, (image, pose)) in posed.iter().enumerate() {
+                    let (q, t) = colmap(pose, origin.as_ref().unwrap_or(pose));
+                    writeln!(write,
+                             "{} {:.9} {:.9} {:.9} {:.9} {:.6} {:.6} {:.6} 1 {}",
+                             i + 1,
+                             q[0],
+                             q[1],
+                             q[2],
+                             q[3],
+                             t[0],
+                             t[1],
+                             t[2],
+                             label(image))?;
+                    writeln!(write)?;
+                }
+            }
+        }
+        Ok(skipped)
+    }
+
+    fn header(&self) -> Vec<&'static str> {
+        let mut header = vec!["label"];
+        header.extend(match self.order {
+                          Order::LatLon => ["latitude", "longitude"],
+                          Order::LonLat => ["longitude", "latitude"],
+                      }
+                      .iter());
+        header.push("altitude");
+        header.extend(match self.angles {
+                          Angles::Ypr => ["yaw", "pitch", "roll"],
+                          Angles::Opk => ["omega", "phi", "kappa"],
+                      }
+                      .iter());
+        if let Some(accuracy) = self.accuracy {
+            header.push("accuracy_horizontal");
+            header.push("accuracy_vertical");
+            if accuracy.angular.is_some() && self.format == Format::Metashape {
+                header.push("accuracy_angular");
+            }
+        }
+        if self.provenance && self.format == Format::Metashape {
+            header.extend(Image::PROVENANCE_COLUMNS.iter());
+        }
+        header
+    }
+
+    fn row(&self, image: &Image, pose: &Pose) -> Vec<String> {
+        let mut row = vec![label(image)];
+        let (latitude, longitude) = (format!("{:.9}", pose.latitude),
+                                     format!("{:.9}", pose.longitude));
+        match self.order {
+            Order::LatLon => row.extend(vec![latitude, longitude]),
+            Order::LonLat => row.extend(vec![longitude, latitude]),
+        }
+        row.push(format!("{:.3}", pose.height));
+        let angles = match self.angles {
+            Angles::Ypr => [pose.yaw, pose.pitch, pose.roll],
+            Angles::Opk => opk(&world_from_camera(pose)),
+        };
+        row.extend(angles.iter().map(|angle| format!("{:.6}", angle)));
+        if let Some(accuracy) = self.accuracy {
+            row.push(accuracy.horizontal.to_string());
+            row.push(accuracy.vertical.to_string());
+            match accuracy.angular {
+                Some(angular) if self.format == Format::Metashape => row.push(angular.to_string()),
+                _ => {}
+            }
+        }
+        if self.provenance && self.format == Format::Metashape {
+            row.extend(image.provenance());
+        }
+        row
+    }
+}
+
+fn label(image: &Image) -> String {
+    image.path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+}
+
+type Matrix = [[f64; 3]; 3];
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut c = [[0.; 3]; 3];
+    for (i, row) in c.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    c
+}
+
+fn transpose(a: &Matrix) -> Matrix {
+    let mut t = [[0.; 3]; 3];
+    for (i, row) in a.iter().enumerate() {
+        for (j, value) in row.iter().enumerate() {
+            t[j][i] = *value;
+        }
+    }
+    t
+}
+
+/// Returns the rotation from a computer vision camera frame (x right, y down, z forward) to a
+/// local east, north, up frame.
+fn world_from_camera(pose: &Pose) -> Matrix {
+    let (sr, cr) = pose.roll.to_radians().sin_cos();
+    let (sp, cp) = pose.pitch.to_radians().sin_cos();
+    let (sy, cy) = pose.yaw.to_radians().sin_cos();
+    let roll = [[1., 0., 0.], [0., cr, -sr], [0., sr, cr]];
+    let pitch = [[cp, 0., sp], [0., 1., 0.], [-sp, 0., cp]];
+    let yaw = [[cy, -sy, 0.], [sy, cy, 0.], [0., 0., 1.]];
+    let ned_from_body = multiply(&yaw, &multiply(&pitch, &roll));
+    let enu_from_ned = [[0., 1., 0.], [1., 0., 0.], [0., 0., -1.]];
+    let body_from_camera = [[0., -1., 0.], [1., 0., 0.], [0., 0., 1.]];
+    multiply(&enu_from_ned, &multiply(&ned_from_body, &body_from_camera))
+}
+
+/// Returns omega, phi and kappa, in degrees, for a photogrammetric camera frame (x right, y up,
+/// z backward).
+fn opk(world_from_camera: &Matrix) -> [f64; 3] {
+    let r = multiply(world_from_camera, &[[1., 0., 0.], [0., -1., 0.], [0., 0., -1.]]);
+    [(-r[1][2]).atan2(r[2][2]).to_degrees(),
+     r[0][2].clamp(-1., 1.).asin().to_degrees(),
+     (-r[0][1]).atan2(r[0][0]).to_degrees()]
+}
+
+/// Returns COLMAP's world-to-camera quaternion (w, x, y, z) and translation.
+fn colmap(pose: &Pose, origin: &Pose) -> ([f64; 4], [f64; 3]) {
+    let east = (pose.longitude - origin.longitude) * PI / 180. * EARTH_RADIUS *
+               origin.latitude.to_radians().cos();
+    let north = (pose.latitude - origin.latitude) * PI / 180. * EARTH_RADIUS;
+    let up = pose.height - origin.height;
+    let r = transpose(&world_from_camera(pose));
+    let t = [-(r[0][0] * east + r[0][1] * north + r[0][2] * up),
+             -(r[1][0] * east + r[1][1] * north + r[1][2] * up),
+             -(r[2][0] * east + r[2][1] * north + r[2][2] * up)];
+    (quaternion(&r), t)
+}
+
+fn quaternion(r: &Matrix) -> [f64; 4] {
+    let trace = r[0][0] + r[1][1] + r[2][2];
+    let q = if trace > 0. {
+        let s = (trace + 1.).sqrt() * 2.;
+        [s / 4., (r[2][1] - r[1][2]) / s, (r[0][2] - r[2][0]) / s, (r[1][0] - r[0][1]) / s]
+    } else if r[0][0] > r[1][1] && r[0][0] > r[2][2] {
+        let s = (1. + r[0][0] - r[1][1] - r[2][2]).sqrt() * 2.;
+        [(r[2][1] - r[1][2]) / s, s / 4., (r[0][1] + r[1][0]) / s, (r[0][2] + r[2][0]) / s]
+    } else if r[1][1] > r[2][2] {
+        let s = (1. + r[1][1] - r[0][0] - r[2][2]).sqrt() * 2.;
+        [(r[0][2] - r[2][0]) / s, (r[0][1] + r[1][0]) / s, s / 4., (r[1][2] + r[2][1]) / s]
+    } else {
+        let s = (1. + r[2][2] - r[0][0] - r[1][1]).sqrt() * 2.;
+        [(r[1][0] - r[0][1]) / s, (r[0][2] + r[2][0]) / s, (r[1][2] + r[2][1]) / s, s / 4.]
+    };
+    if q[0] < 0. { [-q[0], -q[1], -q[2], -q[3]] } else { q }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pose(yaw: f64) -> Pose {
+        Pose {
+            time: 1.,
+            latitude: 40.,
+            longitude: -105.,
+            height: 1600.,
+            roll: 0.,
+            pitch: 0.,
+            yaw,
+        }
+    }
+
+    fn trajectory() -> Trajectory {
+        Trajectory::new(vec![pose(90.), Pose { time: 3., longitude: -104.999, ..pose(90.) }])
+            .unwrap()
+    }
+
+    fn images() -> Vec<Image> {
+        vec![Image {
+                 path: "images/DSC03522.JPG".into(),
+                 timestamp: 1.,
+                 record: 0,
+                 ..Default::default()
+             },
+             Image {
+                 path: "images/DSC03523.JPG".into(),
+                 timestamp: 2.,
+                 record: 0,
+                 ..Default::default()
+             },
+             Image {
+                 path: "images/DSC03524.JPG".into(),
+                 timestamp: 4.,
+                 record: 0,
+                 ..Default::default()
+             }]
+    }
+
+    fn write(config: Config) -> (String, Vec<Image>) {
+        let mut file = Vec::new();
+        let skipped = config.write(&mut file, images(), &trajectory()).unwrap();
+        (String::from_utf8(file).unwrap(), skipped)
+    }
+
+    #[test]
+    fn metashape() {
+        let config = Config {
+            accuracy: Some(Accuracy {
+                               horizontal: 0.05,
+                               vertical: 0.1,
+                               angular: Some(0.5),
+                           }),
+            ..Default::default()
+        };
+        let (file, skipped) = write(config);
+        assert_eq!(1, skipped.len());
+        let lines = file.lines().collect::<Vec<_>>();
+        assert_eq!("# label,latitude,longitude,altitude,yaw,pitch,roll,accuracy_horizontal,\
+                    accuracy_vertical,accuracy_angular",
+                   lines[0]);
+        assert_eq!("DSC03522.JPG,40.000000000,-105.000000000,1600.000,90.000000,0.000000,\
+                    0.000000,0.05,0.1,0.5",
+                   lines[1]);
+        assert_eq!(3, lines.len());
+    }
+
+    #[test]
+    fn metashape_provenance() {
+        let (file, _) = write(Config { provenance: true, ..Default::default() });
+        let lines = file.lines().collect::<Vec<_>>();
+        assert!(lines[0].ends_with(",roll,image_number,timestamp_file,line,raw_timestamp,record,\
+                                    record_name,offset"),
+                "{}",
+                lines[0]);
+        assert!(lines[1].ends_with(",0,,0,0.000000,0,,0.000"), "{}", lines[1]);
+    }
+
+    #[test]
+    fn pix4d() {
+        let config = Config {
+            format: Format::Pix4d,
+            order: Order::LonLat,
+            angles: Angles::Opk,
+            accuracy: Some(Accuracy {
+                               horizontal: 0.05,
+                               vertical: 0.1,
+                               angular: Some(0.5),
+                           }),
+            provenance: true,
+        };
+        let (file, _) = write(config);
+        assert_eq!("DSC03522.JPG,-105.000000000,40.000000000,1600.000,0.000000,0.000000,\
+                    -90.000000,0.05,0.1",
+                   file.lines().next().unwrap());
+    }
+
+    #[test]
+    fn opk_north() {
+        let [omega, phi, kappa] = opk(&world_from_camera(&pose(0.)));
+        assert!(omega.abs() < 1e-9 && phi.abs() < 1e-9 && kappa.abs() < 1e-9);
+    }
+
+    #[test]
+    fn colmap() {
+        let config = Config { format: Format::Colmap, ..Default::default() };
+        let (file, _) = write(config);
+        let lines = file.lines().filter(|line| !line.starts_with('#')).collect::<Vec<_>>();
+        assert_eq!(4, lines.len());
+        assert_eq!("", lines[1]);
+        let values = lines[2].split(' ').collect::<Vec<_>>();
+        assert_eq!("2", values[0]);
+        assert_eq!("DSC03523.JPG", values[9]);
+        // The camera center is about 43 meters east of the first image.
+        let (q, t) = super::colmap(&trajectory().pose(2.).unwrap(), &pose(90.));
+        let r = transpose(&world_from_camera(&pose(90.)));
+        let center = (0..3).map(|i| -(r[0][i] * t[0] + r[1][i] * t[1] + r[2][i] * t[2]))
+            .collect::<Vec<_>>();
+        assert!((center[0] - 42.7).abs() < 0.1, "{:?}", center);
+        assert!(center[1].abs() < 1e-6 && center[2].abs() < 1e-6);
+        assert!(((q.iter().map(|q| q * q).sum::<f64>()) - 1.).abs() < 1e-9);
+    }
+}
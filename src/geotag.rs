@@ -0,0 +1,436 @@
+//! Write GPS time, and optionally pose, into image files.
+//!
+//! Each image gets EXIF `GPSTimeStamp` and `GPSDateStamp` fields, and an XMP property with the
+//! GPS seconds of the week. If a trajectory is available, the position is written to the EXIF
+//! GPS fields and the full pose is written to XMP.
+//!
+//! Only the EXIF and XMP segments are rewritten; the image data is copied byte-for-byte, so there
+//! is no recompression.
+
+use {Image, Result, exif, xmp};
+use exif::Value;
+use std::fmt;
+use std::path::Path;
+use trajectory::Pose;
+
+/// The number of days between the Unix epoch and the GPS epoch (January 6th, 1980).
+const GPS_EPOCH_DAYS: i64 = 3657;
+const SECONDS_PER_DAY: f64 = 86400.;
+
+/// Configuration for geotagging images.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    /// The GPS week of the timestamps.
+    ///
+    /// Timestamps are GPS seconds of the week, so the week is needed to write a date. If None,
+    /// `GPSDateStamp` is not written.
+    pub gps_week: Option<u32>,
+    /// The difference between GPS time and UTC, in seconds.
+    ///
+    /// EXIF GPS time stamps are in UTC. The default, 18 seconds, is correct since 2017.
+    pub leap_seconds: f64,
+    /// Write EXIF GPS fields.
+    pub exif: bool,
+    /// Write XMP properties.
+    pub xmp: bool,
+}
+
+/// A change to one value in an image's metadata.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    /// The name of the changed value, e.g. `GPSTimeStamp` or `riprocess:GpsSecondsOfWeek`.
+    pub name: String,
+    /// The old value, or None if the value wasn't present.
+    pub old: Option<String>,
+    /// The new value.
+    pub new: String,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            gps_week: None,
+            leap_seconds: 18.,
+            exif: true,
+            xmp: true,
+        }
+    }
+}
+
+impl Config {
+    /// Creates a new, default configuration.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::geotag::Config;
+    /// let config = Config::new();
+    /// ```
+    pub fn new() -> Config {
+        Default::default()
+    }
+
+    /// Writes the GPS time, and pose if provided, into an image file.
+    ///
+    /// Returns the changes to the image's metadata. If there are no changes, or if `dry_run` is
+    /// true, the file is not written.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use riprocess::Config;
+    /// use riprocess::geotag;
+    /// let config = Config::from_path("data/config.toml").unwrap();
+    /// let image = config.image_list().unwrap().next().unwrap();
+    /// let changes = geotag::Config::new().geotag(&image, None, true).unwrap();
+    /// assert!(changes.iter().any(|change| change.name == "GPSTimeStamp"));
+    /// ```
+    pub fn geotag(&self, image: &Image, pose: Option<&Pose>, dry_run: bool) -> Result<Vec<Change>> {
+        use jpeg::Jpeg;
+        use std::fs;
+
+        let bytes = fs::read(&image.path)?;
+        let jpeg = Jpeg::parse(&bytes)?;
+        let mut changes = Vec::new();
+        let exif = if self.exif {
+            let fields = self.exif_fields(image.timestamp, pose);
+            let old = match jpeg.exif() {
+                Some(tiff) => exif::gps(tiff)?,
+                None => Vec::new(),
+            };
+            for &(tag, ref value) in &fields {
+                let old = old.iter().find(|&&(t, _)| t == tag).map(|(_, value)| format(value));
+                let new = format(value);
+                if old.as_ref() != Some(&new) {
+                    changes.push(Change {
+                                     name: gps_tag_name(tag).to_string(),
+                                     old,
+                                     new,
+                                 });
+                }
+            }
+            // Only EXIF changes have been collected so far; without any, leave the EXIF alone.
+            if changes.is_empty() {
+                None
+            } else {
+                Some(exif::set_gps(jpeg.exif(), &fields)?)
+            }
+        } else {
+            None
+        };
+        let xmp = if self.xmp {
+            let properties = self.xmp_properties(image.timestamp, pose);
+            let old = jpeg.xmp().map(|xmp| String::from_utf8_lossy(xmp).into_owned());
+            for property in &properties {
+                let old = old.as_ref()
+                    .and_then(|old| xmp::attribute(old, property.namespace, property.name));
+                if old.as_ref() != Some(&property.value) {
+                    changes.push(Change {
+                                     name: format!("{}:{}",
+                                                   property.namespace.prefix,
+                                                   property.name),
+                                     old,
+                                     new: property.value.clone(),
+                                 });
+                }
+            }
+            let description = xmp::description(&properties);
+            Some(match old {
+                     Some(old) => xmp::merge(&old, &description, xmp::RIPROCESS),
+                     None => xmp::packet(&description),
+                 })
+        } else {
+            None
+        };
+        if !dry_run && !changes.is_empty() {
+            let bytes = jpeg.write(exif.as_deref(), xmp.as_ref().map(|xmp| xmp.as_bytes()))?;
+            replace(&image.path, &bytes)?;
+        }
+        Ok(changes)
+    }
+
+    fn exif_fields(&self, timestamp: f64, pose: Option<&Pose>) -> Vec<(u16, Value)> {
+        // Round to the millisecond first, so the seconds never round up to 60.
+        let (date, seconds_of_day) = self.utc((timestamp * 1000.).round() / 1000.);
+        let milliseconds = ((seconds_of_day * 1000.).round() as u32).min(86_399_999);
+        let mut fields = vec![(0x0000, Value::Byte(vec![2, 3, 0, 0])),
+                              (0x0007,
+                               Value::Rational(vec![(milliseconds / 3_600_000, 1),
+                                                    (milliseconds / 60_000 % 60, 1),
+                                                    (milliseconds % 60_000, 1000)]))];
+        if let Some((year, month, day)) = date {
+            fields.push((0x001d, Value::Ascii(format!("{:04}:{:02}:{:02}", year, month, day))));
+        }
+        if let Some(pose) = pose {
+            fields.push((0x0001,
+                         Value::Ascii(if pose.latitude < 0. { "S" } else { "N" }.to_string())));
+            fields.push((0x0002, Value::Rational(degrees(pose.latitude))));
+            fields.push((0x0003,
+                         Value::Ascii(if pose.longitude < 0. { "W" } else { "E" }.to_string())));
+            fields.push((0x0004, Value::Rational(degrees(pose.longitude))));
+            fields.push((0x0005, Value::Byte(vec![if pose.height < 0. { 1 } else { 0 }])));
+            fields.push((0x0006, Value::Rational(vec![rational(pose.height.abs(), 1000)])));
+            fields.push((0x0010, Value::Ascii("T".to_string())));
+            let direction = rational(pose.yaw.rem_euclid(360.), 100);
+            let direction = if direction.0 >= 36000 { (0, 100) } else { direction };
+            fields.push((0x0011, Value::Rational(vec![direction])));
+        }
+        fields.sort_by_key(|&(tag, _)| tag);
+        fields
+    }
+
+    fn xmp_properties(&self, timestamp: f64, pose: Option<&Pose>) -> Vec<xmp::Property> {
+        use xmp::{Property, RIPROCESS};
+
+        let mut properties = vec![Property::new(RIPROCESS,
+                                                "GpsSecondsOfWeek",
+                                                format!("{:.6}", timestamp))];
+        if let Some(gps_week) = self.gps_week {
+            properties.push(Property::new(RIPROCESS, "GpsWeek", gps_week));
+        }
+        if let Some(pose) = pose {
+            properties.push(Property::new(RIPROCESS, "Latitude", format!("{:.9}", pose.latitude)));
+            properties.push(Property::new(RIPROCESS,
+                                          "Longitude",
+                                          format!("{:.9}", pose.longitude)));
+            properties.push(Property::new(RIPROCESS, "Height", format!("{:.3}", pose.height)));
+            properties.push(Property::new(RIPROCESS, "Roll", format!("{:.4}", pose.roll)));
+            properties.push(Property::new(RIPROCESS, "Pitch", format!("{:.4}", pose.pitch)));
+            properties.push(Property::new(RIPROCESS, "Yaw", format!("{:.4}", pose.yaw)));
+        }
+        properties
+    }
+
+    /// Returns the UTC date, if the GPS week is known, and the UTC seconds of the day.
+    fn utc(&self, seconds_of_week: f64) -> (Option<(i64, u32, u32)>, f64) {
+        let utc = seconds_of_week - self.leap_seconds;
+        let day = (utc / SECONDS_PER_DAY).floor();
+        let seconds_of_day = utc - day * SECONDS_PER_DAY;
+        let date = self.gps_week
+            .map(|week| civil_from_days(GPS_EPOCH_DAYS + i64::from(week) * 7 + day as i64));
+        (date, seconds_of_day)
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{}: {} -> {}",
+               self.name,
+               self.old.as_deref().unwrap_or("(none)"),
+               self.new)
+    }
+}
+
+/// Converts days since the Unix epoch to a (year, month, day) civil date.
+///
+/// See http://howardhinnant.github.io/date_algorithms.html#civil_from_days.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn rational(value: f64, denominator: u32) -> (u32, u32) {
+    ((value * f64::from(denominator)).round() as u32, denominator)
+}
+
+/// Converts an angle to unsigned degrees, minutes, and seconds, with the seconds rounded to a
+/// ten-thousandth.
+///
+/// The rounding is done on the whole angle, so a rounded-up second carries into the minutes and
+/// degrees instead of becoming 60.
+fn degrees(value: f64) -> Vec<(u32, u32)> {
+    let total = (value.abs() * 3600. * 10000.).round() as u64;
+    let seconds = total % (60 * 10000);
+    let minutes = total / (60 * 10000) % 60;
+    let degrees = total / (3600 * 10000);
+    vec![(degrees as u32, 1), (minutes as u32, 1), (seconds as u32, 10000)]
+}
+
+fn format(value: &Value) -> String {
+    match *value {
+        Value::Byte(ref bytes) => {
+            bytes.iter().map(|byte| byte.to_string()).collect::<Vec<_>>().join(".")
+        }
+        Value::Ascii(ref string) => string.clone(),
+        Value::Rational(ref rationals) => {
+            rationals.iter()
+                .map(|&(numerator, denominator)| {
+                         (f64::from(numerator) / f64::from(denominator)).to_string()
+                     })
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+    }
+}
+
+fn gps_tag_name(tag: u16) -> &'static str {
+    match tag {
+        0x0000 => "GPSVersionID",
+        0x0001 => "GPSLatitudeRef",
+        0x0002 => "GPSLatitude",
+        0x0003 => "GPSLongitudeRef",
+        0x0004 => "GPSLongitude",
+        0x0005 => "GPSAltitudeRef",
+        0x0006 => "GPSAltitude",
+        0x0007 => "GPSTimeStamp",
+        0x0010 => "GPSImgDirectionRef",
+        0x0011 => "GPSImgDirection",
+        0x001d => "GPSDateStamp",
+        _ => "GPSUnknown",
+    }
+}
+
+/// Replaces a file's contents by writing a sibling file and renaming it over the original.
+fn replace(path: &Path, bytes: &[u8]) -> Result<()> {
+    use std::fs;
+
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".riprocess-tmp");
+    let temporary = path.with_file_name(file_name);
+    fs::write(&temporary, bytes)?;
+    fs::rename(&temporary, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jpeg::Jpeg;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn image(name: &str) -> Image {
+        let directory = std::env::temp_dir().join("riprocess-geotag");
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join(name);
+        fs::copy("data/images/DSC03522.JPG", &path).unwrap();
+        Image {
+            path,
+            timestamp: 332979.899441,
+            record: 0,
+            ..Default::default()
+        }
+    }
+
+    fn pose() -> Pose {
+        Pose {
+            time: 332979.899441,
+            latitude: 40.5,
+            longitude: -105.25,
+            height: 1600.,
+            roll: 1.,
+            pitch: 2.,
+            yaw: 90.,
+        }
+    }
+
+    fn read(path: &PathBuf) -> Vec<u8> {
+        fs::read(path).unwrap()
+    }
+
+    #[test]
+    fn utc() {
+        let config = Config { gps_week: Some(1954), ..Default::default() };
+        let (date, seconds_of_day) = config.utc(332979.899441);
+        assert_eq!(Some((2017, 6, 21)), date);
+        assert!((seconds_of_day - 73761.899441).abs() < 1e-6);
+        assert_eq!((1980, 1, 6), civil_from_days(GPS_EPOCH_DAYS));
+    }
+
+    #[test]
+    fn rationals() {
+        let config = Config::new();
+        let pose = Pose { yaw: -90., ..pose() };
+        let fields = config.exif_fields(332979.899441, Some(&pose));
+        assert!(fields.contains(&(0x0011, Value::Rational(vec![(27000, 100)]))));
+        let pose = Pose { yaw: -0.001, ..pose };
+        let fields = config.exif_fields(332979.899441, Some(&pose));
+        assert!(fields.contains(&(0x0011, Value::Rational(vec![(0, 100)]))));
+
+        assert_eq!(vec![(40, 1), (30, 1), (0, 10000)], degrees(40.49999999999));
+        assert_eq!(vec![(105, 1), (15, 1), (0, 10000)], degrees(-105.25));
+
+        let time = |fields: Vec<(u16, Value)>| {
+            fields.into_iter().find(|&(tag, _)| tag == 0x0007).map(|(_, value)| value)
+        };
+        assert_eq!(Some(Value::Rational(vec![(20, 1), (30, 1), (0, 1000)])),
+                   time(config.exif_fields(333017.9999996, None)));
+    }
+
+    #[test]
+    fn dry_run() {
+        let image = image("dry-run.jpg");
+        let before = read(&image.path);
+        let changes = Config::new().geotag(&image, Some(&pose()), true).unwrap();
+        assert_eq!(before, read(&image.path));
+        let change = changes.iter().find(|change| change.name == "GPSTimeStamp").unwrap();
+        assert_eq!(None, change.old);
+        assert_eq!("20, 29, 21.899", change.new);
+        fs::remove_file(&image.path).unwrap();
+    }
+
+    #[test]
+    fn write() {
+        let image = image("write.jpg");
+        let before = read(&image.path);
+        let config = Config { gps_week: Some(1954), ..Default::default() };
+        let changes = config.geotag(&image, Some(&pose()), false).unwrap();
+        assert!(changes.iter().any(|change| change.name == "GPSDateStamp"));
+
+        let after = read(&image.path);
+        let (before, after) = (Jpeg::parse(&before).unwrap(), Jpeg::parse(&after).unwrap());
+        assert_eq!(before.scan, after.scan);
+        let gps = exif::gps(after.exif().unwrap()).unwrap();
+        assert!(gps.contains(&(0x0003, Value::Ascii("W".to_string()))));
+        let xmp = String::from_utf8(after.xmp().unwrap().to_vec()).unwrap();
+        assert_eq!(Some("90.0000".to_string()), xmp::attribute(&xmp, xmp::RIPROCESS, "Yaw"));
+
+        assert!(config.geotag(&image, Some(&pose()), false).unwrap().is_empty());
+        let changes = config.geotag(&image, None, true).unwrap();
+        assert!(changes.is_empty(), "{:?}", changes);
+        fs::remove_file(&image.path).unwrap();
+    }
+
+    #[test]
+    fn rewrite() {
+        let image = image("rewrite.jpg");
+        let config = Config::new();
+        let exif = |image: &Image| {
+            Jpeg::parse(&read(&image.path)).unwrap().exif().unwrap().to_vec()
+        };
+        config.geotag(&image, Some(&pose()), false).unwrap();
+        let first = exif(&image);
+
+        let rolled = Pose { roll: 5., ..pose() };
+        let changes = config.geotag(&image, Some(&rolled), false).unwrap();
+        assert!(!changes.is_empty());
+        assert!(changes.iter().all(|change| change.name.starts_with("riprocess:")),
+                "{:?}",
+                changes);
+        assert_eq!(first, exif(&image));
+
+        let turned = Pose { yaw: 180., ..rolled };
+        assert!(!config.geotag(&image, Some(&turned), false).unwrap().is_empty());
+        assert_eq!(first.len(), exif(&image).len());
+        fs::remove_file(&image.path).unwrap();
+    }
+
+    #[test]
+    fn decodes() {
+        use jpeg_decoder::Decoder;
+        let image = image("decodes.jpg");
+        Config::new().geotag(&image, Some(&pose()), false).unwrap();
+        Decoder::new(read(&image.path).as_slice()).decode().unwrap();
+        fs::remove_file(&image.path).unwrap();
+    }
+}
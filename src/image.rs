@@ -4,16 +4,22 @@
 //! in an external folder.
 
 use Result;
+use cache::Cache;
+use listing::{Listing, Scan};
 use regex::Regex;
 use std::ffi::OsStr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 lazy_static! {
     static ref FILE_NAME_REGEX: Regex = Regex::new(r"^DSC(?P<image_number>\d{5}).JPG$").unwrap();
+    static ref NEAR_MISS_REGEX: Regex = Regex::new(r"(?i)^dsc\d+\.jpe?g$").unwrap();
 }
 
+/// The image file name pattern, as shown in warnings.
+const FILE_NAME_PATTERN: &str = "DSC#####.JPG";
+
 /// Configuration for a set of images.
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Config {
     /// The directory that holds the images.
     pub path: PathBuf,
@@ -25,6 +31,8 @@ pub struct Config {
     ///
     /// If none, the last image in the directory is used.
     pub end: Option<usize>,
+    /// The name of the camera that took the images, e.g. for use in renamed file names.
+    pub camera: Option<String>,
 }
 
 impl Config {
@@ -55,30 +63,51 @@ impl Config {
     /// assert!(config.paths().is_err());
     /// ```
     pub fn paths(&self) -> Result<Vec<PathBuf>> {
+        self.scan().map(|scan| scan.paths)
+    }
+
+    /// Returns the image paths for this configuration, along with the directory entries that
+    /// were skipped.
+    ///
+    /// Entries are skipped if they can't be read, if their names aren't valid UTF-8, or if their
+    /// names almost match the `DSC#####.JPG` pattern, e.g. `dsc03522.jpg` or `DSC3522.JPG`.
+    /// Errors are the same as for `paths`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::image::Config;
+    /// let config = Config { path: "data/images".into(), ..Default::default() };
+    /// let scan = config.scan().unwrap();
+    /// assert_eq!(7, scan.paths.len());
+    /// assert!(scan.skipped.is_empty());
+    /// ```
+    pub fn scan(&self) -> Result<Scan> {
+        self.scan_with(None)
+    }
+
+    /// Returns the image paths and skipped entries, listing the directory through a cache.
+    pub(crate) fn scan_with(&self, cache: Option<&Cache>) -> Result<Scan> {
         use Error;
-        use std::fs::DirEntry;
-        use std::io::Result;
 
+        let directory = self.path.canonicalize()?;
+        let listing = match cache {
+            Some(cache) => cache.listing(&directory)?,
+            None => Listing::read(&directory)?,
+        };
+        let skipped = listing.skipped(&directory,
+                                      FILE_NAME_PATTERN,
+                                      |file_name| FILE_NAME_REGEX.is_match(file_name),
+                                      |file_name| NEAR_MISS_REGEX.is_match(file_name));
         let mut image_numbers = Vec::new();
-        let mut paths: Vec<PathBuf>;
-        {
-            let select_paths = |result: Result<DirEntry>| match result {
-                Ok(dir_entry) => {
-                    if let Some(image_number) = extract_image_number(&dir_entry.file_name()) {
-                        image_numbers.push(image_number);
-                        if self.image_number_is_in_range(image_number) {
-                            return Some(dir_entry.path());
-                        }
-                    }
-                    None
+        let mut paths = Vec::new();
+        for file_name in listing.file_names {
+            if let Some(image_number) = extract_image_number(OsStr::new(&file_name)) {
+                image_numbers.push(image_number);
+                if self.image_number_is_in_range(image_number) {
+                    paths.push(directory.join(file_name));
                 }
-                Err(_) => None,
-            };
-            paths = self.path
-                .canonicalize()?
-                .read_dir()?
-                .filter_map(select_paths)
-                .collect();
+            }
         }
         if let Some(start) = self.start {
             if !image_numbers.contains(&start) {
@@ -91,7 +120,7 @@ impl Config {
             }
         }
         paths.sort();
-        Ok(paths)
+        Ok(Scan { paths, skipped })
     }
 
     fn image_number_is_in_range(&self, image_number: usize) -> bool {
@@ -100,6 +129,21 @@ impl Config {
     }
 }
 
+/// Returns the image number of the image at the provided path.
+///
+/// Returns None if the file name doesn't follow the `DSC#####.JPG` pattern.
+///
+/// # Examples
+///
+/// ```
+/// use riprocess::image;
+/// assert_eq!(Some(3522), image::image_number("data/images/DSC03522.JPG"));
+/// assert_eq!(None, image::image_number("data/config.toml"));
+/// ```
+pub fn image_number<P: AsRef<Path>>(path: P) -> Option<usize> {
+    path.as_ref().file_name().and_then(extract_image_number)
+}
+
 fn extract_image_number(file_name: &OsStr) -> Option<usize> {
     file_name.to_str()
         .and_then(|file_name| FILE_NAME_REGEX.captures(file_name))
@@ -134,6 +178,7 @@ mod tests {
             path: "data/images".into(),
             start: Some(3522),
             end: None,
+            ..Default::default()
         };
         assert_eq!(6, config.paths().unwrap().len());
     }
@@ -144,6 +189,7 @@ mod tests {
             path: "data/images".into(),
             start: None,
             end: Some(3522),
+            ..Default::default()
         };
         assert_eq!(2, config.paths().unwrap().len());
     }
@@ -154,6 +200,7 @@ mod tests {
             path: "data/images".into(),
             start: Some(3520),
             end: None,
+            ..Default::default()
         };
         assert!(config.paths().is_err());
     }
@@ -164,7 +211,44 @@ mod tests {
             path: "data/images".into(),
             start: None,
             end: Some(3428),
+            ..Default::default()
         };
         assert!(config.paths().is_err());
     }
+
+    #[test]
+    fn skipped() {
+        use listing::Reason;
+        use std::fs;
+
+        let directory = std::env::temp_dir().join("riprocess-image-skipped");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        for file_name in &["DSC03522.JPG", "dsc03523.jpg", "DSC3524.JPG", "notes.txt"] {
+            fs::write(directory.join(file_name), "").unwrap();
+        }
+        #[cfg(unix)]
+        {
+            use std::ffi::OsStr;
+            use std::os::unix::ffi::OsStrExt;
+            fs::write(directory.join(OsStr::from_bytes(b"DSC0352\xff.JPG")), "").unwrap();
+        }
+        let config = Config { path: directory.clone(), ..Default::default() };
+        let scan = config.scan().unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(1, scan.paths.len());
+        let mut reasons = scan.skipped
+            .iter()
+            .map(|skipped| {
+                     (skipped.path.file_name().unwrap().to_string_lossy().into_owned(),
+                      skipped.reason.clone())
+                 })
+            .collect::<Vec<_>>();
+        if cfg!(unix) {
+            assert_eq!(("DSC0352\u{fffd}.JPG".to_string(), Reason::NonUtf8), reasons.remove(0));
+        }
+        assert_eq!(vec![("DSC3524.JPG".to_string(), Reason::NearMiss(FILE_NAME_PATTERN)),
+                        ("dsc03523.jpg".to_string(), Reason::NearMiss(FILE_NAME_PATTERN))],
+                   reasons);
+    }
 }
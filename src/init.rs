@@ -0,0 +1,408 @@
+//! Generating a configuration from a project directory.
+//!
+//! Writing a configuration by hand means looking up image numbers, EIF file names, and record
+//! start stamps. `Project::scan` finds them in a RiPROCESS project tree (or any directory that
+//! holds images and timestamp files), and `render` writes a commented configuration file.
+
+use {Config, Result, image, migrate, record, timestamp};
+use record::Record;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::Value;
+
+/// The timestamp directory inside of a RiPROCESS project.
+pub const TIMESTAMP_DIRECTORY: &str = "04_CAM_RAW/01_EIF";
+
+/// The camera data wizard directory inside of a RiPROCESS project, which holds Records.csv.
+pub const WIZARD_DIRECTORY: &str = "90_CAMERA_DATA_WIZARD";
+
+/// How many directory levels below the project root to search.
+const MAX_DEPTH: usize = 3;
+
+/// What was found in a project directory.
+#[derive(Debug)]
+pub struct Project {
+    /// The project root.
+    pub root: PathBuf,
+    /// The directory that holds the images, if one was found.
+    pub images: Option<PathBuf>,
+    /// The image numbers in the image directory, sorted.
+    pub image_numbers: Vec<usize>,
+    /// The directory that holds the timestamp files, if one was found.
+    pub timestamps: Option<PathBuf>,
+    /// The timestamp files in the timestamp directory, sorted by name.
+    pub timestamp_files: Vec<timestamp::File>,
+    /// The most recent Records.csv written by the camera data wizard, if any.
+    pub records_csv: Option<PathBuf>,
+    /// The records in the Records.csv.
+    pub records: Vec<Record>,
+}
+
+impl Project {
+    /// Scans a project directory.
+    ///
+    /// The image and timestamp directories are looked for in their usual places in a RiPROCESS
+    /// project tree first, and then in any directory a few levels below the root.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::init::Project;
+    /// let project = Project::scan("data").unwrap();
+    /// assert_eq!(Some("data/images".into()), project.images);
+    /// assert_eq!(7, project.image_numbers.len());
+    /// assert_eq!(4, project.timestamp_files.len());
+    /// ```
+    pub fn scan<P: AsRef<Path>>(root: P) -> Result<Project> {
+        use organize::IMAGE_DIRECTORY;
+
+        let root = root.as_ref();
+        let images = conventional(root, IMAGE_DIRECTORY)
+            .or_else(|| find_directory(root, 0, &|path| image::image_number(path).is_some()));
+        let image_numbers = match images {
+            Some(ref images) => image_numbers(images)?,
+            None => Vec::new(),
+        };
+        let timestamps = conventional(root, TIMESTAMP_DIRECTORY).or_else(|| {
+            find_directory(root,
+                           0,
+                           &|path| path.extension().is_some_and(|extension| extension == "eif"))
+        });
+        let timestamp_files = match timestamps {
+            Some(ref timestamps) => timestamp_files(timestamps)?,
+            None => Vec::new(),
+        };
+        let records_csv = records_csv(root)?;
+        let records = match records_csv {
+            Some(ref csv) => record::Config { csv: Some(csv.clone()), ..Default::default() }
+                .records()?,
+            None => Vec::new(),
+        };
+        Ok(Project {
+               root: root.to_path_buf(),
+               images,
+               image_numbers,
+               timestamps,
+               timestamp_files,
+               records_csv,
+               records,
+           })
+    }
+
+    /// Returns a configuration that uses everything that was found.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::init::Project;
+    /// let config = Project::scan("data").unwrap().config();
+    /// assert_eq!(Some(3521), config.images.start);
+    /// assert_eq!(Some("170621_203217.eif".to_string()), config.timestamps.end);
+    /// ```
+    pub fn config(&self) -> Config {
+        let mut config = Config { version: Some(migrate::VERSION), ..Default::default() };
+        if let Some(ref images) = self.images {
+            config.images.path = images.clone();
+            config.images.start = self.image_numbers.first().cloned();
+            config.images.end = self.image_numbers.last().cloned();
+        }
+        if let Some(ref timestamps) = self.timestamps {
+            config.timestamps.path = timestamps.clone();
+            config.timestamps.start = self.timestamp_files.first().and_then(file_name);
+            config.timestamps.end = self.timestamp_files.last().and_then(file_name);
+        }
+        config.records.csv = self.records_csv.clone();
+        config
+    }
+
+    /// Returns the ranges of image numbers that are missing between the first and last image.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::init::Project;
+    /// let mut project = Project::scan("data").unwrap();
+    /// assert!(project.gaps().is_empty());
+    /// project.image_numbers.retain(|&number| number != 3523 && number != 3524);
+    /// assert_eq!(vec![(3523, 3524)], project.gaps());
+    /// ```
+    pub fn gaps(&self) -> Vec<(usize, usize)> {
+        gaps(&self.image_numbers)
+    }
+}
+
+/// Returns the sorted image numbers in a directory.
+pub fn image_numbers<P: AsRef<Path>>(directory: P) -> Result<Vec<usize>> {
+    let config = image::Config { path: directory.as_ref().to_path_buf(), ..Default::default() };
+    let mut numbers = config.paths()?.iter().filter_map(image::image_number).collect::<Vec<_>>();
+    numbers.sort_unstable();
+    Ok(numbers)
+}
+
+/// Returns the timestamp files in a directory, sorted by name.
+pub fn timestamp_files<P: AsRef<Path>>(directory: P) -> Result<Vec<timestamp::File>> {
+    timestamp::Config { path: directory.as_ref().to_path_buf(), ..Default::default() }.files()
+}
+
+/// Returns the ranges of numbers that are missing from a sorted list.
+///
+/// # Examples
+///
+/// ```
+/// use riprocess::init;
+/// assert_eq!(vec![(3, 4), (6, 6)], init::gaps(&[1, 2, 5, 7]));
+/// ```
+pub fn gaps(numbers: &[usize]) -> Vec<(usize, usize)> {
+    numbers.windows(2)
+        .filter(|pair| pair[1] > pair[0] + 1)
+        .map(|pair| (pair[0] + 1, pair[1] - 1))
+        .collect()
+}
+
+/// Writes a commented configuration file.
+///
+/// The file holds the version and the image, timestamp and record sections, along with comments
+/// that describe what was found in the project. Other sections are left out, so they use their
+/// defaults. `Config::from_path` reads the file back as the provided configuration.
+///
+/// # Examples
+///
+/// ```
+/// use riprocess::init::{self, Project};
+/// let project = Project::scan("data").unwrap();
+/// let toml = init::render(&project.config(), &project);
+/// assert!(toml.contains("start = 3521\n"));
+/// ```
+pub fn render(config: &Config, project: &Project) -> String {
+    let mut lines = vec![format!("# riprocess configuration for {}, written by `riprocess init`.",
+                                 project.root.display()),
+                         "#".to_string(),
+                         "# Check it with `riprocess check`. See the README for the other \
+                          sections."
+                             .to_string(),
+                         String::new()];
+    if let Some(version) = config.version {
+        lines.push(format!("version = {}", version));
+        lines.push(String::new());
+    }
+
+    lines.push("[images]".to_string());
+    match project.images {
+        Some(ref images) => {
+            lines.push(format!("# Found {} images in {}{}.",
+                               project.image_numbers.len(),
+                               images.display(),
+                               describe_numbers(&project.image_numbers)));
+            let gaps = project.gaps();
+            if !gaps.is_empty() {
+                lines.push(format!("# Missing image numbers: {}.", describe_gaps(&gaps)));
+            }
+        }
+        None => lines.push("# No images were found; set the image directory.".to_string()),
+    }
+    lines.push(format!("path = {}", path(&config.images.path)));
+    push_value(&mut lines, "start", config.images.start.map(|n| Value::Integer(n as i64)));
+    push_value(&mut lines, "end", config.images.end.map(|n| Value::Integer(n as i64)));
+    push_value(&mut lines, "camera", config.images.camera.clone().map(Value::String));
+    lines.push(String::new());
+
+    lines.push("[timestamps]".to_string());
+    match project.timestamps {
+        Some(ref timestamps) => {
+            lines.push(format!("# Found {} timestamp files in {}:",
+                               project.timestamp_files.len(),
+                               timestamps.display()));
+            for file in &project.timestamp_files {
+                lines.push(format!("#   {}", describe_file(file)));
+            }
+        }
+        None => lines.push("# No timestamp files were found; set the EIF directory.".to_string()),
+    }
+    lines.push(format!("path = {}", path(&config.timestamps.path)));
+    push_value(&mut lines, "start", config.timestamps.start.clone().map(Value::String));
+    push_value(&mut lines, "end", config.timestamps.end.clone().map(Value::String));
+    lines.push(String::new());
+
+    lines.push("[records]".to_string());
+    if let Some(ref csv) = project.records_csv {
+        lines.push(format!("# {} has {} records:", csv.display(), project.records.len()));
+        for record in &project.records {
+            lines.push(format!("#   {}", describe_record(record)));
+        }
+    }
+    if config.records.csv.is_none() && config.records.start_times.is_empty() {
+        lines.push("# Fill in the start time of each record, from the \"Start(stamp)\" column of"
+                       .to_string());
+        lines.push("# the camera data wizard's Records.csv.".to_string());
+    }
+    push_value(&mut lines, "csv", config.records.csv.as_ref().map(|csv| path(csv)));
+    if config.records.csv.is_none() || !config.records.start_times.is_empty() {
+        lines.push(format!("start_times = {}", floats(&config.records.start_times)));
+    }
+    if !config.records.end_times.is_empty() {
+        lines.push(format!("end_times = {}", floats(&config.records.end_times)));
+    }
+    if !config.records.durations.is_empty() {
+        lines.push(format!("durations = {}", floats(&config.records.durations)));
+    }
+    lines.join("\n") + "\n"
+}
+
+fn conventional(root: &Path, directory: &str) -> Option<PathBuf> {
+    let path = root.join(directory);
+    if path.is_dir() { Some(path) } else { None }
+}
+
+/// Finds the first directory, breadth first, that holds a file matching the predicate.
+fn find_directory(root: &Path, depth: usize, is_match: &dyn Fn(&Path) -> bool) -> Option<PathBuf> {
+    let mut directories = vec![root.to_path_buf()];
+    for _ in depth..MAX_DEPTH + 1 {
+        let mut children = Vec::new();
+        for directory in &directories {
+            let mut paths = match fs::read_dir(directory) {
+                Ok(read_dir) => {
+                    read_dir.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                        .collect::<Vec<_>>()
+                }
+                Err(_) => continue,
+            };
+            paths.sort();
+            if paths.iter().any(|path| path.is_file() && is_match(path)) {
+                return Some(directory.clone());
+            }
+            children.extend(paths.into_iter().filter(|path| {
+                path.is_dir() &&
+                !path.file_name()
+                     .and_then(|name| name.to_str())
+                     .is_some_and(|name| name.starts_with('.'))
+            }));
+        }
+        directories = children;
+    }
+    None
+}
+
+/// Returns the most recent Records.csv in the camera data wizard directory, or in the root.
+fn records_csv(root: &Path) -> Result<Option<PathBuf>> {
+    let wizard = root.join(WIZARD_DIRECTORY);
+    if wizard.is_dir() {
+        let mut runs = fs::read_dir(&wizard)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path().join("Records.csv")))
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>();
+        runs.sort();
+        if let Some(csv) = runs.pop() {
+            return Ok(Some(csv));
+        }
+    }
+    let csv = root.join("Records.csv");
+    Ok(if csv.is_file() { Some(csv) } else { None })
+}
+
+fn file_name(file: &timestamp::File) -> Option<String> {
+    file.path.file_name().and_then(|name| name.to_str()).map(String::from)
+}
+
+fn describe_numbers(numbers: &[usize]) -> String {
+    match (numbers.first(), numbers.last()) {
+        (Some(first), Some(last)) => format!(", DSC{:05} to DSC{:05}", first, last),
+        _ => String::new(),
+    }
+}
+
+fn describe_gaps(gaps: &[(usize, usize)]) -> String {
+    gaps.iter()
+        .map(|&(start, end)| if start == end {
+                 start.to_string()
+             } else {
+                 format!("{} to {}", start, end)
+             })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn describe_file(file: &timestamp::File) -> String {
+    let name = file_name(file).unwrap_or_default();
+    match (file.timestamps.first(), file.timestamps.last()) {
+        (Some(first), Some(last)) => {
+            format!("{}: {} events, {:.6} to {:.6}",
+                    name,
+                    file.timestamps.len(),
+                    first,
+                    last)
+        }
+        _ => format!("{}: no events", name),
+    }
+}
+
+fn describe_record(record: &Record) -> String {
+    let name = record.name.as_ref().map(|name| format!("{}: ", name)).unwrap_or_default();
+    match record.end {
+        Some(end) => format!("{}{:.3} to {:.3}", name, record.start, end),
+        None => format!("{}starts at {:.3}", name, record.start),
+    }
+}
+
+fn push_value(lines: &mut Vec<String>, key: &str, value: Option<Value>) {
+    if let Some(value) = value {
+        lines.push(format!("{} = {}", key, value));
+    }
+}
+
+fn path(path: &Path) -> Value {
+    Value::String(path.to_string_lossy().into_owned())
+}
+
+fn floats(values: &[f64]) -> Value {
+    Value::Array(values.iter().map(|&value| Value::Float(value)).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let project = Project::scan("data").unwrap();
+        let mut config = project.config();
+        config.images.start = Some(3522);
+        config.images.end = Some(3525);
+        config.timestamps.start = Some("170621_202939.eif".to_string());
+        config.timestamps.end = Some("170621_203040.eif".to_string());
+        config.records.start_times = vec![332978.669, 333039.279];
+        let path = std::env::temp_dir().join("riprocess-init.toml");
+        fs::write(&path, render(&config, &project)).unwrap();
+        let read = Config::from_path(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(config.to_toml().unwrap(), read.to_toml().unwrap());
+        assert_eq!(4, read.image_list().unwrap().count());
+    }
+
+    #[test]
+    fn project_tree() {
+        use organize::IMAGE_DIRECTORY;
+
+        let root = std::env::temp_dir().join("riprocess-init-project");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join(IMAGE_DIRECTORY)).unwrap();
+        fs::create_dir_all(root.join(TIMESTAMP_DIRECTORY)).unwrap();
+        fs::create_dir_all(root.join(WIZARD_DIRECTORY).join("20170621-210000")).unwrap();
+        fs::copy("data/images/DSC03522.JPG", root.join(IMAGE_DIRECTORY).join("DSC03522.JPG"))
+            .unwrap();
+        fs::copy("data/timestamps/170621_202939.eif",
+                 root.join(TIMESTAMP_DIRECTORY).join("170621_202939.eif"))
+            .unwrap();
+        fs::write(root.join(WIZARD_DIRECTORY).join("20170621-210000/Records.csv"),
+                  "Name;Start(stamp);End(stamp)\nRecord 1;332978.669;333008.669\n")
+            .unwrap();
+        let project = Project::scan(&root).unwrap();
+        assert_eq!(Some(root.join(IMAGE_DIRECTORY)), project.images);
+        assert_eq!(vec![3522], project.image_numbers);
+        assert_eq!(Some(root.join(TIMESTAMP_DIRECTORY)), project.timestamps);
+        assert_eq!(1, project.records.len());
+        let toml = render(&project.config(), &project);
+        assert!(toml.contains("#   Record 1: 332978.669 to 333008.669\n"), "{}", toml);
+        assert!(!toml.contains("start_times"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,236 @@
+//! Inspecting each input stream on its own.
+//!
+//! When images and timestamps don't pair up, it helps to look at the images, the timestamp files,
+//! and the records separately. These summaries are what the `list-images`, `list-timestamps`, and
+//! `list-records` commands print, and they serialize to JSON.
+
+use {Config, Result, image, init, record, timestamp};
+use listing::Skipped;
+use std::path::PathBuf;
+
+/// The configured images.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Images {
+    /// Each image, sorted by path.
+    pub images: Vec<ImageEntry>,
+    /// The ranges of image numbers, inclusive, that are missing between the first and last image.
+    pub gaps: Vec<(usize, usize)>,
+    /// The entries in the image directory that were skipped.
+    pub skipped: Vec<Skipped>,
+}
+
+/// The configured timestamp files.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Timestamps {
+    /// A summary of each timestamp file, sorted by path.
+    pub files: Vec<TimestampFile>,
+    /// The entries in the timestamp directory that were skipped.
+    pub skipped: Vec<Skipped>,
+}
+
+/// A single image file.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ImageEntry {
+    /// The image number, e.g. 3522 for `DSC03522.JPG`.
+    pub number: usize,
+    /// The path to the image.
+    pub path: PathBuf,
+}
+
+/// A summary of a single timestamp file.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TimestampFile {
+    /// The path to the timestamp file.
+    pub path: PathBuf,
+    /// The number of timestamps in the file.
+    pub count: usize,
+    /// The first timestamp, if there are any.
+    pub first: Option<f64>,
+    /// The last timestamp, if there are any.
+    pub last: Option<f64>,
+    /// Statistics on the intervals between consecutive timestamps.
+    ///
+    /// None if the file has fewer than two timestamps.
+    pub intervals: Option<Intervals>,
+}
+
+/// Statistics on the intervals between consecutive timestamps, in seconds.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Intervals {
+    /// The shortest interval.
+    pub min: f64,
+    /// The mean interval.
+    pub mean: f64,
+    /// The longest interval.
+    pub max: f64,
+}
+
+/// A record and the bases used to adjust its timestamps.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RecordEntry {
+    /// The record's position in the configuration, starting at zero.
+    pub index: usize,
+    /// The record's name, if known.
+    pub name: Option<String>,
+    /// The record's start time.
+    pub start: f64,
+    /// The record's end time, if known.
+    pub end: Option<f64>,
+    /// The record's start time, rounded down to the hundred seconds.
+    pub base: f64,
+    /// The timestamp file that is paired with this record, if there is one.
+    pub timestamp_file: Option<PathBuf>,
+    /// The first timestamp in the paired file, rounded down to the hundred seconds.
+    pub timestamp_base: Option<f64>,
+    /// The offset added to each of the paired file's timestamps, `base - timestamp_base`, or zero
+    /// if the timestamps are absolute.
+    pub offset: Option<f64>,
+}
+
+/// Lists the configured images, with their image numbers and any gaps.
+///
+/// # Examples
+///
+/// ```
+/// use riprocess::{Config, inspect};
+/// let config = Config::from_path("data/config.toml").unwrap();
+/// let images = inspect::images(&config.images).unwrap();
+/// assert_eq!(4, images.images.len());
+/// assert_eq!(3522, images.images[0].number);
+/// assert!(images.gaps.is_empty());
+/// ```
+pub fn images(config: &image::Config) -> Result<Images> {
+    let scan = config.scan()?;
+    let images = scan.paths
+        .into_iter()
+        .filter_map(|path| image::image_number(&path).map(|number| ImageEntry { number, path }))
+        .collect::<Vec<_>>();
+    let mut numbers = images.iter().map(|image| image.number).collect::<Vec<_>>();
+    numbers.sort_unstable();
+    Ok(Images {
+           gaps: init::gaps(&numbers),
+           images,
+           skipped: scan.skipped,
+       })
+}
+
+/// Summarizes each configured timestamp file.
+///
+/// # Examples
+///
+/// ```
+/// use riprocess::{Config, inspect};
+/// let config = Config::from_path("data/config.toml").unwrap();
+/// let timestamps = inspect::timestamps(&config.timestamps).unwrap();
+/// assert_eq!(2, timestamps.files.len());
+/// assert_eq!(2, timestamps.files[0].count);
+/// assert_eq!(Some(73779.899441), timestamps.files[0].first);
+/// ```
+pub fn timestamps(config: &timestamp::Config) -> Result<Timestamps> {
+    let (files, skipped) = config.scan_files_with(None, false)?;
+    Ok(Timestamps {
+           files: files.iter().map(summarize).collect(),
+           skipped,
+       })
+}
+
+/// Lists the configured records, with the bases used to adjust their timestamps.
+///
+/// Records are paired with timestamp files in order. If there are more records than files, the
+/// extra records have no timestamp base or offset.
+///
+/// # Examples
+///
+/// ```
+/// use riprocess::{Config, inspect};
+/// let config = Config::from_path("data/config.toml").unwrap();
+/// let records = inspect::records(&config).unwrap();
+/// assert_eq!(2, records.len());
+/// assert_eq!(332900., records[0].base);
+/// assert_eq!(Some(73700.), records[0].timestamp_base);
+/// assert_eq!(Some(332900. - 73700.), records[0].offset);
+/// ```
+pub fn records(config: &Config) -> Result<Vec<RecordEntry>> {
+    let files = config.timestamps.files()?;
+    let absolute = config.timestamps.source().is_absolute();
+    let mut entries = Vec::new();
+    for (index, record) in config.records(&files)?.into_iter().enumerate() {
+        let file = files.get(index);
+        let first = file.and_then(|file| file.timestamps.first()).cloned();
+        let offset = match first {
+            Some(_) if absolute => Some(0.),
+            first => first.map(|first| record.offset(first)),
+        };
+        entries.push(RecordEntry {
+                         index,
+                         name: record.name,
+                         start: record.start,
+                         end: record.end,
+                         base: record::base(record.start),
+                         timestamp_file: file.map(|file| file.path.clone()),
+                         timestamp_base: first.map(record::base),
+                         offset,
+                     });
+    }
+    Ok(entries)
+}
+
+fn summarize(file: &timestamp::File) -> TimestampFile {
+    let intervals = file.timestamps
+        .windows(2)
+        .map(|pair| pair[1] - pair[0])
+        .collect::<Vec<_>>();
+    TimestampFile {
+        path: file.path.clone(),
+        count: file.timestamps.len(),
+        first: file.timestamps.first().cloned(),
+        last: file.timestamps.last().cloned(),
+        intervals: if intervals.is_empty() {
+            None
+        } else {
+            Some(Intervals {
+                     min: intervals.iter().cloned().fold(f64::INFINITY, f64::min),
+                     mean: intervals.iter().sum::<f64>() / intervals.len() as f64,
+                     max: intervals.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+                 })
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intervals() {
+        let file = timestamp::File {
+            path: "a.eif".into(),
+            timestamps: vec![10., 11., 13., 14.],
+            ..Default::default()
+        };
+        let summary = summarize(&file);
+        assert_eq!(4, summary.count);
+        assert_eq!(Some(Intervals {
+                            min: 1.,
+                            mean: 4. / 3.,
+                            max: 2.,
+                        }),
+                   summary.intervals);
+        let file = timestamp::File {
+            path: "b.eif".into(),
+            timestamps: vec![10.],
+            ..Default::default()
+        };
+        assert_eq!(None, summarize(&file).intervals);
+    }
+
+    #[test]
+    fn extra_records() {
+        let mut config = Config::from_path("data/config.toml").unwrap();
+        config.records.start_times.push(333100.);
+        let records = records(&config).unwrap();
+        assert_eq!(3, records.len());
+        assert_eq!(333100., records[2].base);
+        assert_eq!(None, records[2].offset);
+    }
+}
@@ -0,0 +1,290 @@
+//! Image file integrity checks.
+//!
+//! Failed card copies leave behind zero-byte files, truncated JPEGs, and files that are named
+//! like images but aren't. These checks look at the contents of each image file so that such
+//! files don't end up in a RiPROCESS import.
+
+use {Error, exif, image};
+use cache::Cache;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Configuration for image integrity checks.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    /// The minimum size of an image file, in bytes.
+    pub min_size: u64,
+    /// Check that the file starts with a JPEG start of image marker and ends with an end of image
+    /// marker.
+    pub markers: bool,
+    /// Check that the file has a readable EXIF segment.
+    pub exif: bool,
+    /// Decode the entire image.
+    ///
+    /// This is the most thorough check, but it is also much slower than the others.
+    pub decode: bool,
+    /// What to do with images that fail a check.
+    pub on_failure: Action,
+}
+
+/// What to do with images that fail an integrity check.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Leave the image out of the image list.
+    ///
+    /// The image still consumes its timestamp, so the pairing of the other images is unchanged.
+    Exclude,
+    /// Don't create an image list.
+    Abort,
+}
+
+/// An image that failed one or more integrity checks.
+#[derive(Debug)]
+pub struct Failure {
+    /// The image number, if it can be read from the file name.
+    pub image_number: Option<usize>,
+    /// The path to the image.
+    pub path: PathBuf,
+    /// The problems with the image.
+    pub problems: Vec<Problem>,
+}
+
+/// A problem with an image file.
+#[derive(Debug)]
+pub enum Problem {
+    /// The file could not be read.
+    Unreadable(std::io::Error),
+    /// The file is smaller than the configured minimum size.
+    TooSmall(u64),
+    /// The file is not a valid JPEG, e.g. it doesn't start with a start of image marker.
+    InvalidJpeg(Error),
+    /// The file doesn't end with an end of image marker, which usually means it was truncated.
+    MissingEndOfImage,
+    /// The file doesn't have an EXIF segment.
+    MissingExif,
+    /// The file has an EXIF segment, but it can't be read.
+    InvalidExif(Error),
+    /// The image data could not be decoded.
+    Decode(String),
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            min_size: 1024,
+            markers: true,
+            exif: true,
+            decode: false,
+            on_failure: Action::Abort,
+        }
+    }
+}
+
+impl Config {
+    /// Creates a new, default configuration.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::integrity::Config;
+    /// let config = Config::new();
+    /// ```
+    pub fn new() -> Config {
+        Default::default()
+    }
+
+    /// Checks each image, returning a failure for each image that has problems.
+    ///
+    /// Images are checked in parallel. Failures are returned in the same order as the paths.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::{image, integrity};
+    /// let paths = image::Config { path: "data/images".into(), ..Default::default() }
+    ///     .paths()
+    ///     .unwrap();
+    /// let failures = integrity::Config::new().check_paths(&paths);
+    /// assert!(failures.is_empty());
+    /// ```
+    pub fn check_paths(&self, paths: &[PathBuf]) -> Vec<Failure> {
+        self.check_paths_with(paths, None)
+    }
+
+    /// Checks each image, skipping images that already passed the same checks according to the
+    /// cache.
+    pub(crate) fn check_paths_with(&self,
+                                   paths: &[PathBuf],
+                                   cache: Option<&Cache>)
+                                   -> Vec<Failure> {
+        use rayon::prelude::*;
+
+        let key = format!("{:?}", Config { on_failure: Action::Abort, ..*self });
+        paths.par_iter()
+            .filter_map(|path| {
+                let problems = match cache {
+                    Some(cache) => cache.integrity(path, &key, |path| self.check(path)),
+                    None => self.check(path),
+                };
+                if problems.is_empty() {
+                    None
+                } else {
+                    Some(Failure {
+                             image_number: image::image_number(path),
+                             path: path.clone(),
+                             problems,
+                         })
+                }
+            })
+            .collect()
+    }
+
+    /// Checks a single image, returning all of its problems.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::integrity::Config;
+    /// let problems = Config::new().check("data/images/DSC03522.JPG");
+    /// assert!(problems.is_empty());
+    /// let problems = Config::new().check("data/timestamps/170621_202939.eif");
+    /// assert!(!problems.is_empty());
+    /// ```
+    pub fn check<P: AsRef<Path>>(&self, path: P) -> Vec<Problem> {
+        use jpeg::Jpeg;
+        use std::fs::File;
+        use std::io::Read;
+
+        let mut bytes = Vec::new();
+        if let Err(err) = File::open(path).and_then(|mut file| file.read_to_end(&mut bytes)) {
+            return vec![Problem::Unreadable(err)];
+        }
+        let mut problems = Vec::new();
+        if (bytes.len() as u64) < self.min_size {
+            problems.push(Problem::TooSmall(bytes.len() as u64));
+        }
+        if self.markers || self.exif {
+            match Jpeg::parse(&bytes) {
+                Ok(jpeg) => {
+                    if self.markers && !jpeg.has_end_of_image() {
+                        problems.push(Problem::MissingEndOfImage);
+                    }
+                    if self.exif {
+                        match jpeg.exif() {
+                            Some(exif) => {
+                                if let Err(err) = exif::check(exif) {
+                                    problems.push(Problem::InvalidExif(err));
+                                }
+                            }
+                            None => problems.push(Problem::MissingExif),
+                        }
+                    }
+                }
+                Err(err) => problems.push(Problem::InvalidJpeg(err)),
+            }
+        }
+        if self.decode {
+            use jpeg_decoder::Decoder;
+            if let Err(err) = Decoder::new(bytes.as_slice()).decode() {
+                problems.push(Problem::Decode(err.to_string()));
+            }
+        }
+        problems
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.image_number {
+            Some(image_number) => write!(f, "image {} ({})", image_number, self.path.display())?,
+            None => write!(f, "{}", self.path.display())?,
+        }
+        for (i, problem) in self.problems.iter().enumerate() {
+            write!(f, "{} {}", if i == 0 { ":" } else { ";" }, problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Problem::Unreadable(ref err) => write!(f, "could not be read: {}", err),
+            Problem::TooSmall(size) => write!(f, "file is only {} bytes", size),
+            Problem::InvalidJpeg(ref err) => {
+                write!(f, "not a valid JPEG: {}", inner_message(err))
+            }
+            Problem::MissingEndOfImage => write!(f, "missing end of image marker (truncated?)"),
+            Problem::MissingExif => write!(f, "missing EXIF data"),
+            Problem::InvalidExif(ref err) => {
+                write!(f, "unreadable EXIF data: {}", inner_message(err))
+            }
+            Problem::Decode(ref message) => write!(f, "could not be decoded: {}", message),
+        }
+    }
+}
+
+/// Returns an error's message, without the prefix that repeats what the problem already says.
+fn inner_message(err: &Error) -> String {
+    match *err {
+        Error::InvalidJpeg(ref message) | Error::InvalidExif(ref message) => message.clone(),
+        ref err => err.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn write_temp(name: &str, bytes: &[u8]) -> PathBuf {
+        use std::io::Write;
+        let directory = std::env::temp_dir().join("riprocess-integrity");
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join(name);
+        fs::File::create(&path).unwrap().write_all(bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn valid() {
+        let config = Config { decode: true, ..Default::default() };
+        assert!(config.check("data/images/DSC03522.JPG").is_empty());
+    }
+
+    #[test]
+    fn empty() {
+        let path = write_temp("DSC00001.JPG", b"");
+        let problems = Config::new().check(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(problems[0], Problem::TooSmall(0)));
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = fs::read("data/images/DSC03522.JPG").unwrap();
+        let path = write_temp("DSC00002.JPG", &bytes[..bytes.len() - 1000]);
+        let config = Config { decode: true, ..Default::default() };
+        let failures = config.check_paths(std::slice::from_ref(&path));
+        fs::remove_file(&path).unwrap();
+        assert_eq!(1, failures.len());
+        assert_eq!(Some(2), failures[0].image_number);
+        assert!(failures[0]
+                    .problems
+                    .iter()
+                    .any(|problem| matches!(*problem, Problem::MissingEndOfImage)));
+    }
+
+    #[test]
+    fn not_a_jpeg() {
+        let path = write_temp("DSC00003.JPG", &[0; 2048]);
+        let problems = Config::new().check(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(1, problems.len());
+        let message = problems[0].to_string();
+        assert!(message.starts_with("not a valid JPEG: "), "{}", message);
+        assert!(!message.contains("InvalidJpeg"), "{}", message);
+    }
+}
@@ -0,0 +1,232 @@
+//! Minimal JPEG container parsing.
+//!
+//! We only need to find the marker segments in front of the image data, we never decode or
+//! re-encode the image data itself.
+
+use {Error, Result, xmp};
+
+/// Start of image.
+pub const SOI: u8 = 0xd8;
+/// End of image.
+pub const EOI: u8 = 0xd9;
+/// Start of scan.
+pub const SOS: u8 = 0xda;
+/// Application segment 0, used for JFIF.
+pub const APP0: u8 = 0xe0;
+/// Application segment 1, used for EXIF and XMP.
+pub const APP1: u8 = 0xe1;
+
+/// The identifier at the start of an EXIF APP1 segment.
+pub const EXIF_HEADER: &[u8] = b"Exif\0\0";
+
+/// The header segments of a JPEG file, along with everything from the first scan onwards.
+#[derive(Debug)]
+pub struct Jpeg<'a> {
+    /// The marker segments between the start of image and the first scan, in file order.
+    pub segments: Vec<Segment<'a>>,
+    /// Everything from the first start of scan marker to the end of the file.
+    pub scan: &'a [u8],
+}
+
+/// A JPEG marker segment.
+#[derive(Clone, Copy, Debug)]
+pub struct Segment<'a> {
+    /// The marker, without the leading `0xff`.
+    pub marker: u8,
+    /// The segment payload, without the marker and length bytes.
+    pub data: &'a [u8],
+}
+
+impl<'a> Jpeg<'a> {
+    /// Parses the header segments of a JPEG file.
+    pub fn parse(bytes: &'a [u8]) -> Result<Jpeg<'a>> {
+        if bytes.len() < 2 || bytes[0] != 0xff || bytes[1] != SOI {
+            return Err(Error::InvalidJpeg("missing start of image marker".to_string()));
+        }
+        let mut segments = Vec::new();
+        let mut position = 2;
+        loop {
+            if position >= bytes.len() || bytes[position] != 0xff {
+                return Err(Error::InvalidJpeg(format!("expected a marker at byte {}", position)));
+            }
+            while position < bytes.len() && bytes[position] == 0xff {
+                position += 1;
+            }
+            let marker = *bytes.get(position)
+                .ok_or_else(|| Error::InvalidJpeg("file ends inside a marker".to_string()))?;
+            position += 1;
+            if marker == SOS {
+                return Ok(Jpeg {
+                              segments,
+                              scan: &bytes[position - 2..],
+                          });
+            } else if marker == EOI {
+                return Err(Error::InvalidJpeg("end of image before any scan".to_string()));
+            } else if marker == 0x01 || (0xd0..=0xd7).contains(&marker) {
+                continue;
+            }
+            if position + 2 > bytes.len() {
+                return Err(Error::InvalidJpeg("file ends inside a segment length".to_string()));
+            }
+            let length = u16::from(bytes[position]) << 8 | u16::from(bytes[position + 1]);
+            let length = usize::from(length);
+            if length < 2 || position + length > bytes.len() {
+                return Err(Error::InvalidJpeg(format!("segment at byte {} is truncated",
+                                                      position - 2)));
+            }
+            segments.push(Segment {
+                              marker,
+                              data: &bytes[position + 2..position + length],
+                          });
+            position += length;
+        }
+    }
+
+    /// Returns true if the file ends with an end of image marker.
+    ///
+    /// Trailing zero bytes, which some cameras use as padding, are ignored.
+    pub fn has_end_of_image(&self) -> bool {
+        let end = self.scan.iter().rposition(|&byte| byte != 0).map(|i| i + 1).unwrap_or(0);
+        end >= 2 && self.scan[end - 2] == 0xff && self.scan[end - 1] == EOI
+    }
+
+    /// Returns the XMP packet, if there is one.
+    pub fn xmp(&self) -> Option<&'a [u8]> {
+        self.segments
+            .iter()
+            .find(|segment| segment.marker == APP1 && segment.data.starts_with(xmp::HEADER))
+            .map(|segment| &segment.data[xmp::HEADER.len()..])
+    }
+
+    /// Writes this JPEG with new EXIF and/or XMP segments.
+    ///
+    /// Existing EXIF and XMP segments are replaced in place. If there wasn't one before, the new
+    /// segment is inserted after any leading APP0 (JFIF) segments. All other segments and the
+    /// image data are copied unchanged.
+    pub fn write(&self, exif: Option<&[u8]>, xmp: Option<&[u8]>) -> Result<Vec<u8>> {
+        let exif = exif.map(|exif| [EXIF_HEADER, exif].concat());
+        let xmp = xmp.map(|packet| [xmp::HEADER, packet].concat());
+        let is_exif = |segment: &Segment| {
+            segment.marker == APP1 && segment.data.starts_with(EXIF_HEADER)
+        };
+        let is_xmp = |segment: &Segment| {
+            segment.marker == APP1 && segment.data.starts_with(xmp::HEADER)
+        };
+        let mut new = Vec::new();
+        if !self.segments.iter().any(is_exif) {
+            if let Some(ref exif) = exif {
+                new.push(Segment {
+                             marker: APP1,
+                             data: exif,
+                         });
+            }
+        }
+        if !self.segments.iter().any(is_xmp) {
+            if let Some(ref xmp) = xmp {
+                new.push(Segment {
+                             marker: APP1,
+                             data: xmp,
+                         });
+            }
+        }
+        let insert_at = self.segments
+            .iter()
+            .position(|segment| segment.marker != APP0)
+            .unwrap_or(self.segments.len());
+        let mut bytes = vec![0xff, SOI];
+        for (i, segment) in self.segments.iter().enumerate() {
+            if i == insert_at {
+                for segment in &new {
+                    write_segment(&mut bytes, segment)?;
+                }
+            }
+            let data = match (exif.as_ref(), xmp.as_ref()) {
+                (Some(exif), _) if is_exif(segment) => exif.as_slice(),
+                (_, Some(xmp)) if is_xmp(segment) => xmp.as_slice(),
+                _ => segment.data,
+            };
+            write_segment(&mut bytes,
+                          &Segment {
+                               marker: segment.marker,
+                               data,
+                           })?;
+        }
+        if insert_at == self.segments.len() {
+            for segment in &new {
+                write_segment(&mut bytes, segment)?;
+            }
+        }
+        bytes.extend(self.scan);
+        Ok(bytes)
+    }
+
+    /// Returns the TIFF payload of the EXIF segment, if there is one.
+    pub fn exif(&self) -> Option<&'a [u8]> {
+        self.segments
+            .iter()
+            .find(|segment| segment.marker == APP1 && segment.data.starts_with(EXIF_HEADER))
+            .map(|segment| &segment.data[EXIF_HEADER.len()..])
+    }
+}
+
+fn write_segment(bytes: &mut Vec<u8>, segment: &Segment) -> Result<()> {
+    let length = segment.data.len() + 2;
+    if length > usize::from(u16::MAX) {
+        return Err(Error::InvalidJpeg(format!("segment of {} bytes is too large for a JPEG \
+                                               marker segment",
+                                              length)));
+    }
+    bytes.extend(&[0xff, segment.marker]);
+    bytes.extend(&(length as u16).to_be_bytes());
+    bytes.extend(segment.data);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<u8> {
+        use std::fs::File;
+        use std::io::Read;
+        let mut bytes = Vec::new();
+        File::open("data/images/DSC03522.JPG").unwrap().read_to_end(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn parse() {
+        let bytes = sample();
+        let jpeg = Jpeg::parse(&bytes).unwrap();
+        assert!(jpeg.has_end_of_image());
+        assert!(jpeg.exif().is_some());
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = sample();
+        let jpeg = Jpeg::parse(&bytes[..bytes.len() / 2]).unwrap();
+        assert!(!jpeg.has_end_of_image());
+        assert!(Jpeg::parse(&bytes[..10]).is_err());
+    }
+
+    #[test]
+    fn write() {
+        let bytes = sample();
+        let jpeg = Jpeg::parse(&bytes).unwrap();
+        assert_eq!(bytes, jpeg.write(None, None).unwrap());
+
+        let written = jpeg.write(Some(b"MM"), Some(b"<xmp/>")).unwrap();
+        let new = Jpeg::parse(&written).unwrap();
+        assert_eq!(b"MM", new.exif().unwrap());
+        assert_eq!(b"<xmp/>", new.xmp().unwrap());
+        assert_eq!(APP0, new.segments[0].marker);
+        assert_eq!(jpeg.scan, new.scan);
+        assert_eq!(jpeg.segments.len() + 1, new.segments.len());
+    }
+
+    #[test]
+    fn not_a_jpeg() {
+        assert!(Jpeg::parse(b"not a jpeg").is_err());
+    }
+}
@@ -11,33 +11,94 @@
         trivial_numeric_casts, unsafe_code, unstable_features, unused_import_braces,
         unused_qualifications)]
 
+extern crate crc32fast;
+extern crate dirs;
+extern crate jpeg_decoder;
 #[macro_use]
 extern crate lazy_static;
+extern crate rayon;
 extern crate regex;
 #[macro_use]
+extern crate serde;
+#[macro_use]
 extern crate serde_derive;
+#[macro_use]
+extern crate serde_json;
+extern crate strsim;
 extern crate toml;
 
+pub mod cache;
 mod config;
+pub mod exclude;
+mod exif;
+pub mod export;
+pub mod geotag;
 pub mod image;
+pub mod init;
+pub mod inspect;
+pub mod integrity;
+mod jpeg;
+pub mod lint;
+pub mod listing;
+pub mod migrate;
+pub mod organize;
+pub mod overrides;
+pub mod profile;
 pub mod record;
+pub mod rename;
+pub mod report;
+pub mod schema;
+pub mod sidecar;
+pub mod thin;
 pub mod timestamp;
+pub mod trajectory;
+pub mod validate;
+pub mod watch;
+mod xmp;
 
-pub use config::{Config, Image, ImageList};
+pub use config::{Config, ConfigBuilder, Image, ImageList, Source};
 
 /// Our custom error enum.
 #[derive(Debug)]
 pub enum Error {
+    /// A file's checksum changed while it was being placed in the project tree.
+    ChecksumMismatch(std::path::PathBuf),
+    /// A different file already exists at a destination path.
+    DestinationExists(std::path::PathBuf),
+    /// One or more images failed their integrity checks, and the configured action is to abort.
+    Integrity(Vec<integrity::Failure>),
+    /// The configuration is invalid, e.g. because a required path is missing.
+    InvalidConfig(String),
+    /// The EXIF data in an image is invalid.
+    InvalidExif(String),
+    /// The path does not have a valid image file name.
+    InvalidImagePath(std::path::PathBuf),
     /// The image number, as provided in configuration, is invalid.
     ///
     /// Usually means that there wasn't a file with that image number.
     InvalidImageNumber(usize),
+    /// The image is not a valid JPEG file.
+    InvalidJpeg(String),
+    /// The record configuration is invalid, or the Records.csv can't be read.
+    InvalidRecords(String),
+    /// The rename log can't be read.
+    InvalidRenameLog(String),
+    /// The file name template is invalid.
+    InvalidTemplate(String),
+    /// A line in a timestamp file can't be read.
+    InvalidTimestamp(String),
     /// The timestamp filename, as provided in configuration, is not a valid file name.
     InvalidTimestampFileName(String),
+    /// The trajectory file can't be read.
+    InvalidTrajectory(String),
     /// Wrapper around `std::io::Error`.
     Io(std::io::Error),
+    /// The configuration file has unknown keys or values of the wrong type.
+    Lint(Vec<lint::Issue>),
     /// The are no images with the expected naming structure in the provided path.
     NoImages(std::path::PathBuf),
+    /// A trajectory is needed, but none is configured.
+    NoTrajectory,
     /// There are no timestamps in this timestamp record.
     NoTimestamps,
     /// Wrapper around `std::num::ParseFloatError`.
@@ -51,6 +112,8 @@ pub enum Error {
         /// The number of records.
         records: usize,
     },
+    /// Wrapper around `rayon::ThreadPoolBuildError`.
+    ThreadPool(rayon::ThreadPoolBuildError),
     /// The timestamp and image counts don't match.
     TimestampCountMismatch {
         /// The number of timestamps.
@@ -60,6 +123,12 @@ pub enum Error {
     },
     /// Wrapper around `toml::de::Error`.
     TomlDe(toml::de::Error),
+    /// Wrapper around `toml::ser::Error`.
+    TomlSer(toml::ser::Error),
+    /// The timestamps violate one or more validation rules at the error level.
+    Validation(Vec<validate::Issue>),
+    /// There were warnings in strict mode.
+    Warnings(Vec<report::Warning>),
 }
 
 /// Our custom result type.
@@ -83,8 +152,75 @@ impl From<std::num::ParseIntError> for Error {
     }
 }
 
+impl From<rayon::ThreadPoolBuildError> for Error {
+    fn from(err: rayon::ThreadPoolBuildError) -> Error {
+        Error::ThreadPool(err)
+    }
+}
+
 impl From<toml::de::Error> for Error {
     fn from(err: toml::de::Error) -> Error {
         Error::TomlDe(err)
     }
 }
+
+impl From<toml::ser::Error> for Error {
+    fn from(err: toml::ser::Error) -> Error {
+        Error::TomlSer(err)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        fn list<T>(f: &mut std::fmt::Formatter, items: &[T]) -> std::fmt::Result
+            where T: std::fmt::Display
+        {
+            for (i, item) in items.iter().enumerate() {
+                write!(f, "{}{}", if i == 0 { "" } else { "; " }, item)?;
+            }
+            Ok(())
+        }
+        match *self {
+            Error::ChecksumMismatch(ref path) => {
+                write!(f, "{} changed while it was being placed", path.display())
+            }
+            Error::DestinationExists(ref path) => {
+                write!(f, "a different file already exists at {}", path.display())
+            }
+            Error::Integrity(ref failures) => list(f, failures),
+            Error::InvalidConfig(ref message) => write!(f, "invalid configuration: {}", message),
+            Error::InvalidExif(ref message) => write!(f, "invalid EXIF data: {}", message),
+            Error::InvalidImagePath(ref path) => {
+                write!(f, "{} is not a DSC#####.JPG image", path.display())
+            }
+            Error::InvalidImageNumber(number) => write!(f, "there is no image {}", number),
+            Error::InvalidJpeg(ref message) => write!(f, "invalid JPEG: {}", message),
+            Error::InvalidRecords(ref message) => write!(f, "invalid records: {}", message),
+            Error::InvalidRenameLog(ref message) => write!(f, "invalid rename log: {}", message),
+            Error::InvalidTemplate(ref message) => write!(f, "invalid template: {}", message),
+            Error::InvalidTimestamp(ref message) => write!(f, "invalid timestamp: {}", message),
+            Error::InvalidTimestampFileName(ref name) => {
+                write!(f, "there is no timestamp file named {}", name)
+            }
+            Error::InvalidTrajectory(ref message) => write!(f, "invalid trajectory: {}", message),
+            Error::Io(ref err) => write!(f, "{}", err),
+            Error::Lint(ref issues) => list(f, issues),
+            Error::NoImages(ref path) => write!(f, "there are no images in {}", path.display()),
+            Error::NoTrajectory => write!(f, "a trajectory is needed, but none is configured"),
+            Error::NoTimestamps => write!(f, "a timestamp file has no timestamps"),
+            Error::ParseFloat(ref err) => write!(f, "invalid number: {}", err),
+            Error::ParseInt(ref err) => write!(f, "invalid integer: {}", err),
+            Error::RecordCountMismatch { timestamps, records } => {
+                write!(f, "there are {} timestamp files but {} records", timestamps, records)
+            }
+            Error::ThreadPool(ref err) => write!(f, "could not start the threads: {}", err),
+            Error::TimestampCountMismatch { timestamps, images } => {
+                write!(f, "there are {} timestamps but {} images", timestamps, images)
+            }
+            Error::TomlDe(ref err) => write!(f, "invalid TOML: {}", err),
+            Error::TomlSer(ref err) => write!(f, "could not write TOML: {}", err),
+            Error::Validation(ref issues) => list(f, issues),
+            Error::Warnings(ref warnings) => list(f, warnings),
+        }
+    }
+}
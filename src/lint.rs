@@ -0,0 +1,350 @@
+//! Configuration file linting.
+//!
+//! Every configuration section has defaults, so a typo like `[record]` or `strat = 3522` doesn't
+//! fail to parse; the misspelled value is silently ignored and the default is used instead. The
+//! linter compares a configuration file, merged with its profiles, against the configuration
+//! schema, and reports unknown keys (with suggestions), values of the wrong type, and sections
+//! that fell back to their defaults because their name was misspelled.
+
+use {Config, Result, profile};
+use schema::Schema;
+use std::fmt;
+use std::path::Path;
+use toml::Value;
+use toml::value::Table;
+use validate::Level;
+
+/// A problem with a configuration file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Issue {
+    /// `Error` if the configuration is wrong, `Warn` if it is only suspicious.
+    pub level: Level,
+    /// The dotted key of the offending value, e.g. `images.start`.
+    ///
+    /// Empty if the problem is with the file as a whole.
+    pub key: String,
+    /// The line and column of the offending value in the file, both starting at one.
+    ///
+    /// None if the value can't be found in the file, e.g. because it comes from a profile.
+    pub position: Option<(usize, usize)>,
+    /// A human-readable description of the problem.
+    pub message: String,
+}
+
+/// Checks a configuration file.
+///
+/// Returns an error only if the file, or a profile that it extends, can't be read.
+///
+/// # Examples
+///
+/// ```
+/// use riprocess::lint;
+/// use riprocess::validate::Level;
+/// let issues = lint::check("data/config.toml").unwrap();
+/// assert!(issues.is_empty());
+/// ```
+pub fn check<P: AsRef<Path>>(path: P) -> Result<Vec<Issue>> {
+    let path = path.as_ref();
+    let contents = std::fs::read_to_string(path)?;
+    if let Err(err) = contents.parse::<Value>() {
+        return Ok(vec![Issue {
+                           level: Level::Error,
+                           key: String::new(),
+                           position: err.line_col().map(|(line, column)| (line + 1, column + 1)),
+                           message: err.to_string(),
+                       }]);
+    }
+    let table = profile::load(path)?;
+    let mut issues = Vec::new();
+    check_table(&table, &Schema::config(), "", &mut issues);
+    if Value::Table(table.clone()).try_into::<Config>().is_ok() {
+        check_misspelled_sections(&table, &mut issues);
+    }
+    if issues.iter().any(|issue| issue.level == Level::Error) {
+        return Ok(locate_all(issues, &contents));
+    }
+    if let Err(err) = Value::Table(table.clone()).try_into::<Config>() {
+        issues.push(Issue {
+                        level: Level::Error,
+                        key: String::new(),
+                        position: None,
+                        message: err.to_string(),
+                    });
+        return Ok(locate_all(issues, &contents));
+    }
+    Ok(locate_all(issues, &contents))
+}
+
+/// Returns the known name that is closest to a misspelled one, if any is close enough.
+///
+/// # Examples
+///
+/// ```
+/// use riprocess::lint;
+/// assert_eq!(Some("start"), lint::suggest("strat", &["path", "start", "end"]));
+/// assert_eq!(None, lint::suggest("camera", &["path", "start", "end"]));
+/// ```
+pub fn suggest<'a>(name: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    candidates.iter()
+        .map(|&candidate| (strsim::damerau_levenshtein(name, candidate), candidate))
+        .filter(|&(distance, candidate)| distance <= std::cmp::max(candidate.len(), 3) / 3)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+fn check_table(table: &Table, schema: &Schema, prefix: &str, issues: &mut Vec<Issue>) {
+    let fields = match *schema {
+        Schema::Table(ref fields) => fields,
+        Schema::Optional(ref schema) => return check_table(table, schema, prefix, issues),
+        _ => return,
+    };
+    let names = fields.iter().map(|&(name, _)| name).collect::<Vec<_>>();
+    for (key, value) in table {
+        let full_key = join(prefix, key);
+        match fields.iter().find(|&&(name, _)| name == key) {
+            Some((_, schema)) => check_value(value, schema, &full_key, issues),
+            None => {
+                let section = if prefix.is_empty() {
+                    "at the top level".to_string()
+                } else {
+                    format!("in [{}]", prefix)
+                };
+                let mut message = format!("unknown key `{}` {}", key, section);
+                if let Some(suggestion) = suggest(key, &names) {
+                    message.push_str(&format!(", did you mean `{}`?", suggestion));
+                }
+                issues.push(error(full_key, message));
+            }
+        }
+    }
+}
+
+/// Warns about each section that isn't in the file but looks like it was meant to be, since an
+/// unknown key is spelled like it.
+///
+/// Leaving a section out to use its defaults is normal, so other missing sections are fine.
+fn check_misspelled_sections(table: &Table, issues: &mut Vec<Issue>) {
+    let fields = match Schema::config() {
+        Schema::Table(fields) => fields,
+        _ => return,
+    };
+    let names = fields.iter().map(|&(name, _)| name).collect::<Vec<_>>();
+    for key in table.keys().filter(|key| !names.contains(&key.as_str())) {
+        if let Some(name) = suggest(key, &names) {
+            let is_section = fields.iter().any(|&(field, ref schema)| match *schema {
+                                                   Schema::Table(_) => field == name,
+                                                   _ => false,
+                                               });
+            if is_section && !table.contains_key(name) {
+                issues.push(Issue {
+                                level: Level::Warn,
+                                key: key.to_string(),
+                                position: None,
+                                message: format!("there is no [{}] section, so the defaults \
+                                                  are used",
+                                                 name),
+                            });
+            }
+        }
+    }
+}
+
+fn check_value(value: &Value, schema: &Schema, key: &str, issues: &mut Vec<Issue>) {
+    let matches = match (schema, value) {
+        (Schema::Optional(schema), _) => return check_value(value, schema, key, issues),
+        (Schema::Table(_), Value::Table(table)) => {
+            check_table(table, schema, key, issues);
+            true
+        }
+        (Schema::Array(schema), Value::Array(values)) => {
+            for value in values {
+                check_value(value, schema, key, issues);
+            }
+            true
+        }
+        (Schema::Tuple(schemas), Value::Array(values)) if schemas.len() == values.len() => {
+            for (value, schema) in values.iter().zip(schemas) {
+                check_value(value, schema, key, issues);
+            }
+            true
+        }
+        (Schema::Enum(variants), Value::String(variant)) => {
+            if !variants.contains(&variant.as_str()) {
+                let mut message = format!("`{}` should be {}, not {:?}",
+                                          key,
+                                          schema.description(),
+                                          variant);
+                if let Some(suggestion) = suggest(variant, variants) {
+                    message.push_str(&format!(", did you mean {:?}?", suggestion));
+                }
+                issues.push(error(key.to_string(), message));
+            }
+            true
+        }
+        (Schema::Boolean, Value::Boolean(_)) |
+        (Schema::Integer, Value::Integer(_)) |
+        (Schema::Float, Value::Integer(_)) |
+        (Schema::Float, Value::Float(_)) |
+        (Schema::String, Value::String(_)) => true,
+        _ => false,
+    };
+    if !matches {
+        issues.push(error(key.to_string(),
+                          format!("`{}` should be {}, not {}",
+                                  key,
+                                  schema.description(),
+                                  value.type_str())));
+    }
+}
+
+fn error(key: String, message: String) -> Issue {
+    Issue {
+        level: Level::Error,
+        key,
+        position: None,
+        message,
+    }
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", prefix, key)
+    }
+}
+
+fn locate_all(issues: Vec<Issue>, contents: &str) -> Vec<Issue> {
+    issues.into_iter()
+        .map(|issue| {
+                 Issue {
+                     position: issue.position.or_else(|| locate(contents, &issue.key)),
+                     ..issue
+                 }
+             })
+        .collect()
+}
+
+/// Finds the line and column of a dotted key in a TOML file.
+///
+/// Falls back to the key's section, or the section's parent, if the key itself isn't written
+/// out, e.g. because it is inside of an inline table.
+fn locate(contents: &str, key: &str) -> Option<(usize, usize)> {
+    if key.is_empty() {
+        return None;
+    }
+    let mut section = String::new();
+    for (i, line) in contents.lines().enumerate() {
+        let trimmed = line.trim_start();
+        let column = line.len() - trimmed.len() + 1;
+        if trimmed.starts_with('[') {
+            section = trimmed.trim_start_matches('[')
+                .split(']')
+                .next()
+                .unwrap_or_default()
+                .split('.')
+                .map(|part| part.trim().trim_matches('"'))
+                .collect::<Vec<_>>()
+                .join(".");
+            if section == key {
+                return Some((i + 1, column));
+            }
+        } else if let Some(equals) = trimmed.find('=') {
+            let name = trimmed[..equals].trim().trim_matches('"');
+            if join(&section, name) == key {
+                return Some((i + 1, column));
+            }
+        }
+    }
+    key.rfind('.').and_then(|dot| locate(contents, &key[..dot]))
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.position {
+            Some((line, column)) => write!(f, "{}:{}: {}", line, column, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn check_str(name: &str, contents: &str) -> Vec<Issue> {
+        let directory = std::env::temp_dir().join("riprocess-lint");
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join(name);
+        fs::write(&path, contents).unwrap();
+        let issues = check(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        issues
+    }
+
+    fn sample() -> String {
+        fs::read_to_string("data/config.toml").unwrap()
+    }
+
+    fn config(replace: &str, with: &str) -> String {
+        let contents = sample();
+        assert!(contents.contains(replace));
+        contents.replace(replace, with)
+    }
+
+    #[test]
+    fn unknown_key() {
+        let issues = check_str("unknown-key.toml", &config("start = 3522", "strat = 3522"));
+        assert_eq!(1, issues.len());
+        assert_eq!("images.strat", issues[0].key);
+        assert!(issues[0].message.ends_with("did you mean `start`?"),
+                "{}",
+                issues[0].message);
+        assert_eq!(Some((3, 1)), issues[0].position);
+    }
+
+    #[test]
+    fn unknown_section() {
+        let issues = check_str("unknown-section.toml", &config("[records]", "[record]"));
+        assert_eq!("record", issues[0].key);
+        assert!(issues[0].message.contains("did you mean `records`?"));
+        assert!(issues[0].position.is_some());
+    }
+
+    #[test]
+    fn wrong_type() {
+        let issues = check_str("wrong-type.toml", &config("end = 3525", "end = \"3525\""));
+        assert_eq!(1, issues.len());
+        assert_eq!("`images.end` should be an integer, not string", issues[0].message);
+        assert_eq!(Some((4, 1)), issues[0].position);
+    }
+
+    #[test]
+    fn enum_value() {
+        let contents = sample() + "[organize]\nproject = \"p\"\nmethod = \"hardlnk\"\n";
+        let issues = check_str("enum-value.toml", &contents);
+        assert_eq!(1, issues.len());
+        assert!(issues[0].message.ends_with("did you mean \"hardlink\"?"));
+    }
+
+    #[test]
+    fn syntax_error() {
+        let issues = check_str("syntax-error.toml", "[images]\npath = \n");
+        assert_eq!(1, issues.len());
+        assert_eq!(Some(2), issues[0].position.map(|(line, _)| line));
+    }
+
+    #[test]
+    fn defaulted_sections() {
+        assert!(check_str("defaulted.toml", &sample()).is_empty());
+        let contents = sample() + "[validaton]\noutlier_factor = 5.0\n";
+        let issues = check_str("defaulted.toml", &contents);
+        assert_eq!(2, issues.len());
+        assert_eq!(Level::Warn, issues[1].level);
+        assert_eq!("validaton", issues[1].key);
+        assert_eq!("there is no [validation] section, so the defaults are used",
+                   issues[1].message);
+        assert!(issues[1].position.is_some());
+    }
+}
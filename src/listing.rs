@@ -0,0 +1,159 @@
+//! Directory listings, and the entries that were skipped while listing.
+//!
+//! Image and timestamp directories are matched against strict file name patterns. Entries that
+//! can't be read, names that aren't valid UTF-8, and names that almost match a pattern (e.g.
+//! `dsc03522.jpg`) are skipped, but they are reported so a permission problem or a renamed file
+//! doesn't silently shorten the image list.
+
+use Result;
+use std::ffi::OsString;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// A directory entry that was skipped.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Skipped {
+    /// The path to the entry, or to the directory if the entry couldn't be read.
+    pub path: PathBuf,
+    /// Why the entry was skipped.
+    pub reason: Reason,
+}
+
+/// Why a directory entry was skipped.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    /// The entry couldn't be read, e.g. because of a permission error.
+    Unreadable(String),
+    /// The file name isn't valid UTF-8.
+    NonUtf8,
+    /// The file name almost matches the pattern, which is provided, e.g. `DSC#####.JPG`.
+    NearMiss(&'static str),
+}
+
+/// The paths that were found in a directory, and the entries that were skipped.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Scan {
+    /// The matching paths, sorted.
+    pub paths: Vec<PathBuf>,
+    /// The skipped entries: unreadable entries first, then the rest sorted by path.
+    pub skipped: Vec<Skipped>,
+}
+
+/// A directory listing.
+#[derive(Debug, Default)]
+pub(crate) struct Listing {
+    /// The UTF-8 file names.
+    pub file_names: Vec<String>,
+    /// The error message for each entry that couldn't be read.
+    pub unreadable: Vec<String>,
+    /// The file names that aren't valid UTF-8, converted lossily.
+    pub non_utf8: Vec<String>,
+}
+
+impl Listing {
+    /// Lists a directory.
+    pub fn read(directory: &Path) -> Result<Listing> {
+        let mut listing = Listing::default();
+        for result in directory.read_dir()? {
+            match result.map(|entry| entry.file_name()).map(OsString::into_string) {
+                Ok(Ok(file_name)) => listing.file_names.push(file_name),
+                Ok(Err(file_name)) => {
+                    listing.non_utf8.push(file_name.to_string_lossy().into_owned())
+                }
+                Err(err) => listing.unreadable.push(err.to_string()),
+            }
+        }
+        Ok(listing)
+    }
+
+    /// Returns the skipped entries, treating file names for which `is_near_miss` returns true as
+    /// near misses of `pattern`.
+    ///
+    /// `is_near_miss` is only called for names that didn't match the pattern.
+    pub fn skipped<F, G>(&self,
+                         directory: &Path,
+                         pattern: &'static str,
+                         is_match: F,
+                         is_near_miss: G)
+                         -> Vec<Skipped>
+        where F: Fn(&str) -> bool,
+              G: Fn(&str) -> bool
+    {
+        let mut skipped = self.unreadable
+            .iter()
+            .map(|message| {
+                     Skipped {
+                         path: directory.to_path_buf(),
+                         reason: Reason::Unreadable(message.clone()),
+                     }
+                 })
+            .collect::<Vec<_>>();
+        let mut names = self.non_utf8
+            .iter()
+            .map(|name| (name, Reason::NonUtf8))
+            .chain(self.file_names
+                       .iter()
+                       .filter(|name| !is_match(name) && is_near_miss(name))
+                       .map(|name| (name, Reason::NearMiss(pattern))))
+            .map(|(name, reason)| {
+                     Skipped {
+                         path: directory.join(name),
+                         reason,
+                     }
+                 })
+            .collect::<Vec<_>>();
+        names.sort_by(|a, b| a.path.cmp(&b.path));
+        skipped.extend(names);
+        skipped
+    }
+}
+
+impl fmt::Display for Skipped {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.reason {
+            Reason::Unreadable(ref message) => {
+                write!(f,
+                       "skipped an entry in {} that could not be read: {}",
+                       self.path.display(),
+                       message)
+            }
+            Reason::NonUtf8 => {
+                write!(f,
+                       "skipped {}, its file name isn't valid UTF-8",
+                       self.path.display())
+            }
+            Reason::NearMiss(pattern) => {
+                write!(f,
+                       "skipped {}, its file name looks like {} but doesn't match exactly",
+                       self.path.display(),
+                       pattern)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skipped() {
+        let listing = Listing {
+            file_names: vec!["DSC03522.JPG".to_string(),
+                             "dsc03523.jpg".to_string(),
+                             "notes.txt".to_string()],
+            unreadable: vec!["permission denied".to_string()],
+            non_utf8: vec!["DSC0352\u{fffd}.JPG".to_string()],
+        };
+        let skipped = listing.skipped(Path::new("images"),
+                                      "DSC#####.JPG",
+                                      |name| name.starts_with("DSC"),
+                                      |name| name.to_lowercase().starts_with("dsc"));
+        assert_eq!(vec![Reason::Unreadable("permission denied".to_string()),
+                        Reason::NonUtf8,
+                        Reason::NearMiss("DSC#####.JPG")],
+                   skipped.iter().map(|skipped| skipped.reason.clone()).collect::<Vec<_>>());
+        assert_eq!(Path::new("images/dsc03523.jpg"), skipped[2].path);
+    }
+}
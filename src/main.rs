@@ -2,35 +2,689 @@ extern crate docopt;
 extern crate riprocess;
 #[macro_use]
 extern crate serde_derive;
+extern crate serde_json;
 
-use std::path::PathBuf;
+use riprocess::{Config, Image, ImageList};
+use riprocess::cache::Cache;
+use riprocess::overrides::Override;
+use riprocess::Error;
+use riprocess::report::Mode;
+use riprocess::watch::Update;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
-const USAGE: &'static str = "
+/// The exit status when a command fails.
+const EXIT_ERROR: i32 = 1;
+/// The exit status when a command succeeds, but with warnings or repairs.
+const EXIT_WARNINGS: i32 = 2;
+
+/// The number of warnings that have been printed.
+static WARNINGS: AtomicUsize = AtomicUsize::new(0);
+/// Whether the configuration is in strict mode, so printed warnings are errors.
+static STRICT: AtomicBool = AtomicBool::new(false);
+
+const USAGE: &str = "
 Query and/or generate material for RiPROCESS projects.
 
 Usage:
-    riprocess image-list <config>
+    riprocess image-list <config> [options] [--set=<override>]...
+    riprocess list-images <config> [options] [--set=<override>]...
+    riprocess list-timestamps <config> [options] [--set=<override>]...
+    riprocess list-records <config> [options] [--set=<override>]...
+    riprocess export <config> [options] [--set=<override>]...
+    riprocess geotag <config> [options] [--set=<override>]...
+    riprocess organize <config> [options] [--set=<override>]...
+    riprocess rename <config> [options] [--set=<override>]...
+    riprocess sidecars <config> [options] [--set=<override>]...
+    riprocess watch <config> [options] [--set=<override>]... [--interval=<seconds>]
+                    [--output=<path>]
+    riprocess init <project-root> [--yes] [--output=<path>]
+    riprocess undo-rename <log>
+    riprocess cache clear [<config>]
+    riprocess profiles
+    riprocess check <config>
+    riprocess config migrate <config> [--dry-run]
+    riprocess config schema
 
 Options:
-    -h --help           Show this screen.
+    -h --help                   Show this screen.
+    --dry-run                   Show what would be done, but don't touch any files.
+    --set=<override>            Override a configuration value, e.g. --set images.end=3600.
+    --print-config              Print the configuration, with all overrides applied, and exit.
+    --provenance                Add each image's source columns to image lists.
+    --json                      Print list-images, list-timestamps, or list-records as JSON.
+    --every=<n>                 Keep only every nth image.
+    --min-interval=<seconds>    Keep only images at least this many seconds apart.
+    --min-distance=<meters>     Keep only images at least this many meters apart.
+    --threads=<n>               The number of threads to scan and read files with.
+    --no-cache                  Scan every file, without reading or updating the scan cache.
+    --strict                    Treat warnings as errors.
+    --lenient                   Repair recoverable problems, with a warning, instead of failing.
+    --interval=<seconds>        How often to check for new files [default: 5].
+    --output=<path>             Where to write the image list whenever it is rebuilt, or where
+                                init writes the configuration.
+    --yes                       Use everything init finds, without asking.
 ";
 
 #[derive(Debug, Deserialize)]
 struct Args {
+    cmd_cache: bool,
+    cmd_check: bool,
+    cmd_clear: bool,
+    cmd_config: bool,
+    cmd_export: bool,
+    cmd_geotag: bool,
     cmd_image_list: bool,
+    cmd_init: bool,
+    cmd_list_images: bool,
+    cmd_list_records: bool,
+    cmd_list_timestamps: bool,
+    cmd_migrate: bool,
+    cmd_organize: bool,
+    cmd_profiles: bool,
+    cmd_rename: bool,
+    cmd_schema: bool,
+    cmd_sidecars: bool,
+    cmd_undo_rename: bool,
+    cmd_watch: bool,
     arg_config: PathBuf,
+    arg_log: PathBuf,
+    arg_project_root: PathBuf,
+    flag_dry_run: bool,
+    flag_set: Vec<String>,
+    flag_print_config: bool,
+    flag_json: bool,
+    flag_provenance: bool,
+    flag_every: Option<usize>,
+    flag_min_interval: Option<f64>,
+    flag_min_distance: Option<f64>,
+    flag_threads: Option<usize>,
+    flag_no_cache: bool,
+    flag_strict: bool,
+    flag_lenient: bool,
+    flag_interval: f64,
+    flag_output: Option<PathBuf>,
+    flag_yes: bool,
 }
 
 fn main() {
     use docopt::Docopt;
-    use riprocess::Config;
 
     let args: Args = Docopt::new(USAGE).and_then(|d| d.deserialize()).unwrap_or_else(|e| e.exit());
 
     if args.cmd_image_list {
-        let config = Config::from_path(args.arg_config).unwrap();
-        for image in config.image_list().unwrap() {
-            println!("{:.6};{}", image.timestamp, image.path.display());
+        let config = config(&args);
+        for image in image_list(&config, &args) {
+            println!("{}", line(&image, &args));
+        }
+    } else if args.cmd_list_images {
+        list_images(&config(&args), args.flag_json);
+    } else if args.cmd_list_timestamps {
+        list_timestamps(&config(&args), args.flag_json);
+    } else if args.cmd_list_records {
+        list_records(&config(&args), args.flag_json);
+    } else if args.cmd_export {
+        let config = config(&args);
+        let trajectory = config.trajectory
+            .as_ref()
+            .unwrap_or_else(|| {
+                                eprintln!("error: the configuration has no [trajectory] section");
+                                std::process::exit(EXIT_ERROR);
+                            })
+            .read()
+            .unwrap_or_else(|err| fail(err));
+        let stdout = std::io::stdout();
+        let skipped = config.export
+            .write(stdout.lock(), image_list(&config, &args), &trajectory)
+            .unwrap_or_else(|err| fail(err));
+        for image in skipped {
+            warn(format!("{} is outside of the trajectory, skipping", image.path.display()));
+        }
+    } else if args.cmd_geotag {
+        let config = config(&args);
+        let trajectory = config.trajectory
+            .as_ref()
+            .map(|trajectory| trajectory.read().unwrap_or_else(|err| fail(err)));
+        for image in image_list(&config, &args) {
+            let pose = trajectory.as_ref().and_then(|trajectory| trajectory.pose(image.timestamp));
+            if trajectory.is_some() && pose.is_none() {
+                warn(format!("{} is outside of the trajectory", image.path.display()));
+            }
+            let changes = config.geotag
+                .geotag(&image, pose.as_ref(), args.flag_dry_run)
+                .unwrap_or_else(|err| fail(err));
+            if changes.is_empty() {
+                eprintln!("{} is already up to date", image.path.display());
+            } else {
+                println!("{}{}",
+                         if args.flag_dry_run { "would update " } else { "updated " },
+                         image.path.display());
+                for change in changes {
+                    println!("    {}", change);
+                }
+            }
+        }
+    } else if args.cmd_organize {
+        let config = config(&args);
+        let organize = config.organize.as_ref().unwrap_or_else(|| {
+            eprintln!("error: the configuration has no [organize] section");
+            std::process::exit(EXIT_ERROR);
+        });
+        let operations = organize.organize(image_list(&config, &args), args.flag_dry_run)
+            .unwrap_or_else(|err| fail(err));
+        for operation in operations {
+            if operation.skipped {
+                eprintln!("{} is already in place", operation.image.path.display());
+            } else {
+                eprintln!("{}{} {} -> {}",
+                          if args.flag_dry_run { "would " } else { "" },
+                          organize.method,
+                          operation.source.display(),
+                          operation.image.path.display());
+            }
+            println!("{}", line(&operation.image, &args));
+        }
+    } else if args.cmd_rename {
+        let config = config(&args);
+        let renames = config.rename
+            .rename(image_list(&config, &args),
+                    config.images.camera.as_deref(),
+                    args.flag_dry_run)
+            .unwrap_or_else(|err| fail(err));
+        for rename in renames {
+            eprintln!("{} {} -> {}",
+                      if args.flag_dry_run { "would rename" } else { "renamed" },
+                      rename.source.display(),
+                      rename.image.path.display());
+            println!("{}", line(&rename.image, &args));
+        }
+        if !args.flag_dry_run {
+            eprintln!("rename log written to {}", config.rename.log.display());
+        }
+    } else if args.cmd_sidecars {
+        let config = config(&args);
+        let trajectory = config.trajectory
+            .as_ref()
+            .map(|trajectory| trajectory.read().unwrap_or_else(|err| fail(err)));
+        let sidecars = config.sidecar
+            .write(image_list(&config, &args),
+                   config.images.camera.as_deref(),
+                   trajectory.as_ref(),
+                   args.flag_dry_run)
+            .unwrap_or_else(|err| fail(err));
+        for sidecar in sidecars {
+            if trajectory.is_some() && sidecar.pose.is_none() {
+                warn(format!("{} is outside of the trajectory", sidecar.image.path.display()));
+            }
+            println!("{}{}",
+                     if args.flag_dry_run { "would write " } else { "wrote " },
+                     sidecar.path.display());
+        }
+    } else if args.cmd_watch {
+        watch(&config(&args), &args);
+    } else if args.cmd_cache && args.cmd_clear {
+        match cache_path(&args) {
+            Some(path) => {
+                Cache::clear(&path).unwrap_or_else(|err| fail(err));
+                eprintln!("cleared {}", path.display());
+            }
+            None => eprintln!("there is no cache directory on this platform"),
+        }
+    } else if args.cmd_check {
+        check(&args.arg_config);
+    } else if args.cmd_config && args.cmd_migrate {
+        migrate(&args.arg_config, args.flag_dry_run);
+    } else if args.cmd_config && args.cmd_schema {
+        let json = riprocess::schema::Schema::config_json();
+        let json = serde_json::to_string_pretty(&json).expect("the schema should always serialize");
+        println!("{}", json);
+    } else if args.cmd_profiles {
+        let profiles = riprocess::profile::list().unwrap_or_else(|err| fail(err));
+        match riprocess::profile::directory() {
+            Some(ref directory) if profiles.is_empty() => {
+                eprintln!("there are no profiles in {}", directory.display())
+            }
+            Some(_) => {}
+            None => eprintln!("there is no configuration directory on this platform"),
+        }
+        for profile in profiles {
+            println!("{}\t{}", profile.name, profile.path.display());
+        }
+    } else if args.cmd_init {
+        init(&args);
+    } else if args.cmd_undo_rename {
+        let count = riprocess::rename::undo(args.arg_log).unwrap_or_else(|err| fail(err));
+        eprintln!("undid {} renames", count);
+    }
+    let warnings = WARNINGS.load(Ordering::SeqCst);
+    if warnings > 0 && STRICT.load(Ordering::SeqCst) {
+        eprintln!("error: there were {} warnings, and --strict makes them errors", warnings);
+        std::process::exit(EXIT_ERROR);
+    } else if warnings > 0 {
+        std::process::exit(EXIT_WARNINGS);
+    }
+}
+
+fn config(args: &Args) -> Config {
+    let mut config = Config::from_path_with(&args.arg_config, &overrides(args))
+        .unwrap_or_else(|err| fail(err));
+    if args.flag_every.is_some() {
+        config.thin.every = args.flag_every;
+    }
+    if args.flag_min_interval.is_some() {
+        config.thin.min_interval = args.flag_min_interval;
+    }
+    if args.flag_min_distance.is_some() {
+        config.thin.min_distance = args.flag_min_distance;
+    }
+    if args.flag_threads.is_some() {
+        config.threads = args.flag_threads;
+    }
+    if args.flag_strict && args.flag_lenient {
+        eprintln!("error: --strict and --lenient can't be used together");
+        std::process::exit(EXIT_ERROR);
+    } else if args.flag_strict {
+        config.mode = Mode::Strict;
+    } else if args.flag_lenient {
+        config.mode = Mode::Lenient;
+    }
+    STRICT.store(config.mode == Mode::Strict, Ordering::SeqCst);
+    if args.flag_print_config {
+        print!("{}", config.to_toml().unwrap_or_else(|err| fail(err)));
+        std::process::exit(0);
+    }
+    config
+}
+
+/// Returns the environment overrides, followed by the `--set` overrides, so the latter win.
+fn overrides(args: &Args) -> Vec<Override> {
+    let mut overrides = riprocess::overrides::from_env();
+    for set in &args.flag_set {
+        overrides.push(Override::parse(set).unwrap_or_else(|err| fail(err)));
+    }
+    overrides
+}
+
+fn list_images(config: &Config, json: bool) {
+    let images = riprocess::inspect::images(&config.images).unwrap_or_else(|err| fail(err));
+    if json {
+        let json = serde_json::to_string_pretty(&images)
+            .expect("the image summary should always serialize");
+        println!("{}", json);
+        return;
+    }
+    for image in &images.images {
+        println!("{}\t{}", image.number, image.path.display());
+    }
+    for skipped in &images.skipped {
+        warn(skipped);
+    }
+    for &(start, end) in &images.gaps {
+        if start == end {
+            eprintln!("gap: image {} is missing", start);
+        } else {
+            eprintln!("gap: images {} to {} are missing", start, end);
+        }
+    }
+    eprintln!("{} images", images.images.len());
+}
+
+fn list_timestamps(config: &Config, json: bool) {
+    let timestamps = riprocess::inspect::timestamps(&config.timestamps)
+        .unwrap_or_else(|err| fail(err));
+    if json {
+        let json = serde_json::to_string_pretty(&timestamps)
+            .expect("the timestamp summary should always serialize");
+        println!("{}", json);
+        return;
+    }
+    let files = &timestamps.files;
+    for file in files {
+        print!("{}: {} timestamps", file.path.display(), file.count);
+        if let (Some(first), Some(last)) = (file.first, file.last) {
+            print!(", {:.6} to {:.6}", first, last);
+        }
+        if let Some(intervals) = file.intervals {
+            print!(", intervals {:.3}/{:.3}/{:.3} s (min/mean/max)",
+                   intervals.min,
+                   intervals.mean,
+                   intervals.max);
+        }
+        println!();
+    }
+    for skipped in &timestamps.skipped {
+        warn(skipped);
+    }
+    eprintln!("{} files, {} timestamps",
+              files.len(),
+              files.iter().map(|file| file.count).sum::<usize>());
+}
+
+fn list_records(config: &Config, json: bool) {
+    let records = riprocess::inspect::records(config).unwrap_or_else(|err| fail(err));
+    if json {
+        let json = serde_json::to_string_pretty(&records)
+            .expect("the record summary should always serialize");
+        println!("{}", json);
+        return;
+    }
+    for record in &records {
+        print!("{}", record.index + 1);
+        if let Some(ref name) = record.name {
+            print!(" ({})", name);
+        }
+        print!(": start {:.6}", record.start);
+        if let Some(end) = record.end {
+            print!(", end {:.6}", end);
+        }
+        print!(", base {:.0}", record.base);
+        match (&record.timestamp_file, record.timestamp_base, record.offset) {
+            (Some(file), Some(timestamp_base), Some(offset)) => {
+                println!(", {} base {:.0}, offset {:.0}",
+                         file.display(),
+                         timestamp_base,
+                         offset)
+            }
+            (Some(file), _, _) => println!(", {} has no timestamps", file.display()),
+            _ => println!(", no timestamp file"),
+        }
+    }
+    eprintln!("{} records", records.len());
+}
+
+fn check(path: &Path) {
+    use riprocess::validate::Level;
+
+    let issues = riprocess::lint::check(path).unwrap_or_else(|err| fail(err));
+    for issue in &issues {
+        let separator = if issue.position.is_some() { ":" } else { ": " };
+        eprintln!("{}: {}{}{}", issue.level, path.display(), separator, issue);
+    }
+    if issues.iter().any(|issue| issue.level == Level::Error) {
+        std::process::exit(EXIT_ERROR);
+    } else {
+        eprintln!("{} is ok", path.display());
+    }
+}
+
+fn migrate(path: &Path, dry_run: bool) {
+    let contents = std::fs::read_to_string(path).unwrap_or_else(|err| fail(err.into()));
+    let migration = riprocess::migrate::migrate(&contents).unwrap_or_else(|err| fail(err));
+    if migration.changes.is_empty() {
+        eprintln!("{} is already version {}", path.display(), riprocess::migrate::VERSION);
+        return;
+    }
+    for change in &migration.changes {
+        eprintln!("{}: {}", path.display(), change);
+    }
+    if dry_run {
+        print!("{}", migration.contents);
+    } else {
+        std::fs::write(path, migration.contents).unwrap_or_else(|err| fail(err.into()));
+        eprintln!("migrated {} to version {}", path.display(), riprocess::migrate::VERSION);
+    }
+}
+
+fn init(args: &Args) {
+    use riprocess::init::{self, Project};
+
+    let output = args.flag_output
+        .clone()
+        .unwrap_or_else(|| args.arg_project_root.join("riprocess.toml"));
+    if output.exists() {
+        eprintln!("error: {} already exists", output.display());
+        std::process::exit(EXIT_ERROR);
+    }
+    let mut project = Project::scan(&args.arg_project_root).unwrap_or_else(|err| fail(err));
+    let mut config = project.config();
+    if !args.flag_yes {
+        eprint!("{}", init::render(&config, &project));
+        eprintln!();
+        if let Some(images) = ask_path("image directory", project.images.as_ref()) {
+            if project.images.as_ref() != Some(&images) {
+                project.image_numbers = init::image_numbers(&images)
+                    .unwrap_or_else(|err| fail(err));
+                project.images = Some(images);
+            }
+        }
+        config = project.config();
+        if !project.image_numbers.is_empty() {
+            eprintln!("found {} images, {} to {}{}",
+                      project.image_numbers.len(),
+                      project.image_numbers[0],
+                      project.image_numbers[project.image_numbers.len() - 1],
+                      if project.gaps().is_empty() { "" } else { ", with gaps" });
+            config.images.start = ask("first image", config.images.start);
+            config.images.end = ask("last image", config.images.end);
+        }
+        if let Some(timestamps) = ask_path("timestamp directory", project.timestamps.as_ref()) {
+            if project.timestamps.as_ref() != Some(&timestamps) {
+                project.timestamp_files = init::timestamp_files(&timestamps)
+                    .unwrap_or_else(|err| fail(err));
+                project.timestamps = Some(timestamps);
+                let defaults = project.config().timestamps;
+                config.timestamps = defaults;
+            }
+        }
+        if !project.timestamp_files.is_empty() {
+            eprintln!("found {} timestamp files", project.timestamp_files.len());
+            config.timestamps.start = ask("first timestamp file", config.timestamps.start.take());
+            config.timestamps.end = ask("last timestamp file", config.timestamps.end.take());
+        }
+        let question = match config.records.csv {
+            Some(_) => "record start times, separated by commas (blank to use Records.csv)",
+            None => "record start times, separated by commas",
+        };
+        while let Some(times) = ask::<String>(question, None) {
+            match times.split(',').map(|time| time.trim().parse()).collect() {
+                Ok(start_times) => {
+                    config.records.csv = None;
+                    config.records.start_times = start_times;
+                    break;
+                }
+                Err(_) => eprintln!("could not understand {:?}", times),
+            }
+        }
+    }
+    let contents = init::render(&config, &project);
+    std::fs::write(&output, &contents).unwrap_or_else(|err| fail(err.into()));
+    eprintln!("wrote {}", output.display());
+    if config.records.csv.is_none() && config.records.start_times.is_empty() {
+        warn("no records were found, fill in [records] start_times before using it");
+    }
+}
+
+/// Asks a question on stderr and reads the answer from stdin, asking again until it parses.
+///
+/// An empty answer keeps the default.
+fn ask<T>(question: &str, default: Option<T>) -> Option<T>
+    where T: std::str::FromStr + std::fmt::Display
+{
+    use std::io::BufRead;
+
+    let stdin = std::io::stdin();
+    loop {
+        match default {
+            Some(ref default) => eprint!("{} [{}]: ", question, default),
+            None => eprint!("{}: ", question),
+        }
+        let mut answer = String::new();
+        if stdin.lock().read_line(&mut answer).unwrap_or_else(|err| fail(err.into())) == 0 {
+            return default;
+        }
+        let answer = answer.trim();
+        if answer.is_empty() {
+            return default;
+        }
+        match answer.parse() {
+            Ok(value) => return Some(value),
+            Err(_) => eprintln!("could not understand {:?}", answer),
+        }
+    }
+}
+
+fn ask_path(question: &str, default: Option<&PathBuf>) -> Option<PathBuf> {
+    loop {
+        let path = ask(question, default.map(|path| path.display().to_string()))
+            .map(PathBuf::from);
+        match path {
+            Some(ref path) if !path.is_dir() => eprintln!("{} is not a directory", path.display()),
+            _ => return path,
+        }
+    }
+}
+
+fn watch(config: &Config, args: &Args) {
+    use riprocess::watch::Watcher;
+    use std::time::Duration;
+
+    let mut watcher = Watcher::new();
+    eprintln!("watching {} and {}, press Ctrl-C to stop",
+              config.images.path.display(),
+              config.timestamps.path.display());
+    loop {
+        match watcher.poll(config) {
+            Ok(Some(update)) => report(update, args),
+            Ok(None) => {}
+            Err(err) => warn(format!("could not check for changes, trying again: {}", err)),
+        }
+        std::thread::sleep(Duration::from_millis((args.flag_interval * 1000.) as u64));
+    }
+}
+
+/// Prints what changed in a watch update and writes the image list if it's complete.
+fn report(update: Update, args: &Args) {
+    use riprocess::watch::Transition;
+
+    if let Some((images, timestamps)) = update.mismatch() {
+        eprintln!("{} images, {} timestamps", images, timestamps);
+    }
+    match update.transition {
+        Some(Transition::LinedUp) => eprintln!("*** counts line up, data is complete ***"),
+        Some(Transition::Diverged) => eprintln!("*** counts no longer line up ***"),
+        None => {}
+    }
+    match update.image_list {
+        Ok(image_list) => {
+            for warning in image_list.report().warnings() {
+                warn(warning);
+            }
+            let images = image_list.collect::<Vec<_>>();
+            eprintln!("image list has {} images", images.len());
+            if let Some(ref output) = args.flag_output {
+                write_lines(output, &images, args);
+            }
+        }
+        Err(Error::Validation(ref issues)) => {
+            for issue in issues {
+                eprintln!("error: {}", issue);
+            }
+        }
+        Err(ref err) if update.mismatch().is_none() => eprintln!("error: {}", err),
+        Err(_) => {}
+    }
+}
+
+fn cache_path(args: &Args) -> Option<PathBuf> {
+    if args.arg_config.as_os_str().is_empty() {
+        Cache::default_path()
+    } else {
+        Config::from_path_with(&args.arg_config, &overrides(args))
+            .unwrap_or_else(|err| fail(err))
+            .cache
+            .or_else(Cache::default_path)
+    }
+}
+
+/// Returns the image list line for an image, e.g. `332979.899441;DSC03522.JPG`.
+///
+/// With `--provenance`, the image's source columns are appended.
+fn line(image: &Image, args: &Args) -> String {
+    let mut line = format!("{:.6};{}", image.timestamp, image.path.display());
+    if args.flag_provenance {
+        for column in image.provenance() {
+            line.push(';');
+            line.push_str(&column);
+        }
+    }
+    line
+}
+
+fn image_list(config: &Config, args: &Args) -> ImageList {
+    let cache = if args.flag_no_cache {
+        None
+    } else {
+        config.cache.clone().or_else(Cache::default_path).map(Cache::open)
+    };
+    let image_list = config.image_list_with(cache.as_ref()).unwrap_or_else(|err| fail(err));
+    if let Some(cache) = cache {
+        if let Err(err) = cache.save() {
+            warn(format!("could not save the scan cache to {}: {}",
+                         cache.path().display(),
+                         err));
+        }
+    }
+    for warning in image_list.report().warnings() {
+        warn(warning);
+    }
+    for dropped in image_list.dropped() {
+        eprintln!("dropped {}", dropped);
+    }
+    if let Some(ref orphan_list) = config.records.orphan_list {
+        if !args.flag_dry_run {
+            write_lines(orphan_list, image_list.orphans(), args);
+        }
+        if !image_list.orphans().is_empty() {
+            eprintln!("{} orphans {} {}",
+                      image_list.orphans().len(),
+                      if args.flag_dry_run { "would be written to" } else { "written to" },
+                      orphan_list.display());
+        }
+    }
+    image_list
+}
+
+/// Writes the image list lines for some images to a file.
+fn write_lines(path: &Path, images: &[Image], args: &Args) {
+    let mut file = std::fs::File::create(path).unwrap_or_else(|err| fail(err.into()));
+    for image in images {
+        writeln!(file, "{}", line(image, args)).unwrap_or_else(|err| fail(err.into()));
+    }
+}
+
+/// Prints a warning, and remembers to exit with `EXIT_WARNINGS`, or with `EXIT_ERROR` in strict
+/// mode.
+fn warn<T: std::fmt::Display>(warning: T) {
+    WARNINGS.fetch_add(1, Ordering::SeqCst);
+    if STRICT.load(Ordering::SeqCst) {
+        eprintln!("error: {}", warning);
+    } else {
+        eprintln!("warning: {}", warning);
+    }
+}
+
+/// Prints an error and exits with `EXIT_ERROR`.
+fn fail(err: Error) -> ! {
+    match err {
+        Error::Validation(issues) => {
+            for issue in issues {
+                eprintln!("error: {}", issue);
+            }
+        }
+        Error::Integrity(failures) => {
+            for failure in failures {
+                eprintln!("error: {}", failure);
+            }
+        }
+        Error::Warnings(warnings) => {
+            for warning in &warnings {
+                eprintln!("error: {}", warning);
+            }
+            eprintln!("error: there were {} warnings, and --strict makes them errors",
+                      warnings.len());
         }
+        err => eprintln!("error: {}", err),
     }
+    std::process::exit(EXIT_ERROR);
 }
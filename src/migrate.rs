@@ -0,0 +1,143 @@
+//! Configuration format versions and migration.
+//!
+//! Configuration files have a top-level `version`. Files without one predate versioning. Their
+//! format is the same as version 1, so migrating them only adds the version.
+//!
+//! Migrations edit the file's text line by line instead of re-serializing it, so comments and
+//! formatting are kept. When the format changes, bump `VERSION` and add a step to `migrate` that
+//! rewrites the previous version's lines and explains each change.
+
+use {Error, Result};
+use toml::Value;
+
+/// The current configuration format version.
+pub const VERSION: u32 = 1;
+
+/// A migrated configuration file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Migration {
+    /// The version of the original file, or zero if it had no version.
+    pub from: u32,
+    /// The migrated contents.
+    pub contents: String,
+    /// A description of each change, in the order they were made.
+    ///
+    /// Empty if the file was already current.
+    pub changes: Vec<String>,
+}
+
+/// Returns the format version of a configuration file, or zero if it has no version.
+///
+/// # Examples
+///
+/// ```
+/// use riprocess::migrate;
+/// assert_eq!(0, migrate::version("[images]\npath = \"data/images\"\n").unwrap());
+/// assert_eq!(1, migrate::version("version = 1\n").unwrap());
+/// ```
+pub fn version(contents: &str) -> Result<u32> {
+    match contents.parse::<Value>()?.get("version") {
+        None => Ok(0),
+        Some(&Value::Integer(version)) if version >= 0 => Ok(version as u32),
+        Some(_) => Err(Error::InvalidConfig("version must be a whole number".to_string())),
+    }
+}
+
+/// Migrates the contents of a configuration file to the current format version.
+///
+/// Returns an error if the file can't be parsed, or if it was written for a newer version of
+/// riprocess.
+///
+/// # Examples
+///
+/// ```
+/// use riprocess::migrate;
+/// let contents = std::fs::read_to_string("data/config.toml").unwrap();
+/// let migration = migrate::migrate(&contents).unwrap();
+/// assert_eq!(0, migration.from);
+/// assert!(migration.contents.starts_with("version = 1\n"));
+/// assert!(migrate::migrate(&migration.contents).unwrap().changes.is_empty());
+/// ```
+pub fn migrate(contents: &str) -> Result<Migration> {
+    let from = version(contents)?;
+    if from > VERSION {
+        return Err(Error::InvalidConfig(format!("the file is version {}, but this riprocess only \
+                                                 understands up to version {}",
+                                                from,
+                                                VERSION)));
+    }
+    let mut lines = contents.lines().map(String::from).collect::<Vec<_>>();
+    let mut changes = Vec::new();
+    if from < VERSION {
+        changes.push(set_version(&mut lines, from));
+    }
+    let mut contents = lines.join("\n");
+    if !contents.is_empty() {
+        contents.push('\n');
+    }
+    Ok(Migration {
+           from,
+           contents,
+           changes,
+       })
+}
+
+/// Sets the top-level version, replacing an existing version line or adding one before the
+/// first key or section.
+fn set_version(lines: &mut Vec<String>, from: u32) -> String {
+    let line = format!("version = {}", VERSION);
+    for (i, existing) in lines.iter_mut().enumerate() {
+        let trimmed = existing.trim_start();
+        if trimmed.starts_with('[') {
+            break;
+        }
+        let key = trimmed.split('=').next().unwrap_or_default().trim();
+        if key == "version" && trimmed.contains('=') {
+            *existing = line;
+            return format!("line {}: changed the version from {} to {}", i + 1, from, VERSION);
+        }
+    }
+    let i = lines.iter()
+        .position(|line| {
+                      let trimmed = line.trim();
+                      !trimmed.is_empty() && !trimmed.starts_with('#')
+                  })
+        .unwrap_or(lines.len());
+    let needs_blank_line = lines.get(i).is_some_and(|line| line.trim_start().starts_with('['));
+    lines.insert(i, line);
+    if needs_blank_line {
+        lines.insert(i + 1, String::new());
+    }
+    format!("line {}: added version = {}; files without a version use the version {} format, \
+             so nothing else needed to change",
+            i + 1,
+            VERSION,
+            VERSION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_comments() {
+        let contents = "# Flight 3\n\n[images]\npath = \"images\" # card A\n";
+        let migration = migrate(contents).unwrap();
+        assert_eq!("# Flight 3\n\nversion = 1\n\n[images]\npath = \"images\" # card A\n",
+                   migration.contents);
+        assert_eq!(1, migration.changes.len());
+        assert!(migration.changes[0].starts_with("line 3:"));
+    }
+
+    #[test]
+    fn top_level_keys() {
+        let migration = migrate("threads = 2\n[images]\n").unwrap();
+        assert_eq!("version = 1\nthreads = 2\n[images]\n", migration.contents);
+    }
+
+    #[test]
+    fn newer() {
+        assert!(migrate("version = 2\n").is_err());
+        assert!(migrate("version = \"1\"\n").is_err());
+    }
+}
@@ -0,0 +1,373 @@
+//! Organize images into the RiPROCESS project tree.
+//!
+//! RiPROCESS expects camera images in `04_CAM_RAW/03_IMG`, but they usually come off the camera
+//! into some other directory. This module copies, hardlinks, or symlinks the images in an image
+//! list into the project tree.
+
+use {Error, Image, Result};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// The image directory inside of a RiPROCESS project.
+pub const IMAGE_DIRECTORY: &str = "04_CAM_RAW/03_IMG";
+
+/// Configuration for organizing images.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    /// The root directory of the RiPROCESS project.
+    ///
+    /// Images are placed in `04_CAM_RAW/03_IMG` under this directory. This must be set, so
+    /// images don't end up under the current directory by accident.
+    pub project: PathBuf,
+    /// How images are placed in the project tree.
+    pub method: Method,
+    /// Place each record's images in their own subdirectory, e.g. `record_01`.
+    pub group_by_record: bool,
+    /// Compare the checksums of the source and destination files after each image is copied.
+    ///
+    /// Links share their source's contents, so they aren't verified.
+    pub verify: bool,
+}
+
+/// How images are placed in the project tree.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Method {
+    /// Copy each image.
+    Copy,
+    /// Create a hard link to each image.
+    ///
+    /// The source and the project must be on the same filesystem.
+    Hardlink,
+    /// Create a symbolic link to each image.
+    Symlink,
+}
+
+/// A single image placement.
+#[derive(Debug, PartialEq)]
+pub struct Operation {
+    /// The original image path.
+    pub source: PathBuf,
+    /// The image, with its path inside of the project tree.
+    pub image: Image,
+    /// True if the destination already held an identical file, so nothing was done.
+    pub skipped: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            project: PathBuf::new(),
+            method: Method::Copy,
+            group_by_record: false,
+            verify: true,
+        }
+    }
+}
+
+impl Config {
+    /// Creates a new, default configuration.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::organize::Config;
+    /// let config = Config::new();
+    /// ```
+    pub fn new() -> Config {
+        Default::default()
+    }
+
+    /// Returns the directory that the images are placed in.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::organize::Config;
+    /// let config = Config { project: "project".into(), ..Default::default() };
+    /// assert_eq!(std::path::Path::new("project/04_CAM_RAW/03_IMG"), config.directory());
+    /// ```
+    pub fn directory(&self) -> PathBuf {
+        self.project.join(IMAGE_DIRECTORY)
+    }
+
+    /// Returns the path in the project tree for the provided image.
+    pub fn destination(&self, image: &Image) -> PathBuf {
+        let mut destination = self.directory();
+        if self.group_by_record {
+            destination.push(format!("record_{:02}", image.record + 1));
+        }
+        if let Some(file_name) = image.path.file_name() {
+            destination.push(file_name);
+        }
+        destination
+    }
+
+    /// Places each image into the project tree.
+    ///
+    /// Returns one operation per image, in order. The operation's image points to the new
+    /// location, so the images can be used to write an image list for the project tree. If
+    /// `dry_run` is true, nothing is written to disk.
+    ///
+    /// If a destination file already exists and has the same checksum as its source, it is left
+    /// alone. If it exists and differs, an error is returned.
+    ///
+    /// If an image can't be placed, anything written for it is removed, and so are the images
+    /// that were already placed by this call and the directories this call created, so the
+    /// project tree is never half-populated. Returns an error if `project` isn't set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use riprocess::Config;
+    /// use riprocess::organize;
+    /// let config = Config::from_path("data/config.toml").unwrap();
+    /// let images = config.image_list().unwrap().collect::<Vec<_>>();
+    /// let organize = organize::Config { project: "project".into(), ..Default::default() };
+    /// let operations = organize.organize(images, true).unwrap();
+    /// assert!(operations[0].image.path.starts_with("project/04_CAM_RAW/03_IMG"));
+    /// ```
+    pub fn organize<I>(&self, images: I, dry_run: bool) -> Result<Vec<Operation>>
+        where I: IntoIterator<Item = Image>
+    {
+        if self.project.as_os_str().is_empty() {
+            return Err(Error::InvalidConfig("organize.project is not set".to_string()));
+        }
+        let mut operations = Vec::new();
+        let mut created = Vec::new();
+        for image in images {
+            match self.operation(image, dry_run, &mut created) {
+                Ok(operation) => operations.push(operation),
+                Err(err) => {
+                    if !dry_run {
+                        roll_back(&operations, &created);
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        Ok(operations)
+    }
+
+    fn operation(&self,
+                 image: Image,
+                 dry_run: bool,
+                 created: &mut Vec<PathBuf>)
+                 -> Result<Operation> {
+        let destination = self.destination(&image);
+        let skipped = if destination.exists() {
+            if checksum(&image.path)? == checksum(&destination)? {
+                true
+            } else {
+                return Err(Error::DestinationExists(destination));
+            }
+        } else {
+            false
+        };
+        if !dry_run && !skipped {
+            self.place(&image.path, &destination, created)?;
+        }
+        Ok(Operation {
+               source: image.path.clone(),
+               image: Image { path: destination, ..image },
+               skipped,
+           })
+    }
+
+    /// Places a single image, adding the directories that had to be created to `created`.
+    ///
+    /// If the image can't be placed, e.g. because a copy failed partway, the destination is
+    /// removed again.
+    fn place(&self, source: &Path, destination: &Path, created: &mut Vec<PathBuf>) -> Result<()> {
+        use std::fs;
+
+        if let Some(parent) = destination.parent() {
+            let missing = parent.ancestors()
+                .take_while(|path| !path.as_os_str().is_empty() && !path.exists())
+                .map(Path::to_path_buf)
+                .collect::<Vec<_>>();
+            let result = fs::create_dir_all(parent);
+            created.extend(missing.into_iter().rev().filter(|path| path.is_dir()));
+            result?;
+        }
+        let result = self.transfer(source, destination);
+        if result.is_err() {
+            let _ = fs::remove_file(destination);
+        }
+        result
+    }
+
+    fn transfer(&self, source: &Path, destination: &Path) -> Result<()> {
+        use std::fs;
+
+        match self.method {
+            Method::Copy => fs::copy(source, destination).map(|_| ())?,
+            Method::Hardlink => fs::hard_link(source, destination)?,
+            Method::Symlink => symlink(&source.canonicalize()?, destination)?,
+        }
+        if self.verify && self.method == Method::Copy &&
+           checksum(source)? != checksum(destination)? {
+            return Err(Error::ChecksumMismatch(destination.to_path_buf()));
+        }
+        Ok(())
+    }
+}
+
+/// Removes the files placed by these operations, and the directories that were created for them.
+fn roll_back(operations: &[Operation], created: &[PathBuf]) {
+    use std::fs;
+
+    for operation in operations.iter().rev().filter(|operation| !operation.skipped) {
+        let _ = fs::remove_file(&operation.image.path);
+    }
+    for directory in created.iter().rev() {
+        let _ = fs::remove_dir(directory);
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Method::Copy => write!(f, "copy"),
+            Method::Hardlink => write!(f, "hardlink"),
+            Method::Symlink => write!(f, "symlink"),
+        }
+    }
+}
+
+/// Returns the CRC-32 checksum of the file at the provided path.
+pub(crate) fn checksum(path: &Path) -> Result<u32> {
+    use crc32fast::Hasher;
+    use std::fs::File;
+    use std::io::Read;
+
+    let mut file = File::open(path)?;
+    let mut hasher = Hasher::new();
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buffer)?;
+        if n == 0 {
+            return Ok(hasher.finalize());
+        }
+        hasher.update(&buffer[..n]);
+    }
+}
+
+#[cfg(unix)]
+fn symlink(source: &Path, destination: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(source, destination)
+}
+
+#[cfg(windows)]
+fn symlink(source: &Path, destination: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(source, destination)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Config as RiprocessConfig;
+    use std::fs;
+
+    fn project(name: &str) -> PathBuf {
+        let project = std::env::temp_dir().join(format!("riprocess-organize-{}", name));
+        if project.exists() {
+            fs::remove_dir_all(&project).unwrap();
+        }
+        project
+    }
+
+    fn images() -> Vec<Image> {
+        RiprocessConfig::from_path("data/config.toml").unwrap().image_list().unwrap().collect()
+    }
+
+    #[test]
+    fn dry_run() {
+        let config = Config { project: project("dry-run"), ..Default::default() };
+        let operations = config.organize(images(), true).unwrap();
+        assert_eq!(4, operations.len());
+        assert!(!config.directory().exists());
+    }
+
+    #[test]
+    fn copy() {
+        let config = Config {
+            project: project("copy"),
+            group_by_record: true,
+            ..Default::default()
+        };
+        let operations = config.organize(images(), false).unwrap();
+        assert!(operations.iter().all(|operation| operation.image.path.exists()));
+        assert!(operations[0].image.path.ends_with("record_01/DSC03522.JPG"));
+        assert!(operations[3].image.path.ends_with("record_02/DSC03525.JPG"));
+        assert_eq!(images()[0].timestamp, operations[0].image.timestamp);
+
+        let operations = config.organize(images(), false).unwrap();
+        assert!(operations.iter().all(|operation| operation.skipped));
+        fs::remove_dir_all(&config.project).unwrap();
+    }
+
+    #[test]
+    fn destination_exists() {
+        let config = Config { project: project("exists"), ..Default::default() };
+        fs::create_dir_all(config.directory()).unwrap();
+        fs::write(config.directory().join("DSC03522.JPG"), b"not the same").unwrap();
+        assert!(config.organize(images(), true).is_err());
+        fs::remove_dir_all(&config.project).unwrap();
+    }
+
+    #[test]
+    fn roll_back() {
+        let config = Config {
+            project: project("roll-back"),
+            group_by_record: true,
+            ..Default::default()
+        };
+        let conflict = config.directory().join("record_02").join("DSC03525.JPG");
+        fs::create_dir_all(conflict.parent().unwrap()).unwrap();
+        fs::write(&conflict, b"not the same").unwrap();
+        assert!(config.organize(images(), false).is_err());
+        assert!(!config.directory().join("record_01").exists());
+        assert!(!config.directory().join("record_02").join("DSC03524.JPG").exists());
+        assert!(conflict.exists());
+        fs::remove_dir_all(&config.project).unwrap();
+    }
+
+    #[test]
+    fn roll_back_keeps_existing_directories() {
+        let config = Config { project: project("roll-back-existing"), ..Default::default() };
+        fs::create_dir_all(config.directory()).unwrap();
+        let mut images = images();
+        images.truncate(2);
+        images[1].path = config.project.join("DSC03599.JPG");
+        assert!(config.organize(images, false).is_err());
+        assert!(!config.directory().join("DSC03522.JPG").exists());
+        assert!(!config.directory().join("DSC03599.JPG").exists());
+        assert!(config.directory().exists());
+        fs::remove_dir_all(&config.project).unwrap();
+
+        let mut images = self::images();
+        images[0].path = config.project.join("DSC03599.JPG");
+        assert!(config.organize(images, false).is_err());
+        assert!(!config.project.join("04_CAM_RAW").exists());
+    }
+
+    #[test]
+    fn no_project() {
+        assert!(Config::new().organize(images(), true).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink() {
+        let config = Config {
+            project: project("symlink"),
+            method: Method::Symlink,
+            ..Default::default()
+        };
+        let operations = config.organize(images(), false).unwrap();
+        assert!(fs::symlink_metadata(&operations[0].image.path).unwrap().file_type().is_symlink());
+        fs::remove_dir_all(&config.project).unwrap();
+    }
+}
@@ -0,0 +1,185 @@
+//! Configuration overrides from the command line and the environment.
+//!
+//! Tweaking one record start time, or `images.end` during a download, shouldn't mean editing the
+//! TOML file. An override sets a single value, addressed by its dotted key, on top of the file.
+//! Overrides are applied in order, so later ones win.
+
+use {Error, Result};
+use toml::Value;
+use toml::value::Table;
+
+/// The prefix of environment variables that override configuration values.
+pub const ENV_PREFIX: &str = "RIPROCESS_";
+
+/// The sections of the configuration file, used to split environment variable names.
+const SECTIONS: &[&str] = &["images",
+                            "timestamps",
+                            "records",
+                            "validation",
+                            "integrity",
+                            "organize",
+                            "rename",
+                            "trajectory",
+                            "geotag",
+                            "sidecar",
+                            "export",
+                            "thin",
+                            "exclude"];
+
+/// A single overridden configuration value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Override {
+    /// The dotted key of the value, e.g. `images.end`.
+    pub key: String,
+    /// The new value.
+    pub value: Value,
+}
+
+impl Override {
+    /// Parses an override of the form `key=value`.
+    ///
+    /// The value is read as a TOML value, e.g. `3600` or `[332978.669, 333039.279]`. If it isn't
+    /// valid TOML it is taken as a string, so paths and file names don't need quotes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::overrides::Override;
+    /// let over = Override::parse("images.end=3600").unwrap();
+    /// assert_eq!("images.end", over.key);
+    /// assert_eq!(Some(3600), over.value.as_integer());
+    /// let over = Override::parse("images.path=data/images").unwrap();
+    /// assert_eq!(Some("data/images"), over.value.as_str());
+    /// assert!(Override::parse("images.end").is_err());
+    /// ```
+    pub fn parse(s: &str) -> Result<Override> {
+        let (key, value) = match s.find('=') {
+            Some(i) => (s[..i].trim(), s[i + 1..].trim()),
+            None => return Err(invalid(format!("override {} is not of the form key=value", s))),
+        };
+        if key.is_empty() || key.split('.').any(str::is_empty) {
+            return Err(invalid(format!("override {} has an invalid key", s)));
+        }
+        Ok(Override {
+               key: key.to_string(),
+               value: parse_value(value),
+           })
+    }
+
+    /// Sets this override's value in a configuration table, creating tables as needed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate toml;
+    /// # extern crate riprocess;
+    /// use riprocess::overrides::Override;
+    /// # fn main() {
+    /// let mut table = toml::value::Table::new();
+    /// Override::parse("records.start_times=[1.5]").unwrap().apply(&mut table).unwrap();
+    /// assert_eq!("[records]\nstart_times = [1.5]\n", toml::to_string(&table).unwrap());
+    /// # }
+    /// ```
+    pub fn apply(&self, table: &mut Table) -> Result<()> {
+        let keys = self.key.split('.').collect::<Vec<_>>();
+        let (last, parents) = keys.split_last().expect("keys are never empty");
+        let mut table = table;
+        for (i, key) in parents.iter().enumerate() {
+            let value = table.entry(key.to_string()).or_insert_with(|| Value::Table(Table::new()));
+            table = match *value {
+                Value::Table(ref mut table) => table,
+                _ => return Err(invalid(format!("{} is not a section", keys[..i + 1].join(".")))),
+            };
+        }
+        table.insert(last.to_string(), self.value.clone());
+        Ok(())
+    }
+}
+
+/// Returns the overrides set in the environment, sorted by key.
+///
+/// `RIPROCESS_IMAGES_PATH` overrides `images.path`, and `RIPROCESS_THREADS` overrides `threads`.
+pub fn from_env() -> Vec<Override> {
+    from_vars(std::env::vars())
+}
+
+/// Returns the overrides in a set of environment variables, sorted by key.
+///
+/// Variables without the `RIPROCESS_` prefix are ignored. The first word after the prefix is the
+/// section, if there is a section with that name, and the rest is the key within the section.
+///
+/// # Examples
+///
+/// ```
+/// use riprocess::overrides;
+/// let vars = vec![("RIPROCESS_RECORDS_START_TIMES".to_string(), "[1.5]".to_string()),
+///                 ("RIPROCESS_THREADS".to_string(), "2".to_string()),
+///                 ("HOME".to_string(), "/root".to_string())];
+/// let keys = overrides::from_vars(vars).into_iter().map(|over| over.key).collect::<Vec<_>>();
+/// assert_eq!(vec!["records.start_times", "threads"], keys);
+/// ```
+pub fn from_vars<I>(vars: I) -> Vec<Override>
+    where I: IntoIterator<Item = (String, String)>
+{
+    let mut overrides = vars.into_iter()
+        .filter_map(|(name, value)| {
+            if !name.starts_with(ENV_PREFIX) || name.len() == ENV_PREFIX.len() {
+                return None;
+            }
+            let name = name[ENV_PREFIX.len()..].to_lowercase();
+            let key = match name.find('_') {
+                Some(i) if SECTIONS.contains(&&name[..i]) => {
+                    format!("{}.{}", &name[..i], &name[i + 1..])
+                }
+                _ => name,
+            };
+            Some(Override {
+                     key,
+                     value: parse_value(&value),
+                 })
+        })
+        .collect::<Vec<_>>();
+    overrides.sort_by(|a, b| a.key.cmp(&b.key));
+    overrides
+}
+
+/// Applies overrides, in order, to a configuration table.
+pub(crate) fn apply(table: &mut Table, overrides: &[Override]) -> Result<()> {
+    for over in overrides {
+        over.apply(table)?;
+    }
+    Ok(())
+}
+
+fn parse_value(s: &str) -> Value {
+    format!("value = {}", s)
+        .parse::<Value>()
+        .ok()
+        .and_then(|mut value| value.as_table_mut().and_then(|table| table.remove("value")))
+        .unwrap_or_else(|| Value::String(s.to_string()))
+}
+
+fn invalid(message: String) -> Error {
+    Error::InvalidConfig(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn not_a_section() {
+        let mut table = "[images]\npath = \"data/images\"\n".parse::<Value>().unwrap();
+        let over = Override::parse("images.path.end=3").unwrap();
+        assert!(over.apply(table.as_table_mut().unwrap()).is_err());
+    }
+
+    #[test]
+    fn later_wins() {
+        let overrides = vec![Override::parse("images.end=3600").unwrap(),
+                             Override::parse("images.end=3601").unwrap()];
+        let mut value = "[images]\nend = 3525\n".parse::<Value>().unwrap();
+        apply(value.as_table_mut().unwrap(), &overrides).unwrap();
+        assert_eq!(Some(3601), value["images"]["end"].as_integer());
+    }
+}
@@ -0,0 +1,246 @@
+//! Shared configuration profiles.
+//!
+//! Every project with the same sensor repeats the same camera name, validation rules and path
+//! conventions. A configuration file can pull those from one or more profiles with `extends`:
+//!
+//! ```toml
+//! extends = "profiles/ricopter-sony.toml"
+//! # or
+//! extends = ["ricopter-sony", "site-defaults.toml"]
+//! ```
+//!
+//! Profiles are looked up relative to the file that extends them, and then by name in the user
+//! profile directory. Profiles can extend other profiles. Sections are merged deeply: a file's
+//! values win over those of the profiles it extends, and later profiles win over earlier ones.
+//! Arrays are replaced, not appended to.
+
+use {Error, Result};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::Value;
+use toml::value::Table;
+
+/// A profile in a profile directory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    /// The profile's name, which is its file stem.
+    pub name: String,
+    /// The path to the profile.
+    pub path: PathBuf,
+}
+
+/// Returns the user profile directory, e.g. `~/.config/riprocess/profiles` on Linux.
+///
+/// Returns None if the platform doesn't have a configuration directory.
+///
+/// # Examples
+///
+/// ```
+/// use riprocess::profile;
+/// let directory = profile::directory();
+/// ```
+pub fn directory() -> Option<PathBuf> {
+    dirs::config_dir().map(|directory| directory.join("riprocess").join("profiles"))
+}
+
+/// Returns the profiles in the user profile directory, sorted by name.
+///
+/// Returns an empty list if the directory doesn't exist.
+///
+/// # Examples
+///
+/// ```
+/// use riprocess::profile;
+/// let profiles = profile::list().unwrap();
+/// ```
+pub fn list() -> Result<Vec<Profile>> {
+    match directory() {
+        Some(directory) => list_in(directory),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Returns the profiles in a directory, sorted by name.
+///
+/// Every `.toml` file is a profile. Returns an empty list if the directory doesn't exist.
+///
+/// # Examples
+///
+/// ```
+/// use riprocess::profile;
+/// let profiles = profile::list_in("data").unwrap();
+/// assert_eq!(vec!["config"], profiles.iter().map(|p| p.name.as_str()).collect::<Vec<_>>());
+/// ```
+pub fn list_in<P: AsRef<Path>>(directory: P) -> Result<Vec<Profile>> {
+    let read_dir = match fs::read_dir(directory) {
+        Ok(read_dir) => read_dir,
+        Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut profiles = Vec::new();
+    for dir_entry in read_dir {
+        let path = dir_entry?.path();
+        if path.is_file() && path.extension() == Some(OsStr::new("toml")) {
+            if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                profiles.push(Profile {
+                                  name: name.to_string(),
+                                  path: path.clone(),
+                              });
+            }
+        }
+    }
+    profiles.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(profiles)
+}
+
+/// Merges `overlay` into `base`.
+///
+/// Tables are merged recursively. Any other value in `overlay`, including arrays, replaces the
+/// value in `base`.
+///
+/// # Examples
+///
+/// ```
+/// extern crate toml;
+/// # extern crate riprocess;
+/// use riprocess::profile;
+/// # fn main() {
+/// let mut base = "[images]\ncamera = \"sony\"\nstart = 1\n".parse::<toml::Value>().unwrap();
+/// let overlay = "[images]\nstart = 2\n".parse::<toml::Value>().unwrap();
+/// profile::merge(base.as_table_mut().unwrap(), overlay.try_into().unwrap());
+/// assert_eq!(Some("sony"), base["images"]["camera"].as_str());
+/// assert_eq!(Some(2), base["images"]["start"].as_integer());
+/// # }
+/// ```
+pub fn merge(base: &mut Table, overlay: Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(&mut Value::Table(ref mut base)), Value::Table(overlay)) => merge(base, overlay),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Reads a configuration file, merged with the profiles it extends.
+///
+/// The returned table doesn't have an `extends` key.
+pub(crate) fn load(path: &Path) -> Result<Table> {
+    load_with(path, &mut Vec::new())
+}
+
+fn load_with(path: &Path, stack: &mut Vec<PathBuf>) -> Result<Table> {
+    let canonical = path.canonicalize()?;
+    if stack.contains(&canonical) {
+        return Err(Error::InvalidConfig(format!("{} extends itself", path.display())));
+    }
+    let mut table = match fs::read_to_string(path)?.parse::<Value>()? {
+        Value::Table(table) => table,
+        _ => unreachable!("a TOML document is always a table"),
+    };
+    let extends = match table.remove("extends") {
+        None => Vec::new(),
+        Some(Value::String(name)) => vec![name],
+        Some(Value::Array(names)) => {
+            names.into_iter()
+                .map(|name| match name {
+                         Value::String(name) => Ok(name),
+                         _ => Err(invalid_extends(path)),
+                     })
+                .collect::<Result<_>>()?
+        }
+        Some(_) => return Err(invalid_extends(path)),
+    };
+    stack.push(canonical);
+    let mut merged = Table::new();
+    for name in extends {
+        let profile = resolve(&name, path.parent().unwrap_or_else(|| Path::new("")))?;
+        merge(&mut merged, load_with(&profile, stack)?);
+    }
+    stack.pop();
+    merge(&mut merged, table);
+    Ok(merged)
+}
+
+/// Finds a profile, first relative to the extending file's directory, then in the user profile
+/// directory.
+fn resolve(name: &str, relative_to: &Path) -> Result<PathBuf> {
+    let path = relative_to.join(name);
+    if path.is_file() {
+        return Ok(path);
+    }
+    if let Some(directory) = directory() {
+        let mut path = directory.join(name);
+        if path.extension().is_none() {
+            path.set_extension("toml");
+        }
+        if path.is_file() {
+            return Ok(path);
+        }
+    }
+    Err(Error::InvalidConfig(format!("profile {} could not be found", name)))
+}
+
+fn invalid_extends(path: &Path) -> Error {
+    Error::InvalidConfig(format!("extends in {} must be a file name or a list of file names",
+                                 path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join("riprocess-profile").join(name);
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(directory.join("profiles")).unwrap();
+        directory
+    }
+
+    #[test]
+    fn extends() {
+        let directory = directory("extends");
+        fs::write(directory.join("profiles/sony.toml"),
+                  "extends = \"base.toml\"\n[images]\ncamera = \"sony\"\n")
+            .unwrap();
+        fs::write(directory.join("profiles/base.toml"),
+                  "threads = 2\n[images]\ncamera = \"generic\"\nstart = 1\n")
+            .unwrap();
+        fs::write(directory.join("site.toml"), "[images]\nstart = 2\n").unwrap();
+        fs::write(directory.join("config.toml"),
+                  "extends = [\"profiles/sony.toml\", \"site.toml\"]\n[images]\nend = 3\n")
+            .unwrap();
+        let table = load(&directory.join("config.toml")).unwrap();
+        assert!(!table.contains_key("extends"));
+        assert_eq!(Some(2), table["threads"].as_integer());
+        assert_eq!(Some("sony"), table["images"]["camera"].as_str());
+        assert_eq!(Some(2), table["images"]["start"].as_integer());
+        assert_eq!(Some(3), table["images"]["end"].as_integer());
+        let names = list_in(directory.join("profiles"))
+            .unwrap()
+            .into_iter()
+            .map(|profile| profile.name)
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["base", "sony"], names);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn cycle() {
+        let directory = directory("cycle");
+        fs::write(directory.join("a.toml"), "extends = \"b.toml\"\n").unwrap();
+        fs::write(directory.join("b.toml"), "extends = \"a.toml\"\n").unwrap();
+        assert!(load(&directory.join("a.toml")).is_err());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn missing() {
+        let directory = directory("missing");
+        fs::write(directory.join("a.toml"), "extends = \"no-such-profile\"\n").unwrap();
+        assert!(load(&directory.join("a.toml")).is_err());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
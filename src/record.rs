@@ -3,16 +3,100 @@
 //! We sometimes need to extract/use values from records. Maybe someday we'll populate this
 //! information from the RiPROCESS XML itself, but for now we have to manually transcribe values.
 
-use Result;
+use {Error, Result};
+use std::path::PathBuf;
 
 /// Confguration for records.
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Config {
     /// The start time for each record.
     pub start_times: Vec<f64>,
+    /// The end time for each record.
+    ///
+    /// If empty, each record is assumed to last until the next one starts.
+    pub end_times: Vec<f64>,
+    /// The duration of each record, in seconds, as an alternative to `end_times`.
+    pub durations: Vec<f64>,
+    /// The path to a Records.csv file exported from RiPROCESS.
+    ///
+    /// If provided, start and end times are read from this file instead of from the lists above.
+    /// The file must have a header row. Columns are matched by their whole name, ignoring case:
+    /// "Start(stamp)", "Start time" or "Start" for the start, "End(stamp)", "Stop(stamp)", "End
+    /// time", "Stop time", "End" or "Stop" for the end (or "Duration"), and "Name" if present.
+    /// When several of these are present, the one listed first is used.
+    pub csv: Option<PathBuf>,
+    /// The path to write images that are outside of their record's span to.
+    ///
+    /// If provided, these "orphan" images are left out of the image list and written here
+    /// instead. If None, the `outside_record` validation rule decides whether they are rejected.
+    pub orphan_list: Option<PathBuf>,
+}
+
+/// A single record.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    /// The record's name, if known.
+    pub name: Option<String>,
+    /// The record's start time.
+    pub start: f64,
+    /// The record's end time, if known.
+    pub end: Option<f64>,
 }
 
 impl Config {
+    /// Returns the configured records.
+    ///
+    /// Returns an error if the Records.csv can't be read, or if the number of end times or
+    /// durations doesn't match the number of start times.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::record::Config;
+    /// let config = Config {
+    ///     start_times: vec![332978.669, 333039.279],
+    ///     durations: vec![30., 30.],
+    ///     ..Default::default()
+    /// };
+    /// let records = config.records().unwrap();
+    /// assert_eq!(Some(333008.669), records[0].end);
+    /// ```
+    pub fn records(&self) -> Result<Vec<Record>> {
+        if let Some(ref csv) = self.csv {
+            use std::fs;
+            let contents = fs::read_to_string(csv)?;
+            return parse_csv(&contents)
+                .map_err(|message| {
+                             Error::InvalidRecords(format!("{}: {}", csv.display(), message))
+                         });
+        }
+        let ends = if !self.end_times.is_empty() {
+            check_len(self.end_times.len(), self.start_times.len(), "end times")?;
+            self.end_times.iter().map(|&end| Some(end)).collect()
+        } else if !self.durations.is_empty() {
+            check_len(self.durations.len(), self.start_times.len(), "durations")?;
+            self.start_times
+                .iter()
+                .zip(&self.durations)
+                .map(|(start, duration)| Some(start + duration))
+                .collect()
+        } else {
+            vec![None; self.start_times.len()]
+        };
+        Ok(self.start_times
+               .iter()
+               .zip(ends)
+               .map(|(&start, end)| {
+                        Record {
+                            name: None,
+                            start,
+                            end,
+                        }
+                    })
+               .collect())
+    }
+
     /// Adjust an array of timestamps, using the start times defined in this configurtion.
     ///
     /// Adjustment means:
@@ -38,38 +122,181 @@ impl Config {
     ///
     /// ```
     /// use riprocess::record::Config;
-    /// let config = Config { start_times: vec![332978.669] };
+    /// let config = Config { start_times: vec![332978.669], ..Default::default() };
     /// let timestamps = config.adjust_timestamps(&vec![vec![73779.899441]]).unwrap();
     /// assert_eq!(vec![332979.899441], timestamps);
     /// ```
-    pub fn adjust_timestamps(&self, timestamps: &Vec<Vec<f64>>) -> Result<Vec<f64>> {
-        use Error;
-
-        if self.start_times.len() != timestamps.len() {
-            return Err(Error::RecordCountMismatch {
-                           timestamps: timestamps.len(),
-                           records: self.start_times.len(),
-                       });
-        }
-        let timestamp_bases = timestamps.iter()
-            .map(|timestamps| if timestamps.is_empty() {
-                     Err(Error::NoTimestamps)
-                 } else {
-                     Ok(timestamps[0] - timestamps[0] % 100.)
-                 })
-            .collect::<Result<Vec<_>>>()?;
-        Ok(self.start_times
-               .iter()
-               .zip(timestamp_bases.into_iter())
+    pub fn adjust_timestamps(&self, timestamps: &[Vec<f64>]) -> Result<Vec<f64>> {
+        Ok(self.offsets(timestamps)?
+               .into_iter()
                .zip(timestamps.iter())
-               .flat_map(|((start_time, timestamp_base), timestamps)| {
-                             timestamps.iter().map(move |timestamp| {
-                                                       timestamp - timestamp_base + start_time -
-                                                       start_time % 100.
-                                                   })
+               .flat_map(|(offset, timestamps)| {
+                             timestamps.iter().map(move |timestamp| timestamp + offset)
                          })
                .collect())
     }
+
+    /// Returns the offset that is added to each timestamp vector during adjustment.
+    ///
+    /// The offset is the record base minus the timestamp base, as described in
+    /// `adjust_timestamps`. Errors are the same as for `adjust_timestamps`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::record::Config;
+    /// let config = Config { start_times: vec![332978.669], ..Default::default() };
+    /// let offsets = config.offsets(&vec![vec![73779.899441]]).unwrap();
+    /// assert_eq!(vec![332900. - 73700.], offsets);
+    /// ```
+    pub fn offsets(&self, timestamps: &[Vec<f64>]) -> Result<Vec<f64>> {
+        offsets(&self.records()?, timestamps)
+    }
+}
+
+/// Returns the offset that is added to each timestamp vector, for records that have already been
+/// read.
+///
+/// Errors are the same as for `Config::adjust_timestamps`.
+///
+/// # Examples
+///
+/// ```
+/// use riprocess::record::{self, Record};
+/// let records = vec![Record { name: None, start: 332978.669, end: None }];
+/// let offsets = record::offsets(&records, &vec![vec![73779.899441]]).unwrap();
+/// assert_eq!(vec![332900. - 73700.], offsets);
+/// ```
+pub fn offsets(records: &[Record], timestamps: &[Vec<f64>]) -> Result<Vec<f64>> {
+    if records.len() != timestamps.len() {
+        return Err(Error::RecordCountMismatch {
+                       timestamps: timestamps.len(),
+                       records: records.len(),
+                   });
+    }
+    records.iter()
+        .zip(timestamps.iter())
+        .map(|(record, timestamps)| if timestamps.is_empty() {
+                 Err(Error::NoTimestamps)
+             } else {
+                 Ok(record.offset(timestamps[0]))
+             })
+        .collect()
+}
+
+/// Rounds a time down to its hundred seconds, the base that records and timestamps are matched
+/// on.
+///
+/// # Examples
+///
+/// ```
+/// use riprocess::record;
+/// assert_eq!(332900., record::base(332978.669));
+/// ```
+pub fn base(time: f64) -> f64 {
+    time - time % 100.
+}
+
+impl Record {
+    /// Returns the offset that is added to this record's timestamps, given its first timestamp.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::record::Record;
+    /// let record = Record { name: None, start: 332978.669, end: None };
+    /// assert_eq!(332900. - 73700., record.offset(73779.899441));
+    /// ```
+    pub fn offset(&self, first_timestamp: f64) -> f64 {
+        base(self.start) - base(first_timestamp)
+    }
+
+    /// Returns true if the timestamp is inside of this record's span.
+    ///
+    /// The span ends at the record's end time, inclusive, if it is known. Otherwise, it ends when
+    /// the next record starts, exclusive.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::record::Record;
+    /// let record = Record { name: None, start: 10., end: None };
+    /// let next = Record { name: None, start: 20., end: None };
+    /// assert!(record.contains(15., Some(&next)));
+    /// assert!(!record.contains(20., Some(&next)));
+    /// assert!(record.contains(20., None));
+    /// ```
+    pub fn contains(&self, timestamp: f64, next: Option<&Record>) -> bool {
+        timestamp >= self.start &&
+        match (self.end, next) {
+            (Some(end), _) => timestamp <= end,
+            (None, Some(next)) => timestamp < next.start,
+            (None, None) => true,
+        }
+    }
+
+    /// Returns a description of this record's span, e.g. `[332978.669000, 333008.669000]`.
+    pub fn span(&self, next: Option<&Record>) -> String {
+        match (self.end, next) {
+            (Some(end), _) => format!("[{:.6}, {:.6}]", self.start, end),
+            (None, Some(next)) => format!("[{:.6}, {:.6})", self.start, next.start),
+            (None, None) => format!("[{:.6}, ...)", self.start),
+        }
+    }
+}
+
+fn check_len(len: usize, expected: usize, name: &str) -> Result<()> {
+    if len == expected {
+        Ok(())
+    } else {
+        Err(Error::InvalidRecords(format!("there are {} {} for {} start times",
+                                          len,
+                                          name,
+                                          expected)))
+    }
+}
+
+fn parse_csv(contents: &str) -> std::result::Result<Vec<Record>, String> {
+    let split = |line: &str| -> Vec<String> {
+        let delimiter = if line.contains(';') { ';' } else { ',' };
+        line.split(delimiter).map(|value| value.trim().trim_matches('"').to_string()).collect()
+    };
+    let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+    let header = split(lines.next().ok_or("the file is empty")?)
+        .into_iter()
+        .map(|name| name.to_lowercase())
+        .collect::<Vec<_>>();
+    let column = |names: &[&str]| {
+        names.iter().filter_map(|name| header.iter().position(|column| column == name)).next()
+    };
+    let start = column(&["start(stamp)", "start time", "start"]).ok_or("there is no start column")?;
+    let end = column(&["end(stamp)", "stop(stamp)", "end time", "stop time", "end", "stop"]);
+    let duration = column(&["duration"]);
+    let name = column(&["name"]);
+    lines.enumerate()
+        .map(|(i, line)| {
+            let values = split(line);
+            let value = |column: usize| -> std::result::Result<f64, String> {
+                values.get(column)
+                    .ok_or_else(|| format!("line {}: missing column {}", i + 2, column + 1))
+                    .and_then(|value| {
+                                  value.parse()
+                                      .map_err(|err| format!("line {}: {}: {}", i + 2, value, err))
+                              })
+            };
+            let start = value(start)?;
+            let end = match (end, duration) {
+                (Some(end), _) => Some(value(end)?),
+                (None, Some(duration)) => Some(start + value(duration)?),
+                (None, None) => None,
+            };
+            Ok(Record {
+                   name: name.and_then(|name| values.get(name).cloned()),
+                   start,
+                   end,
+               })
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -80,7 +307,7 @@ mod tests {
     fn from_reference() {
         let timestamps = vec![vec![73779.899441, 73781.419326], vec![73840.399224, 73842.018970]];
         let start_times = vec![332978.669, 333039.279];
-        let config = Config { start_times: start_times };
+        let config = Config { start_times, ..Default::default() };
         let timestamps = config.adjust_timestamps(&timestamps).unwrap();
         assert_eq!(4, timestamps.len());
         let expected = vec![332979.899441, 332981.419326, 333040.399224, 333042.018970];
@@ -92,15 +319,61 @@ mod tests {
 
     #[test]
     fn empty_timestamps() {
-        let config = Config { start_times: vec![1.] };
+        let config = Config { start_times: vec![1.], ..Default::default() };
         let timestamps = vec![vec![]];
         assert!(config.adjust_timestamps(&timestamps).is_err());
     }
 
     #[test]
     fn count_mismatch() {
-        let config = Config { start_times: vec![1., 2.] };
+        let config = Config { start_times: vec![1., 2.], ..Default::default() };
         let timestamps = vec![vec![1.]];
         assert!(config.adjust_timestamps(&timestamps).is_err());
     }
+
+    #[test]
+    fn end_times() {
+        let config = Config {
+            start_times: vec![1., 2.],
+            end_times: vec![1.5],
+            ..Default::default()
+        };
+        assert!(config.records().is_err());
+    }
+
+    #[test]
+    fn csv() {
+        let records = parse_csv("Name;Start time;Duration\n\
+                                 \"Record 1\";332978.669;30.5\n\
+                                 \"Record 2\";333039.279;20\n")
+            .unwrap();
+        assert_eq!(vec![Record {
+                            name: Some("Record 1".to_string()),
+                            start: 332978.669,
+                            end: Some(332978.669 + 30.5),
+                        },
+                        Record {
+                            name: Some("Record 2".to_string()),
+                            start: 333039.279,
+                            end: Some(333039.279 + 20.),
+                        }],
+                   records);
+        let records = parse_csv("Name,Start(time),Start(stamp),End(time),End(stamp)\n\
+                                 r1,20:29:38,1,20:30:00,2\n")
+            .unwrap();
+        assert_eq!(1., records[0].start);
+        assert_eq!(Some(2.), records[0].end);
+        let records = parse_csv("Extended name,Name,Restart,Start,Stopped,End\n\
+                                 x,r1,9,1,9,2\n")
+            .unwrap();
+        assert_eq!(Record {
+                       name: Some("r1".to_string()),
+                       start: 1.,
+                       end: Some(2.),
+                   },
+                   records[0]);
+        assert!(parse_csv("name,end\nr1,2\n").is_err());
+        assert!(parse_csv("restart,end\n1,2\n").is_err());
+        assert!(parse_csv("start,end\n1,x\n").is_err());
+    }
 }
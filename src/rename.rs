@@ -0,0 +1,504 @@
+//! Timestamp-based image renaming.
+//!
+//! Camera file names like `DSC03522.JPG` collide between cameras and missions, and they say
+//! nothing about when the image was taken. This module builds new file names from a template,
+//! e.g. `{camera}_{record}_{gps_time:.3}_{image_number}.jpg`, and renames or copies each image.
+//!
+//! Every rename is written to a log, so that it can be undone with `undo`.
+
+use {Error, Image, Result, organize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The default file name template.
+pub const DEFAULT_TEMPLATE: &str = "{camera}_{record}_{gps_time:.3}_{image_number}.jpg";
+
+/// The first line of every rename log.
+const LOG_HEADER: &str = "# riprocess rename log";
+
+/// Configuration for renaming images.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    /// The file name template.
+    ///
+    /// Values in braces are replaced, and can have a format specification after a colon:
+    ///
+    /// - `{camera}`: the camera name from the image configuration.
+    /// - `{record}`: the record number, starting at one.
+    /// - `{gps_time}`: the adjusted timestamp, e.g. `{gps_time:.3}` for three decimal places.
+    /// - `{image_number}`: the image number of the original image file, e.g. `{image_number:05}`
+    ///   for zero-padding.
+    /// - `{stem}`: the original file name without its extension.
+    ///
+    /// Use `{{` and `}}` for literal braces.
+    pub template: String,
+    /// Whether images are renamed in place or copied.
+    pub mode: Mode,
+    /// The directory that copies are written to.
+    ///
+    /// If None, copies are written next to the original images. Ignored when renaming in place.
+    pub destination: Option<PathBuf>,
+    /// The path of the rename log.
+    pub log: PathBuf,
+}
+
+/// Whether images are renamed in place or copied.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Rename the original image files.
+    Rename,
+    /// Copy the images to their new names, leaving the originals alone.
+    Copy,
+}
+
+/// A single renamed image.
+#[derive(Debug, PartialEq)]
+pub struct Rename {
+    /// The original image path.
+    pub source: PathBuf,
+    /// The image, with its new path.
+    pub image: Image,
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Literal(String),
+    Value { name: String, spec: Spec },
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Spec {
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            template: DEFAULT_TEMPLATE.to_string(),
+            mode: Mode::Rename,
+            destination: None,
+            log: "riprocess-rename.log".into(),
+        }
+    }
+}
+
+impl Config {
+    /// Creates a new, default configuration.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::rename::Config;
+    /// let config = Config::new();
+    /// ```
+    pub fn new() -> Config {
+        Default::default()
+    }
+
+    /// Returns the new file name for an image.
+    ///
+    /// Returns an error if the template is malformed, or uses `{camera}` without a camera.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::{Image, Source};
+    /// use riprocess::rename::Config;
+    /// let image = Image {
+    ///     path: "data/images/DSC03522.JPG".into(),
+    ///     timestamp: 332979.899441,
+    ///     record: 0,
+    ///     source: Source { image_number: 3522, ..Default::default() },
+    /// };
+    /// let file_name = Config::new().file_name(&image, Some("sony")).unwrap();
+    /// assert_eq!("sony_1_332979.899_3522.jpg", file_name);
+    /// ```
+    pub fn file_name(&self, image: &Image, camera: Option<&str>) -> Result<String> {
+        let mut file_name = String::new();
+        for token in parse(&self.template)? {
+            match token {
+                Token::Literal(literal) => file_name.push_str(&literal),
+                Token::Value { name, spec } => {
+                    let value = match name.as_str() {
+                        "camera" => {
+                            camera.map(|camera| spec.text(camera))
+                                .ok_or_else(|| {
+                                                invalid("{camera} is used, but no camera is \
+                                                         configured")
+                                            })?
+                        }
+                        "record" => spec.integer(image.record + 1),
+                        "gps_time" => spec.float(image.timestamp),
+                        "image_number" => spec.integer(image.source.image_number),
+                        "stem" => {
+                            image.path
+                                .file_stem()
+                                .map(|stem| spec.text(&stem.to_string_lossy()))
+                                .ok_or_else(|| Error::InvalidImagePath(image.path.clone()))?
+                        }
+                        _ => return Err(invalid(&format!("unknown value {{{}}}", name))),
+                    };
+                    file_name.push_str(&value);
+                }
+            }
+        }
+        Ok(file_name)
+    }
+
+    /// Renames or copies each image to its new name, and writes the rename log.
+    ///
+    /// All new names are computed and checked before any file is touched: an error is returned if
+    /// two images would get the same name or if a file already exists at a new path. If `dry_run`
+    /// is true, nothing is written to disk.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use riprocess::Config;
+    /// use riprocess::rename;
+    /// let config = Config::from_path("data/config.toml").unwrap();
+    /// let images = config.image_list().unwrap();
+    /// let renames = rename::Config::new().rename(images, Some("sony"), true).unwrap();
+    /// assert!(renames[0].image.path.ends_with("sony_1_332979.899_3522.jpg"));
+    /// ```
+    pub fn rename<I>(&self, images: I, camera: Option<&str>, dry_run: bool) -> Result<Vec<Rename>>
+        where I: IntoIterator<Item = Image>
+    {
+        use std::collections::HashSet;
+        use std::io::Write;
+
+        let renames = images.into_iter()
+            .map(|image| {
+                let file_name = self.file_name(&image, camera)?;
+                let path = match (self.mode, self.destination.as_ref()) {
+                    (Mode::Copy, Some(destination)) => destination.join(file_name),
+                    _ => image.path.with_file_name(file_name),
+                };
+                Ok(Rename {
+                       source: image.path.clone(),
+                       image: Image { path, ..image },
+                   })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut paths = HashSet::new();
+        for rename in &renames {
+            if !paths.insert(&rename.image.path) || rename.image.path.exists() {
+                return Err(Error::DestinationExists(rename.image.path.clone()));
+            }
+        }
+        if dry_run {
+            return Ok(renames);
+        }
+        let mut log = fs::File::create(&self.log)?;
+        writeln!(log, "{}", LOG_HEADER)?;
+        for rename in &renames {
+            let source = rename.source.canonicalize()?;
+            if let Some(parent) = rename.image.path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            match self.mode {
+                Mode::Rename => fs::rename(&source, &rename.image.path)?,
+                Mode::Copy => fs::copy(&source, &rename.image.path).map(|_| ())?,
+            }
+            let destination = rename.image.path.canonicalize()?;
+            writeln!(log,
+                     "{}\t{}\t{}\t{}\t{:08x}",
+                     self.mode.as_str(),
+                     source.display(),
+                     destination.display(),
+                     fs::metadata(&destination)?.len(),
+                     organize::checksum(&destination)?)?;
+            log.flush()?;
+        }
+        Ok(renames)
+    }
+}
+
+impl Mode {
+    fn as_str(&self) -> &'static str {
+        match *self {
+            Mode::Rename => "rename",
+            Mode::Copy => "copy",
+        }
+    }
+}
+
+impl Spec {
+    fn text(&self, value: &str) -> String {
+        format!("{:>width$}", value, width = self.width)
+    }
+
+    fn integer(&self, value: usize) -> String {
+        if self.zero {
+            format!("{:0width$}", value, width = self.width)
+        } else {
+            format!("{:width$}", value, width = self.width)
+        }
+    }
+
+    fn float(&self, value: f64) -> String {
+        let precision = self.precision.unwrap_or(6);
+        if self.zero {
+            format!("{:0width$.precision$}", value, width = self.width, precision = precision)
+        } else {
+            format!("{:width$.precision$}", value, width = self.width, precision = precision)
+        }
+    }
+}
+
+/// Undoes the renames recorded in a rename log, in reverse order.
+///
+/// Renamed images are renamed back to their original names, and copies are deleted. Returns the
+/// number of undone operations. The log itself is left in place.
+///
+/// Every entry is checked before anything is touched: each destination must still exist, each
+/// renamed image's original name must be free, and each copy must still have the size and
+/// checksum that were logged, so a copy that was edited afterwards is never deleted. If any
+/// entry fails its check, nothing is undone.
+///
+/// # Examples
+///
+/// ```no_run
+/// use riprocess::rename;
+/// let count = rename::undo("riprocess-rename.log").unwrap();
+/// ```
+pub fn undo<P: AsRef<Path>>(log: P) -> Result<usize> {
+    let contents = fs::read_to_string(log)?;
+    let mut lines = contents.lines();
+    if lines.next() != Some(LOG_HEADER) {
+        return Err(Error::InvalidRenameLog(format!("missing header \"{}\"", LOG_HEADER)));
+    }
+    let entries = lines.filter(|line| !line.is_empty())
+        .map(|line| {
+            let fields = line.split('\t').collect::<Vec<_>>();
+            if fields.len() != 5 || (fields[0] != "rename" && fields[0] != "copy") {
+                return Err(Error::InvalidRenameLog(format!("invalid line: {}", line)));
+            }
+            let invalid = |_| Error::InvalidRenameLog(format!("invalid line: {}", line));
+            Ok(Entry {
+                   mode: fields[0],
+                   source: PathBuf::from(fields[1]),
+                   destination: PathBuf::from(fields[2]),
+                   size: fields[3].parse().map_err(invalid)?,
+                   checksum: u32::from_str_radix(fields[4], 16).map_err(invalid)?,
+               })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    for entry in &entries {
+        entry.check()?;
+    }
+    for entry in entries.iter().rev() {
+        if entry.mode == "rename" {
+            fs::rename(&entry.destination, &entry.source)?;
+        } else {
+            fs::remove_file(&entry.destination)?;
+        }
+    }
+    Ok(entries.len())
+}
+
+/// A single line of a rename log.
+struct Entry<'a> {
+    mode: &'a str,
+    source: PathBuf,
+    destination: PathBuf,
+    size: u64,
+    checksum: u32,
+}
+
+impl<'a> Entry<'a> {
+    /// Checks that this entry can be undone.
+    fn check(&self) -> Result<()> {
+        if !self.destination.exists() {
+            return Err(Error::InvalidRenameLog(format!("{} no longer exists",
+                                                       self.destination.display())));
+        }
+        if self.mode == "rename" {
+            if self.source.exists() {
+                return Err(Error::DestinationExists(self.source.clone()));
+            }
+        } else if fs::metadata(&self.destination)?.len() != self.size ||
+                  organize::checksum(&self.destination)? != self.checksum {
+            return Err(Error::InvalidRenameLog(format!("{} has changed since it was copied",
+                                                       self.destination.display())));
+        }
+        Ok(())
+    }
+}
+
+fn parse(template: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '{' => {
+                if !literal.is_empty() {
+                    tokens.push(Token::Literal(literal.split_off(0)));
+                }
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => value.push(c),
+                        None => return Err(invalid("unclosed '{'")),
+                    }
+                }
+                let mut parts = value.splitn(2, ':');
+                let name = parts.next().unwrap_or("").trim().to_string();
+                let spec = parse_spec(parts.next().unwrap_or(""))?;
+                tokens.push(Token::Value { name, spec });
+            }
+            '}' => return Err(invalid("unmatched '}'")),
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        tokens.push(Token::Literal(literal));
+    }
+    Ok(tokens)
+}
+
+fn parse_spec(spec: &str) -> Result<Spec> {
+    let (width, precision) = match spec.find('.') {
+        Some(i) => (&spec[..i], Some(&spec[i + 1..])),
+        None => (spec, None),
+    };
+    let error = || invalid(&format!("invalid format specification \"{}\"", spec));
+    Ok(Spec {
+           zero: width.starts_with('0'),
+           width: if width.is_empty() {
+               0
+           } else {
+               width.parse().map_err(|_| error())?
+           },
+           precision: match precision {
+               Some(precision) => Some(precision.parse().map_err(|_| error())?),
+               None => None,
+           },
+       })
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidTemplate(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Source;
+
+    fn image() -> Image {
+        Image {
+            path: "data/images/DSC03522.JPG".into(),
+            timestamp: 332979.899441,
+            record: 1,
+            source: Source { image_number: 3522, ..Default::default() },
+        }
+    }
+
+    fn file_name(template: &str) -> Result<String> {
+        let config = Config { template: template.to_string(), ..Default::default() };
+        config.file_name(&image(), Some("cam"))
+    }
+
+    #[test]
+    fn specs() {
+        assert_eq!("2_332980_03522_DSC03522.jpg",
+                   file_name("{record}_{gps_time:.0}_{image_number:05}_{stem}.jpg").unwrap());
+        assert_eq!("{cam}", file_name("{{{camera}}}").unwrap());
+    }
+
+    #[test]
+    fn renamed_image_number() {
+        let config = Config { template: "{image_number:05}.jpg".to_string(), ..Default::default() };
+        let image = Image { path: "cam_2_332979.899_3522.jpg".into(), ..image() };
+        assert_eq!("03522.jpg", config.file_name(&image, None).unwrap());
+    }
+
+    #[test]
+    fn invalid_templates() {
+        assert!(file_name("{camera").is_err());
+        assert!(file_name("camera}").is_err());
+        assert!(file_name("{lens}").is_err());
+        assert!(file_name("{gps_time:.x}").is_err());
+        assert!(Config::new().file_name(&image(), None).is_err());
+    }
+
+    #[test]
+    fn collisions() {
+        let config = Config { template: "{camera}.jpg".to_string(), ..Default::default() };
+        let images = vec![image(), Image { path: "data/images/DSC03523.JPG".into(), ..image() }];
+        assert!(config.rename(images, Some("cam"), true).is_err());
+    }
+
+    #[test]
+    fn rename_and_undo() {
+        let directory = std::env::temp_dir().join("riprocess-rename");
+        if directory.exists() {
+            fs::remove_dir_all(&directory).unwrap();
+        }
+        fs::create_dir_all(&directory).unwrap();
+        let source = directory.join("DSC03522.JPG");
+        fs::copy("data/images/DSC03522.JPG", &source).unwrap();
+        let config = Config { log: directory.join("rename.log"), ..Default::default() };
+        let images = vec![Image { path: source.clone(), ..image() }];
+
+        let renames = config.rename(images, Some("cam"), false).unwrap();
+        assert!(!source.exists());
+        assert!(renames[0].image.path.exists());
+        fs::write(&source, b"in the way").unwrap();
+        assert!(undo(&config.log).is_err());
+        assert!(renames[0].image.path.exists());
+        fs::remove_file(&source).unwrap();
+        assert_eq!(1, undo(&config.log).unwrap());
+        assert!(source.exists());
+        assert!(!renames[0].image.path.exists());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn copy_and_undo() {
+        let directory = std::env::temp_dir().join("riprocess-rename-copy");
+        if directory.exists() {
+            fs::remove_dir_all(&directory).unwrap();
+        }
+        let config = Config {
+            mode: Mode::Copy,
+            destination: Some(directory.join("renamed")),
+            log: directory.join("rename.log"),
+            ..Default::default()
+        };
+        fs::create_dir_all(&directory).unwrap();
+        let images = vec![image(),
+                          Image {
+                              path: "data/images/DSC03523.JPG".into(),
+                              source: Source { image_number: 3523, ..Default::default() },
+                              ..image()
+                          }];
+        let renames = config.rename(images, Some("cam"), false).unwrap();
+        assert!(renames[0].image.path.exists());
+        assert!(Path::new("data/images/DSC03522.JPG").exists());
+        fs::write(&renames[0].image.path, b"edited").unwrap();
+        assert!(undo(&config.log).is_err());
+        assert!(renames[0].image.path.exists());
+        assert!(renames[1].image.path.exists());
+        fs::copy("data/images/DSC03522.JPG", &renames[0].image.path).unwrap();
+        assert_eq!(2, undo(&config.log).unwrap());
+        assert!(!renames[0].image.path.exists());
+        assert!(Path::new("data/images/DSC03522.JPG").exists());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,160 @@
+//! Warnings collected while building an image list.
+//!
+//! Problems that don't stop an image list from being built, like a skipped directory entry or a
+//! timestamp that breaks a warning-level validation rule, are added to a `Report`. The run mode
+//! decides what happens to them: by default they are only reported, in strict mode any warning
+//! fails the run, and in lenient mode some problems that would otherwise be errors are repaired
+//! and reported instead.
+
+use std::fmt;
+
+/// How warnings and recoverable problems are handled.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Warnings are reported, and recoverable problems are errors.
+    #[default]
+    Normal,
+    /// Warnings are errors.
+    Strict,
+    /// Recoverable problems are repaired, and reported as warnings.
+    ///
+    /// Lines in timestamp files that aren't timestamps are skipped, empty timestamp files are
+    /// dropped along with their records, and records without a timestamp file are dropped.
+    Lenient,
+}
+
+/// The stage of the pipeline that a warning comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    /// Listing the image and timestamp directories.
+    Scan,
+    /// Reading the timestamp files.
+    Parse,
+    /// Pairing timestamp files with records, and adjusting their timestamps.
+    Records,
+    /// Checking the timestamps against the validation rules.
+    Validation,
+    /// Checking the image files.
+    Integrity,
+    /// Pairing images with timestamps.
+    Pairing,
+}
+
+/// A single warning.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Warning {
+    /// The stage that the warning comes from.
+    pub stage: Stage,
+    /// A human-readable description of the problem.
+    pub message: String,
+    /// What was done to repair the problem, in lenient mode.
+    ///
+    /// None if nothing was repaired.
+    pub repair: Option<String>,
+}
+
+/// The warnings collected while building an image list, in the order they were found.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Report {
+    warnings: Vec<Warning>,
+}
+
+impl Report {
+    /// Creates a new, empty report.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::report::Report;
+    /// let report = Report::new();
+    /// assert!(report.is_empty());
+    /// ```
+    pub fn new() -> Report {
+        Default::default()
+    }
+
+    /// Adds a warning.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::report::{Report, Stage};
+    /// let mut report = Report::new();
+    /// report.warn(Stage::Scan, "skipped dsc03522.jpg");
+    /// assert_eq!("scan: skipped dsc03522.jpg", report.warnings()[0].to_string());
+    /// ```
+    pub fn warn<S: Into<String>>(&mut self, stage: Stage, message: S) {
+        self.warnings.push(Warning {
+                               stage,
+                               message: message.into(),
+                               repair: None,
+                           });
+    }
+
+    /// Adds a warning for a problem that was repaired.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::report::{Report, Stage};
+    /// let mut report = Report::new();
+    /// report.repaired(Stage::Parse, "a.eif:3: \"\" isn't a timestamp", "skipped the line");
+    /// assert_eq!(1, report.repairs());
+    /// ```
+    pub fn repaired<S, T>(&mut self, stage: Stage, message: S, repair: T)
+        where S: Into<String>,
+              T: Into<String>
+    {
+        self.warnings.push(Warning {
+                               stage,
+                               message: message.into(),
+                               repair: Some(repair.into()),
+                           });
+    }
+
+    /// Returns the warnings.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Returns the number of warnings for problems that were repaired.
+    pub fn repairs(&self) -> usize {
+        self.warnings.iter().filter(|warning| warning.repair.is_some()).count()
+    }
+
+    /// Returns true if there are no warnings.
+    pub fn is_empty(&self) -> bool {
+        self.warnings.is_empty()
+    }
+
+    /// Returns the warnings, consuming the report.
+    pub fn into_warnings(self) -> Vec<Warning> {
+        self.warnings
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Stage::Scan => "scan",
+            Stage::Parse => "parse",
+            Stage::Records => "records",
+            Stage::Validation => "validation",
+            Stage::Integrity => "integrity",
+            Stage::Pairing => "pairing",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.stage, self.message)?;
+        if let Some(ref repair) = self.repair {
+            write!(f, " ({})", repair)?;
+        }
+        Ok(())
+    }
+}
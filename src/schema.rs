@@ -0,0 +1,409 @@
+//! The shape of the configuration file.
+//!
+//! The schema is read from the configuration types' `Deserialize` implementations, by running
+//! them against a deserializer that records what they ask for instead of reading a file. That
+//! way the schema can't drift from the types.
+
+use Config;
+use serde::de::{self, Deserialize, DeserializeSeed, Deserializer, EnumAccess, IntoDeserializer,
+                MapAccess, SeqAccess, VariantAccess, Visitor};
+use serde::de::value::{Error, StrDeserializer};
+
+/// The shape of a configuration value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Schema {
+    /// `true` or `false`.
+    Boolean,
+    /// A whole number.
+    Integer,
+    /// A number, which can be written as a whole number.
+    Float,
+    /// A string, including paths.
+    String,
+    /// An array whose values all have the same shape.
+    Array(Box<Schema>),
+    /// An array with a fixed number of values, e.g. a `[start, end]` range.
+    Tuple(Vec<Schema>),
+    /// A value that can be left out, without a default.
+    Optional(Box<Schema>),
+    /// One of a fixed set of strings.
+    Enum(Vec<&'static str>),
+    /// A table with a fixed set of keys, in declaration order.
+    Table(Vec<(&'static str, Schema)>),
+}
+
+impl Schema {
+    /// Returns the schema of the configuration file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::schema::Schema;
+    /// let schema = Schema::config();
+    /// assert_eq!(Some(&Schema::Optional(Box::new(Schema::Integer))),
+    ///            schema.get("images").and_then(|images| images.get("start")));
+    /// ```
+    pub fn config() -> Schema {
+        Schema::of::<Config>()
+    }
+
+    /// Returns the schema of a type that deserializes from a configuration file.
+    ///
+    /// Panics if the type asks for something that can't be written in a configuration file, e.g.
+    /// a map with arbitrary keys.
+    pub fn of<T>() -> Schema
+        where T: for<'de> Deserialize<'de>
+    {
+        let mut schema = None;
+        if let Err(err) = T::deserialize(Probe(&mut schema)) {
+            panic!("the type can't be described by a schema: {}", err);
+        }
+        schema.expect("deserializing always records a schema")
+    }
+
+    /// Returns the configuration file's JSON Schema, for editor autocompletion.
+    ///
+    /// Besides the configuration's own keys, the schema allows the top-level `extends` key used
+    /// by profiles.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::schema::Schema;
+    /// let json = Schema::config_json();
+    /// assert_eq!("integer", json["properties"]["images"]["properties"]["start"]["type"]);
+    /// ```
+    pub fn config_json() -> serde_json::Value {
+        let mut json = Schema::config().to_json();
+        json["$schema"] = json!("http://json-schema.org/draft-07/schema#");
+        json["title"] = json!("riprocess configuration");
+        json["properties"]["extends"] = json!({
+            "description": "Profiles to merge in before this file",
+            "anyOf": [
+                { "type": "string" },
+                { "type": "array", "items": { "type": "string" } },
+            ],
+        });
+        json
+    }
+
+    /// Returns this schema as a JSON Schema.
+    ///
+    /// Tables don't allow unknown keys, and no key is marked as required, since every key either
+    /// has a default or is optional when writing a file in an editor.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::schema::Schema;
+    /// let json = Schema::Array(Box::new(Schema::Float)).to_json();
+    /// assert_eq!("number", json["items"]["type"]);
+    /// ```
+    pub fn to_json(&self) -> serde_json::Value {
+        match *self {
+            Schema::Boolean => json!({ "type": "boolean" }),
+            Schema::Integer => json!({ "type": "integer" }),
+            Schema::Float => json!({ "type": "number" }),
+            Schema::String => json!({ "type": "string" }),
+            Schema::Array(ref schema) => json!({ "type": "array", "items": schema.to_json() }),
+            Schema::Tuple(ref schemas) => {
+                json!({
+                    "type": "array",
+                    "items": schemas.iter().map(Schema::to_json).collect::<Vec<_>>(),
+                    "minItems": schemas.len(),
+                    "maxItems": schemas.len(),
+                })
+            }
+            Schema::Optional(ref schema) => schema.to_json(),
+            Schema::Enum(ref variants) => json!({ "type": "string", "enum": variants }),
+            Schema::Table(ref fields) => {
+                let properties = fields.iter()
+                    .map(|&(name, ref schema)| (name.to_string(), schema.to_json()))
+                    .collect::<serde_json::Map<_, _>>();
+                json!({
+                    "type": "object",
+                    "properties": properties,
+                    "additionalProperties": false,
+                })
+            }
+        }
+    }
+
+    /// Returns the schema of a key in a table, looking through an optional table.
+    pub fn get(&self, key: &str) -> Option<&Schema> {
+        match *self {
+            Schema::Table(ref fields) => {
+                fields.iter().find(|&&(name, _)| name == key).map(|(_, schema)| schema)
+            }
+            Schema::Optional(ref schema) => schema.get(key),
+            _ => None,
+        }
+    }
+
+    /// Returns a short description of the values this schema accepts, e.g. `an integer`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::schema::Schema;
+    /// assert_eq!("an integer", Schema::Integer.description());
+    /// assert_eq!("one of \"copy\", \"link\"",
+    ///            Schema::Enum(vec!["copy", "link"]).description());
+    /// ```
+    pub fn description(&self) -> String {
+        match *self {
+            Schema::Boolean => "true or false".to_string(),
+            Schema::Integer => "an integer".to_string(),
+            Schema::Float => "a number".to_string(),
+            Schema::String => "a string".to_string(),
+            Schema::Array(_) => "an array".to_string(),
+            Schema::Tuple(ref schemas) => format!("an array of {} values", schemas.len()),
+            Schema::Optional(ref schema) => schema.description(),
+            Schema::Enum(ref variants) => {
+                format!("one of {}",
+                        variants.iter()
+                            .map(|variant| format!("{:?}", variant))
+                            .collect::<Vec<_>>()
+                            .join(", "))
+            }
+            Schema::Table(_) => "a section".to_string(),
+        }
+    }
+}
+
+/// A deserializer that records the schema of whatever is deserialized from it.
+struct Probe<'a>(&'a mut Option<Schema>);
+
+impl<'de, 'a> Deserializer<'de> for Probe<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Error> {
+        Err(de::Error::custom("only fixed shapes can be described"))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        *self.0 = Some(Schema::Boolean);
+        visitor.visit_bool(false)
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_i64(visitor)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        *self.0 = Some(Schema::Integer);
+        visitor.visit_i64(0)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_u64(visitor)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        *self.0 = Some(Schema::Integer);
+        visitor.visit_u64(0)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_f64(visitor)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        *self.0 = Some(Schema::Float);
+        visitor.visit_f64(0.)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        *self.0 = Some(Schema::String);
+        visitor.visit_str("")
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let mut inner = None;
+        let value = visitor.visit_some(Probe(&mut inner))?;
+        *self.0 = inner.map(|inner| Schema::Optional(Box::new(inner)));
+        Ok(value)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let mut slots = vec![None];
+        let value = visitor.visit_seq(ProbeSeq { slots: &mut slots, next: 0 })?;
+        *self.0 = slots.pop().and_then(|slot| slot).map(|inner| Schema::Array(Box::new(inner)));
+        Ok(value)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        let mut slots = vec![None; len];
+        let value = visitor.visit_seq(ProbeSeq { slots: &mut slots, next: 0 })?;
+        *self.0 = slots.into_iter().collect::<Option<Vec<_>>>().map(Schema::Tuple);
+        Ok(value)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self,
+                                           _: &'static str,
+                                           fields: &'static [&'static str],
+                                           visitor: V)
+                                           -> Result<V::Value, Error> {
+        let mut schemas = Vec::new();
+        let value = visitor.visit_map(ProbeMap {
+                                          fields,
+                                          schemas: &mut schemas,
+                                      })?;
+        *self.0 = Some(Schema::Table(schemas));
+        Ok(value)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self,
+                                         _: &'static str,
+                                         variants: &'static [&'static str],
+                                         visitor: V)
+                                         -> Result<V::Value, Error> {
+        *self.0 = Some(Schema::Enum(variants.to_vec()));
+        visitor.visit_enum(ProbeEnum(variants[0]))
+    }
+
+    forward_to_deserialize_any! {
+        bytes byte_buf unit unit_struct newtype_struct tuple_struct map identifier ignored_any
+    }
+}
+
+/// Yields one value into each slot.
+struct ProbeSeq<'a> {
+    slots: &'a mut [Option<Schema>],
+    next: usize,
+}
+
+impl<'de, 'a> SeqAccess<'de> for ProbeSeq<'a> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Error>
+        where T: DeserializeSeed<'de>
+    {
+        match self.slots.get_mut(self.next) {
+            Some(slot) => {
+                self.next += 1;
+                seed.deserialize(Probe(slot)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Yields every field of a struct, recording the schema of each.
+struct ProbeMap<'a> {
+    fields: &'static [&'static str],
+    schemas: &'a mut Vec<(&'static str, Schema)>,
+}
+
+impl<'de, 'a> MapAccess<'de> for ProbeMap<'a> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+        where K: DeserializeSeed<'de>
+    {
+        match self.fields.get(self.schemas.len()) {
+            Some(&field) => {
+                let key: StrDeserializer<Error> = field.into_deserializer();
+                seed.deserialize(key).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+        where V: DeserializeSeed<'de>
+    {
+        let field = self.fields[self.schemas.len()];
+        let mut schema = None;
+        let value = seed.deserialize(Probe(&mut schema))?;
+        let schema = schema.ok_or_else(|| de::Error::custom(format!("{} has no shape", field)))?;
+        self.schemas.push((field, schema));
+        Ok(value)
+    }
+}
+
+/// Picks the first variant of a unit-only enum.
+struct ProbeEnum(&'static str);
+
+impl<'de> EnumAccess<'de> for ProbeEnum {
+    type Error = Error;
+    type Variant = ProbeEnum;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, ProbeEnum), Error>
+        where V: DeserializeSeed<'de>
+    {
+        let variant: StrDeserializer<Error> = self.0.into_deserializer();
+        seed.deserialize(variant).map(|value| (value, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for ProbeEnum {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, _: T) -> Result<T::Value, Error>
+        where T: DeserializeSeed<'de>
+    {
+        Err(de::Error::custom("only unit variants can be described"))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _: usize, _: V) -> Result<V::Value, Error> {
+        Err(de::Error::custom("only unit variants can be described"))
+    }
+
+    fn struct_variant<V>(self, _: &'static [&'static str], _: V) -> Result<V::Value, Error>
+        where V: Visitor<'de>
+    {
+        Err(de::Error::custom("only unit variants can be described"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config() {
+        let schema = Schema::config();
+        assert_eq!(Some(&Schema::Array(Box::new(Schema::Float))),
+                   schema.get("records").and_then(|records| records.get("start_times")));
+        assert_eq!(Some(&Schema::Array(Box::new(Schema::Tuple(vec![Schema::Integer,
+                                                                 Schema::Integer])))),
+                   schema.get("exclude").and_then(|exclude| exclude.get("image_ranges")));
+        assert_eq!(Some(&Schema::Enum(vec!["copy", "hardlink", "symlink"])),
+                   schema.get("organize").and_then(|organize| organize.get("method")));
+        assert_eq!(Some(&Schema::Enum(vec!["eif", "csv", "novatel"])),
+                   schema.get("timestamps").and_then(|timestamps| timestamps.get("format")));
+        assert_eq!(Some(&Schema::Integer),
+                   schema.get("trajectory")
+                       .and_then(|trajectory| trajectory.get("columns"))
+                       .and_then(|columns| columns.get("yaw")));
+    }
+}
@@ -0,0 +1,315 @@
+//! XMP sidecar files.
+//!
+//! Some clients won't accept modified originals, so instead of writing into the images (see the
+//! `geotag` module) we can write a `.xmp` file next to each one. Photogrammetry packages pick up
+//! sidecars with the same file stem as the image, e.g. `DSC03522.xmp` for `DSC03522.JPG`.
+
+use {Image, Result, xmp};
+use std::fs;
+use std::path::PathBuf;
+use trajectory::{Pose, Trajectory};
+use xmp::{Namespace, Property, RIPROCESS};
+
+const EXIF: Namespace = Namespace {
+    prefix: "exif",
+    uri: "http://ns.adobe.com/exif/1.0/",
+};
+
+const PIX4D: Namespace = Namespace {
+    prefix: "Camera",
+    uri: "http://pix4d.com/camera/1.0/",
+};
+
+const DJI: Namespace = Namespace {
+    prefix: "drone-dji",
+    uri: "http://www.dji.com/drone-dji/1.0/",
+};
+
+const REALITY_CAPTURE: Namespace = Namespace {
+    prefix: "xcr",
+    uri: "http://www.capturingreality.com/ns/xcr/1.1#",
+};
+
+/// Configuration for writing XMP sidecars.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    /// The directory to write the sidecars to.
+    ///
+    /// If None, each sidecar is written next to its image.
+    pub directory: Option<PathBuf>,
+    /// The packages to write camera tags for.
+    pub packages: Vec<Package>,
+}
+
+/// A photogrammetry package that reads camera poses from XMP.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Package {
+    /// Pix4D, which reads the `Camera` namespace.
+    Pix4d,
+    /// Agisoft Metashape, which reads DJI's `drone-dji` namespace.
+    Metashape,
+    /// RealityCapture, which reads the `xcr` namespace.
+    ///
+    /// RealityCapture wants the rotation in its own camera frame, which we can't know from the
+    /// trajectory, so only the position is written.
+    #[serde(rename = "realitycapture")]
+    RealityCapture,
+}
+
+/// A written, or to-be-written, sidecar.
+#[derive(Debug)]
+pub struct Sidecar {
+    /// The path to the sidecar.
+    pub path: PathBuf,
+    /// The image that the sidecar describes.
+    pub image: Image,
+    /// The image's pose, if there was a trajectory and the image is inside of it.
+    pub pose: Option<Pose>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            directory: None,
+            packages: vec![Package::Pix4d, Package::Metashape, Package::RealityCapture],
+        }
+    }
+}
+
+impl Config {
+    /// Creates a new, default configuration.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::sidecar::Config;
+    /// let config = Config::new();
+    /// ```
+    pub fn new() -> Config {
+        Default::default()
+    }
+
+    /// Returns the path of an image's sidecar.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use riprocess::Image;
+    /// use riprocess::sidecar::Config;
+    /// let image = Image {
+    ///     path: "images/DSC03522.JPG".into(),
+    ///     timestamp: 332979.899441,
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(std::path::Path::new("images/DSC03522.xmp"), Config::new().path(&image));
+    /// ```
+    pub fn path(&self, image: &Image) -> PathBuf {
+        match self.directory {
+            Some(ref directory) => {
+                let mut file_name = image.path.file_stem().unwrap_or_default().to_os_string();
+                file_name.push(".xmp");
+                directory.join(file_name)
+            }
+            None => image.path.with_extension("xmp"),
+        }
+    }
+
+    /// Returns the sidecar's `rdf:Description` element for an image.
+    pub fn description(&self, image: &Image, camera: Option<&str>, pose: Option<&Pose>) -> String {
+        let mut properties = vec![Property::new(RIPROCESS,
+                                                "GpsSecondsOfWeek",
+                                                format!("{:.6}", image.timestamp)),
+                                  Property::new(RIPROCESS, "Record", image.record + 1)];
+        if let Some(camera) = camera {
+            properties.push(Property::new(RIPROCESS, "Camera", camera));
+        }
+        if let Some(pose) = pose {
+            properties.push(Property::new(EXIF,
+                                          "GPSLatitude",
+                                          coordinate(pose.latitude, 'N', 'S')));
+            properties.push(Property::new(EXIF,
+                                          "GPSLongitude",
+                                          coordinate(pose.longitude, 'E', 'W')));
+            properties.push(Property::new(EXIF,
+                                          "GPSAltitude",
+                                          format!("{}/1000",
+                                                  (pose.height.abs() * 1000.).round())));
+            properties.push(Property::new(EXIF,
+                                          "GPSAltitudeRef",
+                                          if pose.height < 0. { 1 } else { 0 }));
+            for package in &self.packages {
+                properties.extend(package.properties(pose));
+            }
+        }
+        xmp::description(&properties)
+    }
+
+    /// Writes a sidecar for each image.
+    ///
+    /// If a trajectory is provided, each image's pose is interpolated from it. If a sidecar
+    /// already exists, our description is added to it (or replaces one we wrote earlier) and the
+    /// rest of the file is kept.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use riprocess::Config;
+    /// use riprocess::sidecar;
+    /// let config = Config::from_path("data/config.toml").unwrap();
+    /// let images = config.image_list().unwrap();
+    /// let sidecars = sidecar::Config::new().write(images, None, None, true).unwrap();
+    /// assert!(sidecars[0].path.ends_with("DSC03522.xmp"));
+    /// ```
+    pub fn write<I>(&self,
+                    images: I,
+                    camera: Option<&str>,
+                    trajectory: Option<&Trajectory>,
+                    dry_run: bool)
+                    -> Result<Vec<Sidecar>>
+        where I: IntoIterator<Item = Image>
+    {
+        let sidecars = images.into_iter()
+            .map(|image| {
+                     Sidecar {
+                         path: self.path(&image),
+                         pose: trajectory.and_then(|trajectory| trajectory.pose(image.timestamp)),
+                         image,
+                     }
+                 })
+            .collect::<Vec<_>>();
+        if dry_run {
+            return Ok(sidecars);
+        }
+        if let Some(ref directory) = self.directory {
+            fs::create_dir_all(directory)?;
+        }
+        for sidecar in &sidecars {
+            let description = self.description(&sidecar.image, camera, sidecar.pose.as_ref());
+            let contents = if sidecar.path.exists() {
+                xmp::merge(&fs::read_to_string(&sidecar.path)?, &description, RIPROCESS)
+            } else {
+                xmp::packet(&description)
+            };
+            fs::write(&sidecar.path, contents)?;
+        }
+        Ok(sidecars)
+    }
+}
+
+impl Package {
+    fn properties(&self, pose: &Pose) -> Vec<Property> {
+        match *self {
+            Package::Pix4d => {
+                vec![Property::new(PIX4D, "Roll", format!("{:.4}", pose.roll)),
+                     Property::new(PIX4D, "Pitch", format!("{:.4}", pose.pitch)),
+                     Property::new(PIX4D, "Yaw", format!("{:.4}", pose.yaw))]
+            }
+            Package::Metashape => {
+                vec![Property::new(DJI, "GpsLatitude", format!("{:.9}", pose.latitude)),
+                     Property::new(DJI, "GpsLongitude", format!("{:.9}", pose.longitude)),
+                     Property::new(DJI, "AbsoluteAltitude", format!("{:.3}", pose.height)),
+                     Property::new(DJI, "GimbalRollDegree", format!("{:.4}", pose.roll)),
+                     Property::new(DJI, "GimbalPitchDegree", format!("{:.4}", pose.pitch)),
+                     Property::new(DJI, "GimbalYawDegree", format!("{:.4}", pose.yaw))]
+            }
+            Package::RealityCapture => {
+                vec![Property::new(REALITY_CAPTURE, "Version", 3),
+                     Property::new(REALITY_CAPTURE, "PosePrior", "initial"),
+                     Property::new(REALITY_CAPTURE, "Coordinates", "absolute"),
+                     Property::new(REALITY_CAPTURE,
+                                   "Position",
+                                   format!("{:.9} {:.9} {:.3}",
+                                           pose.longitude,
+                                           pose.latitude,
+                                           pose.height))]
+            }
+        }
+    }
+}
+
+/// Formats a coordinate as an XMP `GPSCoordinate`, e.g. `40,30.00000000N`.
+fn coordinate(value: f64, positive: char, negative: char) -> String {
+    let degrees = value.abs().floor();
+    format!("{},{:.8}{}",
+            degrees,
+            (value.abs() - degrees) * 60.,
+            if value < 0. { negative } else { positive })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image() -> Image {
+        Image {
+            path: "DSC03522.JPG".into(),
+            timestamp: 332979.899441,
+            record: 1,
+            ..Default::default()
+        }
+    }
+
+    fn pose() -> Pose {
+        Pose {
+            time: 332979.899441,
+            latitude: 40.5,
+            longitude: -105.25,
+            height: 1600.,
+            roll: 1.,
+            pitch: 2.,
+            yaw: 90.,
+        }
+    }
+
+    #[test]
+    fn description() {
+        let config = Config::new();
+        let xmp = xmp::packet(&config.description(&image(), Some("sony"), Some(&pose())));
+        assert_eq!(Some("332979.899441".to_string()),
+                   xmp::attribute(&xmp, RIPROCESS, "GpsSecondsOfWeek"));
+        assert_eq!(Some("2".to_string()), xmp::attribute(&xmp, RIPROCESS, "Record"));
+        assert_eq!(Some("sony".to_string()), xmp::attribute(&xmp, RIPROCESS, "Camera"));
+        assert_eq!(Some("40,30.00000000N".to_string()),
+                   xmp::attribute(&xmp, EXIF, "GPSLatitude"));
+        assert_eq!(Some("105,15.00000000W".to_string()),
+                   xmp::attribute(&xmp, EXIF, "GPSLongitude"));
+        assert_eq!(Some("90.0000".to_string()), xmp::attribute(&xmp, PIX4D, "Yaw"));
+        assert_eq!(Some("2.0000".to_string()),
+                   xmp::attribute(&xmp, DJI, "GimbalPitchDegree"));
+        assert_eq!(Some("-105.250000000 40.500000000 1600.000".to_string()),
+                   xmp::attribute(&xmp, REALITY_CAPTURE, "Position"));
+    }
+
+    #[test]
+    fn no_pose() {
+        let config = Config { packages: vec![Package::Pix4d], ..Default::default() };
+        let xmp = xmp::packet(&config.description(&image(), None, None));
+        assert_eq!(None, xmp::attribute(&xmp, RIPROCESS, "Camera"));
+        assert_eq!(None, xmp::attribute(&xmp, EXIF, "GPSLatitude"));
+        assert_eq!(None, xmp::attribute(&xmp, PIX4D, "Yaw"));
+    }
+
+    #[test]
+    fn write() {
+        let directory = std::env::temp_dir().join("riprocess-sidecar");
+        let _ = fs::remove_dir_all(&directory);
+        let config = Config { directory: Some(directory.clone()), ..Default::default() };
+        let path = directory.join("DSC03522.xmp");
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(&path,
+                  xmp::packet(&xmp::description(&[Property::new(EXIF, "Make", "SONY")])))
+            .unwrap();
+        let trajectory = Trajectory::new(vec![pose(), Pose { time: 332980., ..pose() }]).unwrap();
+        let sidecars = config.write(vec![image()], None, Some(&trajectory), false).unwrap();
+        assert_eq!(path, sidecars[0].path);
+        assert_eq!(Some(pose()), sidecars[0].pose);
+        config.write(vec![image()], None, Some(&trajectory), false).unwrap();
+        let xmp = fs::read_to_string(&path).unwrap();
+        assert_eq!(Some("SONY".to_string()), xmp::attribute(&xmp, EXIF, "Make"));
+        assert_eq!(Some("90.0000".to_string()), xmp::attribute(&xmp, PIX4D, "Yaw"));
+        assert_eq!(1, xmp.matches("xmlns:riprocess").count());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
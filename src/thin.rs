@@ -0,0 +1,182 @@
+//! Image thinning.
+//!
+//! Some deliverables only need every Nth image, or one image every so many seconds or meters.
+//! Thinning is applied to the paired image list, so the timestamps of the dropped images are
+//! still consumed and the pairing of the others is unchanged.
+
+use {Error, Image, Result};
+use trajectory::Trajectory;
+
+/// Configuration for thinning the image list.
+///
+/// Every configured strategy is applied, in the order every-Nth, minimum interval, minimum
+/// distance.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    /// Keep only every Nth image, starting with the first.
+    ///
+    /// Must be at least one.
+    pub every: Option<usize>,
+    /// Keep an image only if at least this many seconds have passed since the last kept image.
+    pub min_interval: Option<f64>,
+    /// Keep an image only if the platform has travelled at least this many meters since the last
+    /// kept image.
+    ///
+    /// The distance is measured along the image positions, so it needs a trajectory. Images
+    /// outside of the trajectory are kept.
+    pub min_distance: Option<f64>,
+}
+
+impl Config {
+    /// Creates a new configuration that doesn't thin.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::thin::Config;
+    /// let config = Config::new();
+    /// ```
+    pub fn new() -> Config {
+        Default::default()
+    }
+
+    /// Returns true if any thinning strategy is configured.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::thin::Config;
+    /// assert!(!Config::new().is_enabled());
+    /// assert!(Config { every: Some(2), ..Default::default() }.is_enabled());
+    /// ```
+    pub fn is_enabled(&self) -> bool {
+        self.every.is_some() || self.min_interval.is_some() || self.min_distance.is_some()
+    }
+
+    /// Thins a list of images.
+    ///
+    /// Returns an error if `every` is zero, or if a minimum distance is configured but there
+    /// isn't a trajectory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use riprocess::Config;
+    /// use riprocess::thin;
+    /// let config = Config::from_path("data/config.toml").unwrap();
+    /// let images = config.image_list().unwrap().collect();
+    /// let images = thin::Config { every: Some(2), ..Default::default() }
+    ///     .thin(images, None)
+    ///     .unwrap();
+    /// assert_eq!(2, images.len());
+    /// ```
+    pub fn thin(&self,
+                mut images: Vec<Image>,
+                trajectory: Option<&Trajectory>)
+                -> Result<Vec<Image>> {
+        if let Some(every) = self.every {
+            if every == 0 {
+                return Err(Error::InvalidConfig("thinning can't keep every 0th image".to_string()));
+            }
+            images = images.into_iter()
+                .enumerate()
+                .filter(|&(i, _)| i % every == 0)
+                .map(|(_, image)| image)
+                .collect();
+        }
+        if let Some(min_interval) = self.min_interval {
+            let mut last = None;
+            images.retain(|image| match last {
+                              Some(last) if image.timestamp - last < min_interval => false,
+                              _ => {
+                                  last = Some(image.timestamp);
+                                  true
+                              }
+                          });
+        }
+        if let Some(min_distance) = self.min_distance {
+            let trajectory = trajectory.ok_or(Error::NoTrajectory)?;
+            let mut previous = None;
+            let mut travelled = 0.;
+            images.retain(|image| {
+                let pose = match trajectory.pose(image.timestamp) {
+                    Some(pose) => pose,
+                    None => return true,
+                };
+                let keep = match previous {
+                    Some(previous) => {
+                        travelled += pose.distance(&previous);
+                        travelled >= min_distance
+                    }
+                    None => true,
+                };
+                previous = Some(pose);
+                if keep {
+                    travelled = 0.;
+                }
+                keep
+            });
+        }
+        Ok(images)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use trajectory::Pose;
+
+    fn images() -> Vec<Image> {
+        (0..10)
+            .map(|i| {
+                     Image {
+                         path: format!("DSC{:05}.JPG", i).into(),
+                         timestamp: f64::from(i) * 0.75,
+                         record: 0,
+                         ..Default::default()
+                     }
+                 })
+            .collect()
+    }
+
+    fn timestamps(images: &[Image]) -> Vec<f64> {
+        images.iter().map(|image| image.timestamp).collect()
+    }
+
+    #[test]
+    fn every() {
+        let config = Config { every: Some(3), ..Default::default() };
+        let images = config.thin(images(), None).unwrap();
+        assert_eq!(vec![0., 2.25, 4.5, 6.75], timestamps(&images));
+        let config = Config { every: Some(0), ..Default::default() };
+        assert!(config.thin(self::images(), None).is_err());
+    }
+
+    #[test]
+    fn min_interval() {
+        let config = Config { min_interval: Some(2.), ..Default::default() };
+        let images = config.thin(images(), None).unwrap();
+        assert_eq!(vec![0., 2.25, 4.5, 6.75], timestamps(&images));
+    }
+
+    #[test]
+    fn min_distance() {
+        let pose = Pose {
+            time: 0.,
+            latitude: 0.,
+            longitude: 0.,
+            height: 0.,
+            roll: 0.,
+            pitch: 0.,
+            yaw: 0.,
+        };
+        // Ten meters up per second, so 7.5 meters between images.
+        let trajectory = Trajectory::new(vec![pose, Pose { time: 6., height: 60., ..pose }])
+            .unwrap();
+        let config = Config { min_distance: Some(20.), ..Default::default() };
+        let images = config.thin(images(), Some(&trajectory)).unwrap();
+        assert_eq!(vec![0., 2.25, 4.5, 6.75], timestamps(&images));
+        assert!(config.thin(vec![], None).is_err());
+    }
+}
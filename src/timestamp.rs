@@ -1,18 +1,175 @@
 //! Timestamps for camera images.
 //!
-//! Timestamps are contained in `.eif` files, usually residing in `04_CAM_RAW/01_EIF`.
+//! Timestamps are usually contained in `.eif` files, residing in `04_CAM_RAW/01_EIF`. Other event
+//! logs can be used instead, by picking a different `Format`: generic CSV files, or NovAtel ASCII
+//! logs with `MARKTIME` or `MARK1PVA` records. Each file is paired with a record, the same as
+//! EIF files. EIF and CSV timestamps are adjusted to their record; NovAtel timestamps are already
+//! GPS seconds of the week, so they are used as they are, and records are optional.
 
-use Result;
+use {Error, Result};
+use cache::Cache;
+use listing::{Listing, Scan, Skipped};
+use record::{self, Record};
 use regex::Regex;
-use std::ffi::OsStr;
+use std::fmt::Debug;
 use std::path::{Path, PathBuf};
 
 lazy_static! {
-    static ref FILE_NAME_REGEX: Regex = Regex::new(r"^\d{6}_\d{6}.eif$").unwrap();
+    static ref FILE_NAME_REGEX: Regex =
+        Regex::new(r"^\d{6}_(?P<hours>\d{2})(?P<minutes>\d{2})(?P<seconds>\d{2}).eif$").unwrap();
+    static ref NEAR_MISS_REGEX: Regex = Regex::new(r"(?i)^\d+_\d+\.eif$").unwrap();
 }
 
+/// The EIF file name pattern, as shown in warnings.
+const FILE_NAME_PATTERN: &str = "YYMMDD_HHMMSS.eif";
+
+/// The NovAtel logs that hold event times.
+const NOVATEL_LOGS: [&str; 3] = ["MARKTIMEA", "MARK1TIMEA", "MARK1PVAA"];
+
+/// A source of timestamps, i.e. a format of event log.
+///
+/// A source decides which files in the timestamp directory belong to it, and reads the event
+/// times from each line of those files.
+pub trait TimestampSource: Debug + Sync {
+    /// Returns the file name pattern, as shown in warnings, e.g. `YYMMDD_HHMMSS.eif`.
+    fn pattern(&self) -> &'static str;
+
+    /// Returns a name for this source and its settings, e.g. `eif`.
+    ///
+    /// Sources that read files differently have different keys, so timestamps that were cached
+    /// by one source aren't reused by another.
+    fn key(&self) -> String;
+
+    /// Returns true if a file in the timestamp directory is one of this source's files.
+    fn is_match(&self, file_name: &str) -> bool;
+
+    /// Returns true if a file name almost matches, so it should be reported when it's skipped.
+    fn is_near_miss(&self, _file_name: &str) -> bool {
+        false
+    }
+
+    /// Returns the number of header lines to skip at the start of each file.
+    fn skip_lines(&self) -> usize {
+        0
+    }
+
+    /// Returns true if the timestamps are already GPS seconds of the week, like the record start
+    /// times, so they don't need to be adjusted to their record.
+    fn is_absolute(&self) -> bool {
+        false
+    }
+
+    /// Returns the offset that is added to each file's timestamps.
+    ///
+    /// The offsets are the same as for `record::offsets`, except that they are zero for absolute
+    /// sources. Either way, the number of records must match the number of files, and each file
+    /// must have a timestamp.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::record::Record;
+    /// use riprocess::timestamp::{Eif, Novatel, TimestampSource};
+    /// let records = vec![Record { name: None, start: 332978.669, end: None }];
+    /// assert_eq!(vec![332900. - 73700.], Eif.offsets(&records, &[vec![73779.899441]]).unwrap());
+    /// assert_eq!(vec![0.], Novatel.offsets(&records, &[vec![333001.]]).unwrap());
+    /// ```
+    fn offsets(&self, records: &[Record], timestamps: &[Vec<f64>]) -> Result<Vec<f64>> {
+        let offsets = record::offsets(records, timestamps)?;
+        if self.is_absolute() {
+            Ok(vec![0.; offsets.len()])
+        } else {
+            Ok(offsets)
+        }
+    }
+
+    /// Reads the timestamp from a line.
+    ///
+    /// Returns None if the line doesn't hold an event, e.g. because it is a comment or a
+    /// different kind of log.
+    fn timestamp(&self, line: &str) -> Option<Result<f64>>;
+
+    /// Reads a timestamp file.
+    ///
+    /// If `lenient`, lines that should hold an event but can't be read are skipped instead of
+    /// failing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::timestamp::{Eif, TimestampSource};
+    /// let file = Eif.read("data/timestamps/170621_202939.eif".as_ref(), false).unwrap();
+    /// assert_eq!(vec![73779.899441, 73781.419326], file.timestamps);
+    /// ```
+    fn read(&self, path: &Path, lenient: bool) -> Result<File> {
+        let mut file = File { path: path.to_path_buf(), ..Default::default() };
+        let contents = std::fs::read_to_string(path)?;
+        for (i, line) in contents.lines().enumerate().skip(self.skip_lines()) {
+            match self.timestamp(line) {
+                Some(Ok(timestamp)) => {
+                    file.timestamps.push(timestamp);
+                    file.lines.push(i + 1);
+                }
+                Some(Err(_)) if lenient => file.skipped_lines.push(i + 1),
+                Some(Err(err)) => return Err(err),
+                None => {}
+            }
+        }
+        Ok(file)
+    }
+}
+
+/// The format of the timestamp files.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// Riegl `.eif` files, named `YYMMDD_HHMMSS.eif`, with one timestamp per line.
+    #[default]
+    Eif,
+    /// Delimited text files ending in `.csv`, read as configured by `Config::csv`.
+    Csv,
+    /// NovAtel ASCII logs ending in `.asc` or `.gps`.
+    ///
+    /// Only `MARKTIMEA`, `MARK1TIMEA`, and `MARK1PVAA` logs are read, using their GPS seconds.
+    /// Every other log is ignored. The GPS seconds aren't adjusted to their records.
+    Novatel,
+}
+
+/// Riegl `.eif` files.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Eif;
+
+/// Delimited text event logs, with configurable columns.
+///
+/// Values can be separated by commas, semicolons, or whitespace. Empty lines and lines starting
+/// with `#` are ignored.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Csv {
+    /// The number of header lines to skip at the start of each file.
+    pub skip_lines: usize,
+    /// The column of each value, starting at zero.
+    pub columns: Columns,
+    /// If set, only rows whose channel column holds this value are read, e.g. `CAM1`.
+    pub channel: Option<String>,
+}
+
+/// The column of each CSV value, starting at zero.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Columns {
+    /// The event time.
+    pub time: usize,
+    /// The channel that recorded the event, used with `Csv::channel`.
+    pub channel: Option<usize>,
+}
+
+/// NovAtel ASCII logs.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Novatel;
+
 /// Configuration for timestamps.
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Config {
     /// The directory that holds the timestamp files.
     pub path: PathBuf,
@@ -24,6 +181,219 @@ pub struct Config {
     ///
     /// If None, uses the last file in the directory.
     pub end: Option<String>,
+    /// The format of the timestamp files.
+    #[serde(default)]
+    pub format: Format,
+    /// How to read CSV event logs, if the format is `csv`.
+    #[serde(default)]
+    pub csv: Csv,
+}
+
+/// The timestamps read from a single timestamp file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct File {
+    /// The path to the timestamp file.
+    pub path: PathBuf,
+    /// The timestamps, in file order.
+    ///
+    /// Use `line` to find the line that a timestamp was read from.
+    pub timestamps: Vec<f64>,
+    /// The line, starting at one, that each timestamp was read from.
+    ///
+    /// If empty, the timestamps are assumed to be on consecutive lines.
+    pub lines: Vec<usize>,
+    /// The lines, starting at one, that were skipped because they aren't timestamps.
+    ///
+    /// Lines are only skipped in lenient mode, so this is usually empty.
+    pub skipped_lines: Vec<usize>,
+}
+
+impl File {
+    /// Returns the time of day encoded in this file's name, in seconds.
+    ///
+    /// Timestamp files are named `YYMMDD_HHMMSS.eif`, where the time matches the seconds-of-day of
+    /// the file's first event. Returns None if the file name doesn't follow this pattern.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::timestamp::File;
+    /// let file = File { path: "data/timestamps/170621_202939.eif".into(), ..Default::default() };
+    /// assert_eq!(Some(73779), file.file_name_time());
+    /// ```
+    pub fn file_name_time(&self) -> Option<u32> {
+        self.path
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .and_then(|file_name| FILE_NAME_REGEX.captures(file_name))
+            .map(|captures| {
+                let value = |name| -> u32 {
+                    captures.name(name)
+                        .expect("FILE_NAME_REGEX should have hours, minutes, and seconds patterns")
+                        .as_str()
+                        .parse()
+                        .expect("\\d{2} should always parse to a u32")
+                };
+                value("hours") * 3600 + value("minutes") * 60 + value("seconds")
+            })
+    }
+
+    /// Returns the line, starting at one, that the timestamp at an index was read from.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::timestamp::File;
+    /// let file = File {
+    ///     path: "170621_202939.eif".into(),
+    ///     timestamps: vec![1., 2., 3.],
+    ///     lines: vec![1, 3, 4],
+    ///     skipped_lines: vec![2],
+    /// };
+    /// assert_eq!(1, file.line(0));
+    /// assert_eq!(3, file.line(1));
+    /// assert_eq!(4, file.line(2));
+    /// ```
+    pub fn line(&self, index: usize) -> usize {
+        self.lines.get(index).cloned().unwrap_or(index + 1)
+    }
+}
+
+impl TimestampSource for Eif {
+    fn pattern(&self) -> &'static str {
+        FILE_NAME_PATTERN
+    }
+
+    fn key(&self) -> String {
+        "eif".to_string()
+    }
+
+    fn is_match(&self, file_name: &str) -> bool {
+        FILE_NAME_REGEX.is_match(file_name)
+    }
+
+    fn is_near_miss(&self, file_name: &str) -> bool {
+        NEAR_MISS_REGEX.is_match(file_name)
+    }
+
+    fn timestamp(&self, line: &str) -> Option<Result<f64>> {
+        Some(line.trim().parse().map_err(Error::from))
+    }
+}
+
+impl TimestampSource for Csv {
+    fn pattern(&self) -> &'static str {
+        "*.csv"
+    }
+
+    /// Returns the settings that decide how rows are read, e.g.
+    /// `csv skip_lines=1 time=0 channel_column=- channel=-`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::timestamp::{Columns, Csv, TimestampSource};
+    /// let csv = Csv {
+    ///     columns: Columns { time: 1, channel: Some(0) },
+    ///     channel: Some("CAM1".to_string()),
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!("csv skip_lines=0 time=1 channel_column=0 channel=CAM1", csv.key());
+    /// ```
+    fn key(&self) -> String {
+        format!("csv skip_lines={} time={} channel_column={} channel={}",
+                self.skip_lines,
+                self.columns.time,
+                self.columns.channel.map_or_else(|| "-".to_string(), |column| column.to_string()),
+                self.channel.as_ref().map_or("-", String::as_str))
+    }
+
+    fn is_match(&self, file_name: &str) -> bool {
+        has_extension(file_name, &["csv"])
+    }
+
+    fn skip_lines(&self) -> usize {
+        self.skip_lines
+    }
+
+    /// Reads the time column from a row.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::timestamp::{Columns, Csv, TimestampSource};
+    /// let csv = Csv {
+    ///     columns: Columns { time: 1, channel: Some(0) },
+    ///     channel: Some("CAM1".to_string()),
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(73779.899441, csv.timestamp("CAM1,73779.899441").unwrap().unwrap());
+    /// assert!(csv.timestamp("CAM2,73779.9").is_none());
+    /// assert!(csv.timestamp("CAM1").unwrap().is_err());
+    /// ```
+    fn timestamp(&self, line: &str) -> Option<Result<f64>> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let values = line.split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+            .filter(|value| !value.is_empty())
+            .collect::<Vec<_>>();
+        if let (Some(column), Some(channel)) = (self.columns.channel, self.channel.as_ref()) {
+            if values.get(column) != Some(&channel.as_str()) {
+                return None;
+            }
+        }
+        Some(values.get(self.columns.time)
+                 .ok_or_else(|| {
+                                 Error::InvalidTimestamp(format!("missing column {} in {:?}",
+                                                                 self.columns.time,
+                                                                 line))
+                             })
+                 .and_then(|value| value.parse().map_err(Error::from)))
+    }
+}
+
+impl TimestampSource for Novatel {
+    fn pattern(&self) -> &'static str {
+        "*.{asc,gps}"
+    }
+
+    fn key(&self) -> String {
+        "novatel".to_string()
+    }
+
+    fn is_match(&self, file_name: &str) -> bool {
+        has_extension(file_name, &["asc", "gps"])
+    }
+
+    fn is_absolute(&self) -> bool {
+        true
+    }
+
+    /// Reads the GPS seconds from a `MARKTIMEA`, `MARK1TIMEA`, or `MARK1PVAA` log.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::timestamp::{Novatel, TimestampSource};
+    /// let line = "#MARKTIMEA,COM1,0,77.5,FINESTEERING,1953,332979.899,00000000,292e,13307;\
+    ///             1953,332979.899441000,-0.000000015,0.000000003,-18.00000000,VALID*d8a2b4e5";
+    /// assert_eq!(332979.899441, Novatel.timestamp(line).unwrap().unwrap());
+    /// assert!(Novatel.timestamp("#BESTPOSA,COM1,0,77.5;SOL_COMPUTED*4a3e1b2c").is_none());
+    /// ```
+    fn timestamp(&self, line: &str) -> Option<Result<f64>> {
+        let line = line.trim();
+        let name = line.strip_prefix('#')?.split([',', ';']).next()?;
+        if !NOVATEL_LOGS.contains(&name) {
+            return None;
+        }
+        let seconds = line.split_once(';')
+            .map(|(_, body)| body.split('*').next().unwrap_or(body))
+            .and_then(|body| body.split(',').nth(1));
+        Some(seconds.ok_or_else(|| Error::InvalidTimestamp(format!("no GPS seconds in {:?}", line)))
+                 .and_then(|seconds| seconds.trim().parse().map_err(Error::from)))
+    }
 }
 
 impl Config {
@@ -37,42 +407,70 @@ impl Config {
     /// let paths = config.paths().unwrap();
     /// ```
     pub fn paths(&self) -> Result<Vec<PathBuf>> {
-        use Error;
-        use std::fs::DirEntry;
-        use std::io::Result;
-
-        let mut file_names = Vec::new();
-        let mut paths: Vec<PathBuf>;
-        {
-            let select_paths = |result: Result<DirEntry>| match result {
-                Ok(dir_entry) => {
-                    let file_name = dir_entry.file_name();
-                    if file_name_is_match(&file_name) && self.file_name_is_in_range(&file_name) {
-                        file_names.push(file_name.to_string_lossy().into_owned());
-                        Some(dir_entry.path())
-                    } else {
-                        None
-                    }
-                }
-                Err(_) => None,
-            };
-            paths = self.path
-                .read_dir()?
-                .filter_map(select_paths)
-                .collect();
+        self.scan().map(|scan| scan.paths)
+    }
+
+    /// Returns all timestamp file paths for this config, along with the directory entries that
+    /// were skipped.
+    ///
+    /// Entries are skipped if they can't be read, if their names aren't valid UTF-8, or if their
+    /// names almost match the `YYMMDD_HHMMSS.eif` pattern, e.g. `170621_202939.EIF`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::timestamp::Config;
+    /// let config = Config { path: "data/timestamps".into(), ..Default::default() };
+    /// let scan = config.scan().unwrap();
+    /// assert_eq!(4, scan.paths.len());
+    /// assert!(scan.skipped.is_empty());
+    /// ```
+    pub fn scan(&self) -> Result<Scan> {
+        self.scan_with(None)
+    }
+
+    /// Returns the source that reads this config's timestamp files.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::timestamp::{Config, Format};
+    /// let mut config = Config { path: "data/timestamps".into(), ..Default::default() };
+    /// assert_eq!("YYMMDD_HHMMSS.eif", config.source().pattern());
+    /// config.format = Format::Csv;
+    /// assert_eq!("*.csv", config.source().pattern());
+    /// ```
+    pub fn source(&self) -> &dyn TimestampSource {
+        match self.format {
+            Format::Eif => &Eif,
+            Format::Csv => &self.csv,
+            Format::Novatel => &Novatel,
         }
-        if let Some(start) = self.start.as_ref() {
-            if !file_names.contains(start) {
-                return Err(Error::InvalidTimestampFileName(start.clone()));
+    }
+
+    fn scan_with(&self, cache: Option<&Cache>) -> Result<Scan> {
+        let source = self.source();
+        let listing = match cache {
+            Some(cache) => cache.listing(&self.path)?,
+            None => Listing::read(&self.path)?,
+        };
+        let skipped = listing.skipped(&self.path,
+                                      source.pattern(),
+                                      |file_name| source.is_match(file_name),
+                                      |file_name| source.is_near_miss(file_name));
+        let mut paths = Vec::new();
+        for file_name in &listing.file_names {
+            if source.is_match(file_name) && self.file_name_is_in_range(file_name) {
+                paths.push(self.path.join(file_name));
             }
         }
-        if let Some(end) = self.end.as_ref() {
-            if !file_names.contains(&end) {
-                return Err(Error::InvalidTimestampFileName(end.clone()));
+        for name in self.start.iter().chain(self.end.iter()) {
+            if !source.is_match(name) || !listing.file_names.contains(name) {
+                return Err(Error::InvalidTimestampFileName(name.clone()));
             }
         }
         paths.sort();
-        Ok(paths)
+        Ok(Scan { paths, skipped })
     }
 
     /// Returns the timestamps in each configured timestamp file.
@@ -85,41 +483,71 @@ impl Config {
     /// let timestamps = config.timestamps().unwrap();
     /// ```
     pub fn timestamps(&self) -> Result<Vec<Vec<f64>>> {
-        self.paths().and_then(|paths| paths.into_iter().map(read_timestamps).collect())
-    }
-
-    fn file_name_is_in_range(&self, file_name: &OsStr) -> bool {
-        file_name.to_str()
-            .map(|file_name| {
-                self.start
-                    .as_ref()
-                    .map(|start| start.as_str() <= file_name)
-                    .unwrap_or(true) &&
-                self.end
-                    .as_ref()
-                    .map(|end| end.as_str() >= file_name)
-                    .unwrap_or(true)
+        self.files().map(|files| files.into_iter().map(|file| file.timestamps).collect())
+    }
+
+    /// Returns each configured timestamp file along with its timestamps.
+    ///
+    /// Files are read in parallel, but are returned sorted by path. If more than one file can't
+    /// be read, the error for the first one is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::timestamp::Config;
+    /// let config = Config { path: "data/timestamps".into(), ..Default::default() };
+    /// let files = config.files().unwrap();
+    /// assert_eq!(4, files.len());
+    /// ```
+    pub fn files(&self) -> Result<Vec<File>> {
+        self.files_with(None)
+    }
+
+    /// Returns each configured timestamp file along with its timestamps, reading through a cache.
+    pub(crate) fn files_with(&self, cache: Option<&Cache>) -> Result<Vec<File>> {
+        self.scan_files_with(cache, false).map(|(files, _)| files)
+    }
+
+    /// Returns each configured timestamp file along with its timestamps, and the directory
+    /// entries that were skipped, reading through a cache.
+    ///
+    /// If `lenient`, lines that aren't timestamps are skipped instead of failing. Files that
+    /// needed skipping aren't cached, so they are read again, and reported again, every time.
+    pub(crate) fn scan_files_with(&self,
+                                  cache: Option<&Cache>,
+                                  lenient: bool)
+                                  -> Result<(Vec<File>, Vec<Skipped>)> {
+        use rayon::prelude::*;
+
+        let source = self.source();
+        let key = source.key();
+        let Scan { paths, skipped } = self.scan_with(cache)?;
+        let files = paths.into_par_iter()
+            .map(|path| {
+                let file = match cache {
+                    Some(cache) => cache.timestamps(&path, &key, |path| source.read(path, false)),
+                    None => source.read(&path, false),
+                };
+                match file {
+                    Err(_) if lenient => source.read(&path, true),
+                    result => result,
+                }
             })
-            .unwrap_or(false)
+            .collect::<Vec<_>>();
+        files.into_iter().collect::<Result<_>>().map(|files| (files, skipped))
     }
-}
 
-fn file_name_is_match(file_name: &OsStr) -> bool {
-    file_name.to_str().map(|file_name| FILE_NAME_REGEX.is_match(file_name)).unwrap_or(false)
+    fn file_name_is_in_range(&self, file_name: &str) -> bool {
+        self.start.as_ref().map(|start| start.as_str() <= file_name).unwrap_or(true) &&
+        self.end.as_ref().map(|end| end.as_str() >= file_name).unwrap_or(true)
+    }
 }
 
-fn read_timestamps<P: AsRef<Path>>(path: P) -> Result<Vec<f64>> {
-    use Error;
-    use std::fs::File;
-    use std::io::{BufRead, BufReader};
-
-    let file = File::open(path)?;
-    BufReader::new(file)
-        .lines()
-        .map(|result| {
-                 result.map_err(Error::from).and_then(|line| line.parse().map_err(Error::from))
-             })
-        .collect()
+fn has_extension(file_name: &str, extensions: &[&str]) -> bool {
+    Path::new(file_name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extensions.iter().any(|e| extension.eq_ignore_ascii_case(e)))
 }
 
 #[cfg(test)]
@@ -130,8 +558,7 @@ mod tests {
     fn no_paths() {
         let config = Config {
             path: "data".into(),
-            start: None,
-            end: None,
+            ..Default::default()
         };
         assert!(config.paths().unwrap().is_empty());
     }
@@ -140,8 +567,7 @@ mod tests {
     fn all_paths() {
         let config = Config {
             path: "data/timestamps".into(),
-            start: None,
-            end: None,
+            ..Default::default()
         };
         assert_eq!(4, config.paths().unwrap().len());
     }
@@ -151,7 +577,7 @@ mod tests {
         let config = Config {
             path: "data/timestamps".into(),
             start: Some("170621_202939.eif".to_string()),
-            end: None,
+            ..Default::default()
         };
         assert_eq!(3, config.paths().unwrap().len());
     }
@@ -162,6 +588,7 @@ mod tests {
             path: "data/timestamps".into(),
             start: None,
             end: Some("170621_202939.eif".to_string()),
+            ..Default::default()
         };
         assert_eq!(2, config.paths().unwrap().len());
     }
@@ -171,7 +598,7 @@ mod tests {
         let config = Config {
             path: "data/timestamps".into(),
             start: Some("not a timestamp file".to_string()),
-            end: None,
+            ..Default::default()
         };
         assert!(config.paths().is_err());
     }
@@ -182,20 +609,95 @@ mod tests {
             path: "data/timestamps".into(),
             start: None,
             end: Some("not a timestamp file".to_string()),
+            ..Default::default()
         };
         assert!(config.paths().is_err());
     }
 
+    #[test]
+    fn start_is_not_a_timestamp_file() {
+        let directory = std::env::temp_dir().join("riprocess-timestamp-not-a-timestamp-file");
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("170621_202939.eif"), "73779.5\n").unwrap();
+        std::fs::write(directory.join("notes.txt"), "").unwrap();
+        let config = Config {
+            path: directory.clone(),
+            start: Some("notes.txt".to_string()),
+            ..Default::default()
+        };
+        let paths = config.paths();
+        std::fs::remove_dir_all(&directory).unwrap();
+        match paths {
+            Err(Error::InvalidTimestampFileName(name)) => assert_eq!("notes.txt", name),
+            other => panic!("expected an invalid timestamp file name, got {:?}", other),
+        }
+    }
+
     #[test]
     fn timestamps() {
         let config = Config {
             path: "data/timestamps".into(),
             start: None,
             end: Some("170621_202939.eif".to_string()),
+            ..Default::default()
         };
         let timestamps = config.timestamps().unwrap();
         assert_eq!(vec![vec![73779.899441, 73781.419326, 73782.899381],
                         vec![73779.899441, 73781.419326]],
                    timestamps);
     }
+
+    #[test]
+    fn lenient() {
+        let directory = std::env::temp_dir().join("riprocess-timestamp-lenient");
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("170621_202939.eif"), "73779.5\n\nbad\n73781.5\n")
+            .unwrap();
+        let config = Config { path: directory.clone(), ..Default::default() };
+        assert!(config.files().is_err());
+        let (files, _) = config.scan_files_with(None, true).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(vec![73779.5, 73781.5], files[0].timestamps);
+        assert_eq!(vec![2, 3], files[0].skipped_lines);
+        assert_eq!(4, files[0].line(1));
+    }
+
+    #[test]
+    fn csv() {
+        let directory = std::env::temp_dir().join("riprocess-timestamp-csv");
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("events.csv"), "time;channel\n\n1.5;CAM1\n2.5;CAM1\n")
+            .unwrap();
+        std::fs::write(directory.join("170621_202939.eif"), "1.5\n").unwrap();
+        let config = Config {
+            path: directory.clone(),
+            format: Format::Csv,
+            csv: Csv { skip_lines: 1, ..Default::default() },
+            ..Default::default()
+        };
+        let files = config.files();
+        std::fs::remove_dir_all(&directory).unwrap();
+        let files = files.unwrap();
+        assert_eq!(1, files.len());
+        assert_eq!(vec![1.5, 2.5], files[0].timestamps);
+        assert_eq!(vec![3, 4], files[0].lines);
+    }
+
+    #[test]
+    fn novatel() {
+        let log = "#MARKTIMEA,COM1,0,77.5,FINESTEERING,1953,332979.899,0,0,1;\
+                   1953,332979.899441000,0.0,0.0,-18.0,VALID*d8a2b4e5";
+        assert_eq!(332979.899441, Novatel.timestamp(log).unwrap().unwrap());
+        assert!(Novatel.timestamp("#MARKTIMEA,COM1,0,77.5*d8a2b4e5").unwrap().is_err());
+        assert!(Novatel.timestamp("%INSPVASA,1953,332979.899;1953,332979.899*d8a2b4e5")
+                    .is_none());
+        assert!(Novatel.is_match("mission.ASC"));
+        assert!(Novatel.is_match("mission.gps"));
+        assert!(!Novatel.is_match("notes.txt"));
+        assert!(!Novatel.is_match("receiver.log"));
+        assert!(!Novatel.is_match("170621_202939.eif"));
+    }
 }
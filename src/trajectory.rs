@@ -0,0 +1,298 @@
+//! Platform trajectories.
+//!
+//! A trajectory is a time series of positions and attitudes, e.g. as exported from RiPROCESS or
+//! POSPac as a delimited text file. With a trajectory, each image's timestamp can be turned into
+//! a position and orientation.
+
+use {Error, Result};
+use std::path::{Path, PathBuf};
+
+/// The mean earth radius, in meters.
+const EARTH_RADIUS: f64 = 6371008.8;
+
+/// Configuration for reading a trajectory from a delimited text file.
+///
+/// Values can be separated by commas, semicolons, or whitespace. Empty lines and lines starting
+/// with `#` are ignored.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    /// The path to the trajectory file.
+    pub path: PathBuf,
+    /// The number of header lines to skip at the start of the file.
+    pub skip_lines: usize,
+    /// The column of each value, starting at zero.
+    pub columns: Columns,
+}
+
+/// The column of each trajectory value, starting at zero.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Columns {
+    /// The GPS time, in seconds of the week.
+    pub time: usize,
+    /// The latitude, in decimal degrees.
+    pub latitude: usize,
+    /// The longitude, in decimal degrees.
+    pub longitude: usize,
+    /// The ellipsoidal height, in meters.
+    pub height: usize,
+    /// The roll, in degrees.
+    pub roll: usize,
+    /// The pitch, in degrees.
+    pub pitch: usize,
+    /// The yaw, or heading, in degrees clockwise from north.
+    pub yaw: usize,
+}
+
+/// A trajectory, sorted by time.
+#[derive(Debug)]
+pub struct Trajectory {
+    poses: Vec<Pose>,
+}
+
+/// A position and attitude at a point in time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pose {
+    /// The GPS time, in seconds of the week.
+    pub time: f64,
+    /// The latitude, in decimal degrees.
+    pub latitude: f64,
+    /// The longitude, in decimal degrees.
+    pub longitude: f64,
+    /// The ellipsoidal height, in meters.
+    pub height: f64,
+    /// The roll, in degrees.
+    pub roll: f64,
+    /// The pitch, in degrees.
+    pub pitch: f64,
+    /// The yaw, or heading, in degrees clockwise from north.
+    pub yaw: f64,
+}
+
+impl Default for Columns {
+    fn default() -> Columns {
+        Columns {
+            time: 0,
+            latitude: 1,
+            longitude: 2,
+            height: 3,
+            roll: 4,
+            pitch: 5,
+            yaw: 6,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the configured trajectory file.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use riprocess::trajectory::Config;
+    /// let config = Config { path: "trajectory.txt".into(), ..Default::default() };
+    /// let trajectory = config.read().unwrap();
+    /// ```
+    pub fn read(&self) -> Result<Trajectory> {
+        use std::fs;
+        let contents = fs::read_to_string(&self.path)?;
+        self.parse(&contents, &self.path)
+    }
+
+    fn parse(&self, contents: &str, path: &Path) -> Result<Trajectory> {
+        let columns = self.columns;
+        let poses = contents.lines()
+            .enumerate()
+            .skip(self.skip_lines)
+            .filter(|&(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+            .map(|(i, line)| {
+                let values = line.split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+                    .filter(|value| !value.is_empty())
+                    .collect::<Vec<_>>();
+                let value = |column: usize| -> Result<f64> {
+                    values.get(column)
+                        .ok_or_else(|| {
+                                        Error::InvalidTrajectory(format!("{}:{}: missing column {}",
+                                                                         path.display(),
+                                                                         i + 1,
+                                                                         column))
+                                    })
+                        .and_then(|value| value.parse().map_err(Error::from))
+                };
+                Ok(Pose {
+                       time: value(columns.time)?,
+                       latitude: value(columns.latitude)?,
+                       longitude: value(columns.longitude)?,
+                       height: value(columns.height)?,
+                       roll: value(columns.roll)?,
+                       pitch: value(columns.pitch)?,
+                       yaw: value(columns.yaw)?,
+                   })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Trajectory::new(poses)
+    }
+}
+
+impl Pose {
+    /// Returns the straight-line distance to another pose, in meters.
+    ///
+    /// Uses a spherical earth, which is plenty for the short distances between images.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::trajectory::Pose;
+    /// let pose = Pose {
+    ///     time: 1.,
+    ///     latitude: 40.,
+    ///     longitude: -105.,
+    ///     height: 1600.,
+    ///     roll: 0.,
+    ///     pitch: 0.,
+    ///     yaw: 0.,
+    /// };
+    /// assert_eq!(10., pose.distance(&Pose { height: 1610., ..pose }));
+    /// ```
+    pub fn distance(&self, other: &Pose) -> f64 {
+        let (a, b) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let dlat = b - a;
+        let dlon = (other.longitude - self.longitude).to_radians();
+        let h = (dlat / 2.).sin().powi(2) + a.cos() * b.cos() * (dlon / 2.).sin().powi(2);
+        let horizontal = 2. * EARTH_RADIUS * h.sqrt().asin();
+        horizontal.hypot(other.height - self.height)
+    }
+}
+
+impl Trajectory {
+    /// Creates a new trajectory from poses.
+    ///
+    /// Returns an error if the poses aren't sorted by strictly increasing time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::trajectory::{Pose, Trajectory};
+    /// let pose = Pose {
+    ///     time: 1.,
+    ///     latitude: 0.,
+    ///     longitude: 0.,
+    ///     height: 0.,
+    ///     roll: 0.,
+    ///     pitch: 0.,
+    ///     yaw: 0.,
+    /// };
+    /// let trajectory = Trajectory::new(vec![pose, Pose { time: 2., ..pose }]).unwrap();
+    /// assert!(Trajectory::new(vec![pose, pose]).is_err());
+    /// ```
+    pub fn new(poses: Vec<Pose>) -> Result<Trajectory> {
+        if let Some(pair) = poses.windows(2).find(|pair| pair[1].time <= pair[0].time) {
+            return Err(Error::InvalidTrajectory(format!("time {} is not after time {}",
+                                                        pair[1].time,
+                                                        pair[0].time)));
+        }
+        Ok(Trajectory { poses })
+    }
+
+    /// Returns the poses in this trajectory.
+    pub fn poses(&self) -> &[Pose] {
+        &self.poses
+    }
+
+    /// Returns the pose at the provided time, linearly interpolated between the surrounding poses.
+    ///
+    /// Returns None if the time is outside of the trajectory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::trajectory::{Pose, Trajectory};
+    /// let pose = Pose {
+    ///     time: 1.,
+    ///     latitude: 40.,
+    ///     longitude: -105.,
+    ///     height: 1600.,
+    ///     roll: 0.,
+    ///     pitch: 0.,
+    ///     yaw: 350.,
+    /// };
+    /// let trajectory = Trajectory::new(vec![pose,
+    ///                                       Pose { time: 2., height: 1700., yaw: 10., ..pose }])
+    ///     .unwrap();
+    /// let pose = trajectory.pose(1.5).unwrap();
+    /// assert_eq!(1650., pose.height);
+    /// assert_eq!(0., pose.yaw);
+    /// assert!(trajectory.pose(3.).is_none());
+    /// ```
+    pub fn pose(&self, time: f64) -> Option<Pose> {
+        let i = match self.poses
+                  .binary_search_by(|pose| {
+                                        pose.time
+                                            .partial_cmp(&time)
+                                            .unwrap_or(std::cmp::Ordering::Less)
+                                    }) {
+            Ok(i) => return Some(self.poses[i]),
+            Err(i) => i,
+        };
+        if i == 0 || i >= self.poses.len() {
+            return None;
+        }
+        let (a, b) = (self.poses[i - 1], self.poses[i]);
+        let t = (time - a.time) / (b.time - a.time);
+        let linear = |a: f64, b: f64| a + (b - a) * t;
+        let angular = |a: f64, b: f64| {
+            let difference = (b - a + 540.) % 360. - 180.;
+            let angle = a + difference * t;
+            if angle < 0. { angle + 360. } else { angle % 360. }
+        };
+        Some(Pose {
+                 time,
+                 latitude: linear(a.latitude, b.latitude),
+                 longitude: linear(a.longitude, b.longitude),
+                 height: linear(a.height, b.height),
+                 roll: linear(a.roll, b.roll),
+                 pitch: linear(a.pitch, b.pitch),
+                 yaw: angular(a.yaw, b.yaw),
+             })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRAJECTORY: &str = "time,lat,lon,height,roll,pitch,yaw
+# a comment
+332979.0, 40.0, -105.0, 1600.0, 1.0, 2.0, 90.0
+332980.0, 40.001, -105.001, 1610.0, 3.0, 4.0, 100.0
+";
+
+    #[test]
+    fn parse() {
+        let config = Config { skip_lines: 1, ..Default::default() };
+        let trajectory = config.parse(TRAJECTORY, Path::new("test.txt")).unwrap();
+        assert_eq!(2, trajectory.poses().len());
+        let pose = trajectory.pose(332979.5).unwrap();
+        assert!((pose.latitude - 40.0005).abs() < 1e-9);
+        assert_eq!(1605., pose.height);
+        assert_eq!(2., pose.roll);
+        assert_eq!(95., pose.yaw);
+    }
+
+    #[test]
+    fn columns() {
+        let config = Config {
+            skip_lines: 2,
+            columns: Columns { yaw: 7, ..Default::default() },
+            ..Default::default()
+        };
+        assert!(config.parse(TRAJECTORY, Path::new("test.txt")).is_err());
+    }
+
+    #[test]
+    fn header_not_skipped() {
+        let config = Config::default();
+        assert!(config.parse(TRAJECTORY, Path::new("test.txt")).is_err());
+    }
+}
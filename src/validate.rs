@@ -0,0 +1,563 @@
+//! Validation of timestamp files and sequences.
+//!
+//! `.eif` files are plain lists of floats, so nothing stops them from being out of order, holding
+//! duplicated events, missing triggers, or being copies of one another. This module checks each
+//! timestamp file, and the sequence of adjusted timestamps, against a set of rules. Each rule can
+//! be configured to be an error, a warning, or ignored.
+
+use {Result, record, timestamp};
+use timestamp::TimestampSource;
+use std::fmt;
+use std::path::PathBuf;
+
+/// Configuration for timestamp validation.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    /// A timestamp is smaller than the one before it.
+    pub non_increasing: Level,
+    /// A timestamp is exactly equal to an earlier timestamp in the same sequence.
+    pub duplicate: Level,
+    /// The interval to the previous timestamp is far from the median trigger interval.
+    pub interval_outlier: Level,
+    /// How far from the median interval an interval can be before it is an outlier.
+    ///
+    /// An interval is an outlier if it is more than `outlier_factor` times the median interval,
+    /// or less than the median interval divided by `outlier_factor`.
+    pub outlier_factor: f64,
+    /// An adjusted timestamp is before its record's start time, or after its record's end time.
+    ///
+    /// If the record's end time isn't known, the record is assumed to end when the next one
+    /// starts.
+    pub outside_record: Level,
+    /// The time in a timestamp file's name doesn't match the time of day of its first event.
+    pub file_name_time: Level,
+    /// How far apart, in seconds, the file name time and the first event may be.
+    pub file_name_tolerance: f64,
+    /// A timestamp file shares most of its events with another timestamp file, e.g. because it
+    /// is a copy.
+    ///
+    /// If only one of the two files has a name that matches its first event, the other one is
+    /// the copy and is the only one reported. Otherwise both files are reported.
+    pub duplicate_file: Level,
+    /// The fraction of the shorter file's events that must be shared before two files are
+    /// duplicates.
+    pub duplicate_file_overlap: f64,
+}
+
+/// How seriously to take a violation of a validation rule.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    /// The violation prevents an image list from being created.
+    Error,
+    /// The violation is reported, but does not prevent an image list from being created.
+    Warn,
+    /// The rule is not checked.
+    Ignore,
+}
+
+/// A validation rule.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rule {
+    /// See `Config::non_increasing`.
+    NonIncreasing,
+    /// See `Config::duplicate`.
+    Duplicate,
+    /// See `Config::interval_outlier`.
+    IntervalOutlier,
+    /// See `Config::outside_record`.
+    OutsideRecord,
+    /// See `Config::file_name_time`.
+    FileNameTime,
+    /// See `Config::duplicate_file`.
+    DuplicateFile,
+}
+
+/// A single violation of a validation rule.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Issue {
+    /// The level of the violated rule, either `Error` or `Warn`.
+    pub level: Level,
+    /// The violated rule.
+    pub rule: Rule,
+    /// The timestamp file that holds the offending timestamp.
+    pub path: PathBuf,
+    /// The line number of the offending timestamp in its file, starting at one.
+    pub line: usize,
+    /// The offending timestamp.
+    ///
+    /// For rules that are checked on adjusted timestamps, this is the adjusted value.
+    pub value: f64,
+    /// A human-readable description of the violation.
+    pub message: String,
+}
+
+const SECONDS_PER_DAY: f64 = 86400.;
+
+struct Entry<'a> {
+    path: &'a PathBuf,
+    line: usize,
+    value: f64,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            non_increasing: Level::Error,
+            duplicate: Level::Error,
+            interval_outlier: Level::Warn,
+            outlier_factor: 3.,
+            outside_record: Level::Warn,
+            file_name_time: Level::Error,
+            file_name_tolerance: 1.,
+            duplicate_file: Level::Error,
+            duplicate_file_overlap: 0.5,
+        }
+    }
+}
+
+impl Config {
+    /// Creates a new, default configuration.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::validate::Config;
+    /// let config = Config::new();
+    /// ```
+    pub fn new() -> Config {
+        Default::default()
+    }
+
+    /// Checks timestamp files, and their adjusted timestamps, against this configuration.
+    ///
+    /// Each file's name is checked against its first event, and each pair of files is checked
+    /// for shared events. Each file is then checked on its own for non-increasing
+    /// values, duplicates, and interval outliers. If none of these checks produced an error, the
+    /// timestamps are adjusted to their records as the source does, the adjusted timestamps of
+    /// all files are checked as one sequence for non-increasing values and duplicates, and each
+    /// adjusted timestamp is checked against its record's time span.
+    ///
+    /// Returns an error if the timestamps can't be adjusted, e.g. if the number of files doesn't
+    /// match the number of records.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::{Config, validate};
+    /// let config = Config::from_path("data/config.toml").unwrap();
+    /// let files = config.timestamps.files().unwrap();
+    /// let records = config.records.records().unwrap();
+    /// let source = config.timestamps.source();
+    /// let issues = validate::Config::new().check(&files, &records, source).unwrap();
+    /// assert!(issues.is_empty());
+    /// ```
+    pub fn check(&self,
+                 files: &[timestamp::File],
+                 records: &[record::Record],
+                 source: &dyn TimestampSource)
+                 -> Result<Vec<Issue>> {
+        let mut issues = self.check_files(files);
+        if issues.iter().any(|issue| issue.level == Level::Error) {
+            return Ok(issues);
+        }
+        let timestamps = files.iter().map(|file| file.timestamps.clone()).collect::<Vec<_>>();
+        let offsets = source.offsets(records, &timestamps)?;
+        let mut adjusted = Vec::new();
+        for (i, (file, offset)) in files.iter().zip(offsets).enumerate() {
+            let entries = entries(file, offset);
+            self.check_record(&entries, &records[i], records.get(i + 1), &mut issues);
+            adjusted.extend(entries);
+        }
+        self.check_sequence(&adjusted, "adjusted ", &mut issues);
+        Ok(issues)
+    }
+
+    /// Checks timestamp files against the rules that don't need record information.
+    ///
+    /// These are the checks on file names, on events shared between files, and on each file's own
+    /// sequence of timestamps.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::{timestamp, validate};
+    /// let timestamps = timestamp::Config { path: "data/timestamps".into(), ..Default::default() };
+    /// let issues = validate::Config::new().check_files(&timestamps.files().unwrap());
+    /// assert!(issues.iter().any(|issue| issue.rule == validate::Rule::DuplicateFile));
+    /// ```
+    pub fn check_files(&self, files: &[timestamp::File]) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        for (i, file) in files.iter().enumerate() {
+            let entries = entries(file, 0.);
+            self.check_file_name_time(file, &mut issues);
+            for other in &files[..i] {
+                self.check_duplicate_file(file, other, &mut issues);
+            }
+            self.check_sequence(&entries, "", &mut issues);
+            self.check_intervals(&entries, &mut issues);
+        }
+        issues
+    }
+
+    fn check_file_name_time(&self, file: &timestamp::File, issues: &mut Vec<Issue>) {
+        if let (Some(difference), Some(entry)) = (file_name_difference(file),
+                                                  entries(file, 0.).first()) {
+            let file_name_time = f64::from(file.file_name_time()
+                .expect("there is a difference only if there is a file name time"));
+            if difference.abs() > self.file_name_tolerance {
+                push(issues,
+                     self.file_name_time,
+                     Rule::FileNameTime,
+                     entry,
+                     format!("first event is {}s from the file name time of {:02}:{:02}:{:02}",
+                             difference,
+                             file_name_time as u32 / 3600,
+                             file_name_time as u32 % 3600 / 60,
+                             file_name_time as u32 % 60));
+            }
+        }
+    }
+
+    fn check_duplicate_file(&self,
+                            file: &timestamp::File,
+                            other: &timestamp::File,
+                            issues: &mut Vec<Issue>) {
+        use std::collections::HashSet;
+
+        let values = file.timestamps.iter().map(|value| value.to_bits()).collect::<HashSet<_>>();
+        let shared = other.timestamps
+            .iter()
+            .filter(|value| values.contains(&value.to_bits()))
+            .count();
+        let shorter = file.timestamps.len().min(other.timestamps.len());
+        if shared == 0 || (shared as f64) < self.duplicate_file_overlap * shorter as f64 {
+            return;
+        }
+        let is_named_right = |file: &timestamp::File| {
+            file_name_difference(file)
+                .is_some_and(|difference| difference.abs() <= self.file_name_tolerance)
+        };
+        let pair = [(file, other), (other, file)];
+        let culprits = match (is_named_right(file), is_named_right(other)) {
+            (true, false) => &pair[1..],
+            (false, true) => &pair[..1],
+            _ => &pair[..],
+        };
+        for &(culprit, original) in culprits {
+            let message = if culprit.timestamps == original.timestamps {
+                format!("file contents are identical to {}", original.path.display())
+            } else {
+                format!("file shares {} of its {} events with {}",
+                        shared,
+                        culprit.timestamps.len(),
+                        original.path.display())
+            };
+            if let Some(entry) = entries(culprit, 0.).first() {
+                push(issues, self.duplicate_file, Rule::DuplicateFile, entry, message);
+            }
+        }
+    }
+
+    fn check_sequence(&self, entries: &[Entry], prefix: &str, issues: &mut Vec<Issue>) {
+        use std::collections::HashSet;
+
+        let mut seen = HashSet::new();
+        for (i, entry) in entries.iter().enumerate() {
+            if !seen.insert(entry.value.to_bits()) {
+                push(issues,
+                     self.duplicate,
+                     Rule::Duplicate,
+                     entry,
+                     format!("{}timestamp is a duplicate of an earlier timestamp", prefix));
+            } else if i > 0 && entry.value < entries[i - 1].value {
+                push(issues,
+                     self.non_increasing,
+                     Rule::NonIncreasing,
+                     entry,
+                     format!("{}timestamp is smaller than the previous timestamp ({:.6})",
+                             prefix,
+                             entries[i - 1].value));
+            }
+        }
+    }
+
+    fn check_intervals(&self, entries: &[Entry], issues: &mut Vec<Issue>) {
+        let intervals = entries.windows(2)
+            .map(|pair| pair[1].value - pair[0].value)
+            .filter(|&interval| interval > 0.)
+            .collect::<Vec<_>>();
+        let median = match median(intervals) {
+            Some(median) => median,
+            None => return,
+        };
+        for pair in entries.windows(2) {
+            let interval = pair[1].value - pair[0].value;
+            if interval > 0. &&
+               (interval > median * self.outlier_factor ||
+                interval < median / self.outlier_factor) {
+                push(issues,
+                     self.interval_outlier,
+                     Rule::IntervalOutlier,
+                     &pair[1],
+                     format!("interval of {:.6}s is far from the median interval of {:.6}s",
+                             interval,
+                             median));
+            }
+        }
+    }
+
+    fn check_record(&self,
+                    entries: &[Entry],
+                    record: &record::Record,
+                    next: Option<&record::Record>,
+                    issues: &mut Vec<Issue>) {
+        for entry in entries {
+            if !record.contains(entry.value, next) {
+                push(issues,
+                     self.outside_record,
+                     Rule::OutsideRecord,
+                     entry,
+                     format!("adjusted timestamp is outside of its record's span {}",
+                             record.span(next)));
+            }
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Level::Error => write!(f, "error"),
+            Level::Warn => write!(f, "warning"),
+            Level::Ignore => write!(f, "ignore"),
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{}:{}: {:.6}: {}",
+               self.path.display(),
+               self.line,
+               self.value,
+               self.message)
+    }
+}
+
+fn entries<'a>(file: &'a timestamp::File, offset: f64) -> Vec<Entry<'a>> {
+    file.timestamps
+        .iter()
+        .enumerate()
+        .map(|(i, &timestamp)| {
+                 Entry {
+                     path: &file.path,
+                     line: file.line(i),
+                     value: timestamp + offset,
+                 }
+             })
+        .collect()
+}
+
+/// Returns the difference between a file's first event and the time in its name, in seconds,
+/// wrapped to within half a day.
+fn file_name_difference(file: &timestamp::File) -> Option<f64> {
+    let file_name_time = f64::from(file.file_name_time()?);
+    let first = file.timestamps.first()?;
+    let mut difference = (first.floor() - file_name_time) % SECONDS_PER_DAY;
+    if difference >= SECONDS_PER_DAY / 2. {
+        difference -= SECONDS_PER_DAY;
+    } else if difference < -SECONDS_PER_DAY / 2. {
+        difference += SECONDS_PER_DAY;
+    }
+    Some(difference)
+}
+
+fn push(issues: &mut Vec<Issue>, level: Level, rule: Rule, entry: &Entry, message: String) {
+    if level != Level::Ignore {
+        issues.push(Issue {
+                        level,
+                        rule,
+                        path: entry.path.clone(),
+                        line: entry.line,
+                        value: entry.value,
+                        message,
+                    });
+    }
+}
+
+fn median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).expect("intervals should not be NaN"));
+    let n = values.len();
+    if n % 2 == 1 {
+        Some(values[n / 2])
+    } else {
+        Some((values[n / 2 - 1] + values[n / 2]) / 2.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use timestamp::Eif;
+
+    fn file(timestamps: Vec<f64>) -> timestamp::File {
+        timestamp::File {
+            path: "test.eif".into(),
+            timestamps,
+            ..Default::default()
+        }
+    }
+
+    fn records(start_times: Vec<f64>) -> Vec<record::Record> {
+        record::Config { start_times, ..Default::default() }.records().unwrap()
+    }
+
+    #[test]
+    fn valid() {
+        let files = vec![file(vec![73779.899441, 73781.419326])];
+        let issues = Config::new().check(&files, &records(vec![332978.669]), &Eif).unwrap();
+        assert!(issues.is_empty(), "{:?}", issues);
+    }
+
+    #[test]
+    fn non_increasing() {
+        let files = vec![file(vec![73779., 73781., 73780.])];
+        let issues = Config::new().check(&files, &records(vec![332978.]), &Eif).unwrap();
+        assert_eq!(1, issues.len());
+        assert_eq!(Rule::NonIncreasing, issues[0].rule);
+        assert_eq!(3, issues[0].line);
+        assert_eq!(73780., issues[0].value);
+        assert_eq!(Level::Error, issues[0].level);
+    }
+
+    #[test]
+    fn duplicate() {
+        let files = vec![file(vec![73779., 73781., 73781.])];
+        let issues = Config::new().check(&files, &records(vec![332978.]), &Eif).unwrap();
+        assert_eq!(1, issues.len());
+        assert_eq!(Rule::Duplicate, issues[0].rule);
+    }
+
+    #[test]
+    fn interval_outlier() {
+        let files = vec![file(vec![73701., 73702.5, 73704., 73720., 73721.5])];
+        let issues = Config::new().check(&files, &records(vec![332900.]), &Eif).unwrap();
+        assert_eq!(1, issues.len());
+        assert_eq!(Rule::IntervalOutlier, issues[0].rule);
+        assert_eq!(Level::Warn, issues[0].level);
+        assert_eq!(4, issues[0].line);
+    }
+
+    #[test]
+    fn outside_record() {
+        let files = vec![file(vec![73710.]), file(vec![73801.])];
+        let issues = Config::new().check(&files, &records(vec![332900., 333005.]), &Eif).unwrap();
+        assert_eq!(1, issues.len());
+        assert_eq!(Rule::OutsideRecord, issues[0].rule);
+        assert_eq!(333001., issues[0].value);
+    }
+
+    #[test]
+    fn outside_record_end() {
+        let files = vec![file(vec![73710., 73740.])];
+        let records = record::Config {
+            start_times: vec![332900.],
+            durations: vec![30.],
+            ..Default::default()
+        };
+        let issues = Config::new().check(&files, &records.records().unwrap(), &Eif).unwrap();
+        assert_eq!(1, issues.len());
+        assert_eq!(332940., issues[0].value);
+        assert!(issues[0].message.ends_with("[332900.000000, 332930.000000]"));
+    }
+
+    #[test]
+    fn overlapping_records() {
+        let files = vec![file(vec![73710.]), file(vec![73801.])];
+        let config = Config { outside_record: Level::Ignore, ..Default::default() };
+        let issues = config.check(&files, &records(vec![332900., 332800.]), &Eif).unwrap();
+        assert_eq!(1, issues.len());
+        assert_eq!(Rule::NonIncreasing, issues[0].rule);
+        assert!(issues[0].message.starts_with("adjusted"));
+    }
+
+    #[test]
+    fn ignore() {
+        let files = vec![file(vec![73779., 73781., 73780.])];
+        let config = Config { non_increasing: Level::Ignore, ..Default::default() };
+        assert!(config.check(&files, &records(vec![332978.]), &Eif).unwrap().is_empty());
+    }
+
+    #[test]
+    fn file_name_time() {
+        let files = vec![timestamp::File {
+                             path: "data/timestamps/170621_202750.eif".into(),
+                             timestamps: vec![73779.899441],
+                             ..Default::default()
+                         }];
+        let issues = Config::new().check_files(&files);
+        assert_eq!(1, issues.len());
+        assert_eq!(Rule::FileNameTime, issues[0].rule);
+        assert_eq!(Level::Error, issues[0].level);
+    }
+
+    #[test]
+    fn file_name_time_across_midnight() {
+        let files = vec![timestamp::File {
+                             path: "data/timestamps/170621_235959.eif".into(),
+                             timestamps: vec![86400. * 3. + 0.5],
+                             ..Default::default()
+                         }];
+        let config = Config { file_name_tolerance: 1., ..Default::default() };
+        assert!(config.check_files(&files).is_empty());
+    }
+
+    #[test]
+    fn duplicate_file() {
+        let timestamps = ::timestamp::Config {
+            path: "data/timestamps".into(),
+            ..Default::default()
+        };
+        let config = Config { file_name_time: Level::Ignore, ..Default::default() };
+        let issues = config.check_files(&timestamps.files().unwrap());
+        let duplicates = issues.iter()
+            .filter(|issue| issue.rule == Rule::DuplicateFile)
+            .map(|issue| issue.path.file_name().unwrap().to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["170621_202750.eif", "170621_203217.eif", "170621_202750.eif",
+                        "170621_203217.eif"],
+                   duplicates);
+        assert!(issues.iter()
+                    .any(|issue| issue.message.starts_with("file contents are identical")));
+    }
+
+    #[test]
+    fn file_errors_skip_adjustment() {
+        let files = vec![file(vec![73779.]), file(vec![73779.])];
+        let issues = Config::new().check(&files, &records(vec![1.]), &Eif).unwrap();
+        assert_eq!(2, issues.len());
+        assert!(issues.iter().all(|issue| issue.rule == Rule::DuplicateFile));
+    }
+
+    #[test]
+    fn duplicate_file_overlap() {
+        let files = vec![file(vec![1., 2., 3., 4.]), file(vec![4., 5., 6., 7.])];
+        assert!(Config::new().check_files(&files).is_empty());
+        let files = vec![file(vec![1., 2., 3., 4.]), file(vec![3., 4., 5., 6.])];
+        assert_eq!(2, Config::new().check_files(&files).len());
+        let config = Config { duplicate_file_overlap: 1., ..Default::default() };
+        assert!(config.check_files(&files).is_empty());
+    }
+
+    #[test]
+    fn record_count_mismatch() {
+        let files = vec![file(vec![73779.])];
+        assert!(Config::new().check(&files, &records(vec![1., 2.]), &Eif).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+//! Watch the image and timestamp directories during a field download.
+//!
+//! Cards and EIF files are copied while the next flight is in the air, so the crew wants to know,
+//! before leaving the site, whether the data is complete. A `Watcher` is polled periodically; when
+//! the contents of the directories change, it rebuilds the image list and reports whether the
+//! image and timestamp counts line up.
+//!
+//! We poll instead of using file system notifications because notifications aren't reliable on
+//! network drives.
+
+use {Config, Error, ImageList, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Watches the directories of a configuration for changes.
+#[derive(Debug, Default)]
+pub struct Watcher {
+    snapshot: Option<Snapshot>,
+    complete: Option<bool>,
+}
+
+/// The result of rebuilding the image list after a change.
+#[derive(Debug)]
+pub struct Update {
+    /// The image list, or the error that prevented it from being built.
+    ///
+    /// Validation warnings are in the image list's report, and validation errors are returned as
+    /// `Error::Validation`, so the timestamp files are only read once per update.
+    pub image_list: Result<ImageList>,
+    /// Set if the data became complete, or stopped being complete, with this update.
+    pub transition: Option<Transition>,
+}
+
+/// A change in whether the image and timestamp counts line up.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transition {
+    /// The counts line up, and an image list could be built.
+    LinedUp,
+    /// The counts lined up before, but no longer do.
+    Diverged,
+}
+
+/// The names, sizes, and modification times of the files in the watched directories.
+#[derive(Debug, PartialEq)]
+struct Snapshot {
+    entries: Vec<(PathBuf, u64, Option<SystemTime>)>,
+}
+
+impl Watcher {
+    /// Creates a new watcher.
+    ///
+    /// The first call to `poll` always produces an update.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::watch::Watcher;
+    /// let watcher = Watcher::new();
+    /// ```
+    pub fn new() -> Watcher {
+        Default::default()
+    }
+
+    /// Checks the watched directories, and rebuilds the image list if anything has changed.
+    ///
+    /// Returns None if nothing has changed since the last poll. Returns an error only if a
+    /// watched directory can't be read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::Config;
+    /// use riprocess::watch::{Transition, Watcher};
+    /// let config = Config::from_path("data/config.toml").unwrap();
+    /// let mut watcher = Watcher::new();
+    /// let update = watcher.poll(&config).unwrap().unwrap();
+    /// assert_eq!(Some(Transition::LinedUp), update.transition);
+    /// assert!(watcher.poll(&config).unwrap().is_none());
+    /// ```
+    pub fn poll(&mut self, config: &Config) -> Result<Option<Update>> {
+        let snapshot = Snapshot::take(&[&config.images.path, &config.timestamps.path])?;
+        if self.snapshot.as_ref() == Some(&snapshot) {
+            return Ok(None);
+        }
+        self.snapshot = Some(snapshot);
+        let image_list = config.image_list();
+        let complete = image_list.is_ok();
+        let transition = match (self.complete, complete) {
+            (Some(true), true) | (Some(false), false) => None,
+            (_, true) => Some(Transition::LinedUp),
+            (Some(true), false) => Some(Transition::Diverged),
+            (None, false) => None,
+        };
+        self.complete = Some(complete);
+        Ok(Some(Update {
+                    image_list,
+                    transition,
+                }))
+    }
+}
+
+impl Update {
+    /// Returns the image and timestamp counts, if the image list failed because they don't match.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use riprocess::Config;
+    /// use riprocess::watch::Watcher;
+    /// let mut config = Config::from_path("data/config.toml").unwrap();
+    /// config.images.end = None;
+    /// let update = Watcher::new().poll(&config).unwrap().unwrap();
+    /// assert!(update.mismatch().is_some());
+    /// ```
+    pub fn mismatch(&self) -> Option<(usize, usize)> {
+        match self.image_list {
+            Err(Error::TimestampCountMismatch { timestamps, images }) => Some((images, timestamps)),
+            _ => None,
+        }
+    }
+}
+
+impl Snapshot {
+    fn take(directories: &[&Path]) -> Result<Snapshot> {
+        let mut entries = Vec::new();
+        for directory in directories {
+            for dir_entry in fs::read_dir(directory)? {
+                let dir_entry = dir_entry?;
+                let metadata = dir_entry.metadata()?;
+                entries.push((dir_entry.path(), metadata.len(), metadata.modified().ok()));
+            }
+        }
+        entries.sort();
+        Ok(Snapshot { entries })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn download() {
+        let directory = std::env::temp_dir().join("riprocess-watch");
+        let _ = fs::remove_dir_all(&directory);
+        let (images, timestamps) = (directory.join("images"), directory.join("timestamps"));
+        fs::create_dir_all(&images).unwrap();
+        fs::create_dir_all(&timestamps).unwrap();
+        for file_name in &["170621_202939.eif", "170621_203040.eif"] {
+            fs::copy(Path::new("data/timestamps").join(file_name),
+                     timestamps.join(file_name))
+                .unwrap();
+        }
+        let mut config = Config::from_path("data/config.toml").unwrap();
+        config.images.path = images.clone();
+        config.images.end = None;
+        config.images.start = None;
+        config.timestamps.path = timestamps.clone();
+
+        let mut watcher = Watcher::new();
+        let copy = |image_number: usize| {
+            let file_name = format!("DSC{:05}.JPG", image_number);
+            fs::copy(Path::new("data/images").join(&file_name), images.join(&file_name)).unwrap();
+        };
+        copy(3522);
+        let update = watcher.poll(&config).unwrap().unwrap();
+        assert_eq!(Some((1, 4)), update.mismatch());
+        assert_eq!(None, update.transition);
+        assert!(watcher.poll(&config).unwrap().is_none());
+
+        for image_number in 3523..3526 {
+            copy(image_number);
+        }
+        let update = watcher.poll(&config).unwrap().unwrap();
+        assert_eq!(Some(Transition::LinedUp), update.transition);
+        assert_eq!(4, update.image_list.unwrap().count());
+
+        copy(3526);
+        let update = watcher.poll(&config).unwrap().unwrap();
+        assert_eq!(Some(Transition::Diverged), update.transition);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,159 @@
+//! Minimal XMP packet writing.
+//!
+//! We write flat properties only, as attributes of a single `rdf:Description` element, which is
+//! enough for the tags that photogrammetry packages read.
+
+/// The identifier at the start of an XMP APP1 segment.
+pub const HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// An XMP namespace.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Namespace {
+    /// The namespace prefix, e.g. `riprocess`.
+    pub prefix: &'static str,
+    /// The namespace URI.
+    pub uri: &'static str,
+}
+
+/// Our own namespace, for values that don't have a standard home.
+pub const RIPROCESS: Namespace = Namespace {
+    prefix: "riprocess",
+    uri: "https://github.com/gadomski/riprocess/ns/1.0/",
+};
+
+/// An XMP property.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Property {
+    /// The property's namespace.
+    pub namespace: Namespace,
+    /// The property name, without the namespace prefix.
+    pub name: &'static str,
+    /// The property value.
+    pub value: String,
+}
+
+impl Property {
+    /// Creates a new property.
+    pub fn new<S: ToString>(namespace: Namespace, name: &'static str, value: S) -> Property {
+        Property {
+            namespace,
+            name,
+            value: value.to_string(),
+        }
+    }
+}
+
+/// Returns an `rdf:Description` element that holds the provided properties.
+pub fn description(properties: &[Property]) -> String {
+    let mut namespaces: Vec<Namespace> = Vec::new();
+    for property in properties {
+        if !namespaces.contains(&property.namespace) {
+            namespaces.push(property.namespace);
+        }
+    }
+    let mut description = String::from("  <rdf:Description rdf:about=\"\"");
+    for namespace in namespaces {
+        description.push_str(&format!("\n    xmlns:{}=\"{}\"", namespace.prefix, namespace.uri));
+    }
+    for property in properties {
+        description.push_str(&format!("\n    {}:{}=\"{}\"",
+                                      property.namespace.prefix,
+                                      property.name,
+                                      escape(&property.value)));
+    }
+    description.push_str("/>\n");
+    description
+}
+
+/// Returns a complete XMP packet that holds the provided description.
+pub fn packet(description: &str) -> String {
+    format!("<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+             <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n \
+             <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+             {} </rdf:RDF>\n\
+             </x:xmpmeta>\n\
+             <?xpacket end=\"w\"?>",
+            description)
+}
+
+/// Adds a description to an existing XMP packet.
+///
+/// Any existing description that declares `namespace` is assumed to be one that we wrote
+/// earlier, and is replaced. If the existing packet doesn't have an `rdf:RDF` element, a new
+/// packet is returned.
+pub fn merge(existing: &str, description: &str, namespace: Namespace) -> String {
+    let declaration = format!("xmlns:{}=\"{}\"", namespace.prefix, namespace.uri);
+    let mut existing = existing.to_string();
+    while let Some((start, end)) = find_description(&existing, &declaration) {
+        existing.replace_range(start..end, "");
+    }
+    match existing.rfind("</rdf:RDF>") {
+        Some(i) => {
+            existing.insert_str(i, description);
+            existing
+        }
+        None => packet(description),
+    }
+}
+
+/// Returns the value of a property in an XMP packet, if it is present as an attribute.
+pub fn attribute(xmp: &str, namespace: Namespace, name: &str) -> Option<String> {
+    let needle = format!("{}:{}=\"", namespace.prefix, name);
+    xmp.find(&needle).and_then(|start| {
+        let start = start + needle.len();
+        xmp[start..].find('"').map(|end| unescape(&xmp[start..start + end]))
+    })
+}
+
+fn find_description(xmp: &str, declaration: &str) -> Option<(usize, usize)> {
+    let position = xmp.find(declaration)?;
+    let start = xmp[..position].rfind("<rdf:Description")?;
+    let start = xmp[..start].rfind('\n').map(|i| i + 1).unwrap_or(start);
+    let end = position + xmp[position..].find("/>")? + 2;
+    let end = if xmp[end..].starts_with('\n') { end + 1 } else { end };
+    Some((start, end))
+}
+
+fn escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn unescape(value: &str) -> String {
+    value.replace("&quot;", "\"").replace("&gt;", ">").replace("&lt;", "<").replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties() -> Vec<Property> {
+        vec![Property::new(RIPROCESS, "GpsSecondsOfWeek", "332979.899441"),
+             Property::new(RIPROCESS, "Note", "a \"quoted\" & <odd> value")]
+    }
+
+    #[test]
+    fn roundtrip() {
+        let xmp = packet(&description(&properties()));
+        assert_eq!(Some("332979.899441".to_string()),
+                   attribute(&xmp, RIPROCESS, "GpsSecondsOfWeek"));
+        assert_eq!(Some("a \"quoted\" & <odd> value".to_string()),
+                   attribute(&xmp, RIPROCESS, "Note"));
+    }
+
+    #[test]
+    fn merge_replaces_our_description() {
+        let other = Namespace {
+            prefix: "other",
+            uri: "http://example.com/",
+        };
+        let xmp = packet(&description(&[Property::new(other, "Value", 1)]));
+        let xmp = merge(&xmp, &description(&properties()), RIPROCESS);
+        let xmp = merge(&xmp,
+                        &description(&[Property::new(RIPROCESS, "GpsSecondsOfWeek", 2)]),
+                        RIPROCESS);
+        assert_eq!(Some("1".to_string()), attribute(&xmp, other, "Value"));
+        assert_eq!(Some("2".to_string()), attribute(&xmp, RIPROCESS, "GpsSecondsOfWeek"));
+        assert_eq!(None, attribute(&xmp, RIPROCESS, "Note"));
+        assert_eq!(1, xmp.matches("xmlns:riprocess").count());
+    }
+}